@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use unit_forge_lib::{Interpretor, UnitDefinitions};
+
+const DEFINITIONS: &str = include_str!("../../unit_definitions/basic.ud");
+
+// Any input must be answered with a result or an error, never a panic
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let unit_definitions: UnitDefinitions = toml::from_str(DEFINITIONS).unwrap();
+    let mut interpretor = Interpretor::new(&unit_definitions).unwrap();
+
+    let _ = interpretor.check_syntax(input);
+    let _ = interpretor.debug_parse(input);
+    let (_, diagnostics) = interpretor.evaluate_with_diagnostics(input);
+    for diagnostic in diagnostics {
+        diagnostic.render(input);
+    }
+    // Evaluate once more so the input can refer to its own result as `$`
+    let _ = interpretor.execute_command(input);
+});
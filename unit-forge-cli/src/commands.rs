@@ -0,0 +1,175 @@
+use unit_forge_lib::{FormatOptions, Interpretor, format_result};
+
+/// Runs a `:`-prefixed REPL command such as `:search mile`.
+/// Returns `None` when `line` is not a command, so it can be evaluated as an expression instead.
+pub fn run_command(interpretor: &mut Interpretor, line: &str) -> Option<Result<String, String>> {
+    let command = line.trim().strip_prefix(':')?;
+    let (name, argument) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+
+    Some(match name {
+        "search" => search(interpretor, argument.trim()),
+        "category" => category(interpretor, argument.trim()),
+        "save" => save(interpretor, argument.trim()),
+        "stack" => Ok(stack(interpretor)),
+        "assume" => assume(interpretor, argument.trim()),
+        "pin" => pin(interpretor, argument.trim()),
+        "unpin" => unpin(interpretor, argument.trim()),
+        "check" => Ok(check(interpretor)),
+        "factor" => factor(interpretor, argument.trim()),
+        _ => Err(format!("Unknown command \":{}\"", name)),
+    })
+}
+
+fn search(interpretor: &Interpretor, query: &str) -> Result<String, String> {
+    if query.is_empty() {
+        return Err("Usage: :search <text>".to_string());
+    }
+
+    let units = interpretor.search_units(query);
+    if units.is_empty() {
+        return Ok(format!("No units found matching \"{}\"", query));
+    }
+    Ok(units
+        .iter()
+        .map(|(key, name, symbol)| format!("{}: {} ({})", key, name, symbol))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Sets the unit of numbers typed without one, `:assume off` makes them dimensionless again
+fn assume(interpretor: &mut Interpretor, unit: &str) -> Result<String, String> {
+    match unit {
+        "" => Ok(match interpretor.assumed_unit() {
+            Some(unit) => format!("Assuming {}", unit),
+            None => "No unit assumed".to_string(),
+        }),
+        "off" => {
+            interpretor.set_assumed_unit(None)?;
+            Ok("No unit assumed".to_string())
+        }
+        unit => {
+            interpretor.set_assumed_unit(Some(unit))?;
+            Ok(format!("Assuming {}", unit))
+        }
+    }
+}
+
+/// Makes a variable read-only until it's unpinned
+fn pin(interpretor: &mut Interpretor, name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("Usage: :pin <name>".to_string());
+    }
+
+    interpretor.pin_var(name)?;
+    Ok(format!("Pinned {}", name))
+}
+
+fn unpin(interpretor: &mut Interpretor, name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("Usage: :unpin <name>".to_string());
+    }
+
+    interpretor.unpin_var(name)?;
+    Ok(format!("Unpinned {}", name))
+}
+
+/// Prints how many of one unit make another, e.g. `1 km = 1000 m` for `:factor km m`
+fn factor(interpretor: &Interpretor, argument: &str) -> Result<String, String> {
+    let mut units = argument.split_whitespace();
+    let (Some(from), Some(to), None) = (units.next(), units.next(), units.next()) else {
+        return Err("Usage: :factor <from> <to>".to_string());
+    };
+
+    let factor = interpretor.conversion_factor(from, to)?;
+    Ok(format!("1 {} = {} {}", from, factor, to))
+}
+
+/// Lists the warnings about units of the loaded definitions that can't be converted
+fn check(interpretor: &Interpretor) -> String {
+    let warnings = interpretor.lint_units();
+    if warnings.is_empty() {
+        "No issues found".to_string()
+    } else {
+        warnings.join("\n")
+    }
+}
+
+/// Lists the recent results as `$n: result`, the most recent first
+fn stack(interpretor: &Interpretor) -> String {
+    let options = FormatOptions::default();
+    let results = interpretor
+        .stack()
+        .enumerate()
+        .map(|(i, (value, unit))| format!("${}: {}", i + 1, format_result(value, unit, &options)))
+        .collect::<Vec<_>>();
+    if results.is_empty() {
+        "No results yet".to_string()
+    } else {
+        results.join("\n")
+    }
+}
+
+/// Writes the unit definitions to `path`, as JSON if it ends with `.json` and in the `.ud` format otherwise
+fn save(interpretor: &Interpretor, path: &str) -> Result<String, String> {
+    if path.is_empty() {
+        return Err("Usage: :save <path>".to_string());
+    }
+
+    let definitions = interpretor.unit_definitions();
+    let content = if path.ends_with(".json") {
+        definitions.to_json_string().map_err(|e| e.to_string())?
+    } else {
+        definitions.to_toml_string().map_err(|e| e.to_string())?
+    };
+    std::fs::write(path, content)
+        .map_err(|e| format!("Failed to write unit definitions to {}: {}", path, e))?;
+    Ok(format!("Saved unit definitions to {}", path))
+}
+
+/// Prints the conversion factors between the units of a category, one row per unit converted from
+fn category(interpretor: &Interpretor, name: &str) -> Result<String, String> {
+    if name.is_empty() {
+        return Err("Usage: :category <name>".to_string());
+    }
+
+    let (units, matrix) = interpretor.conversion_matrix(name)?;
+    let mut rows = vec![
+        std::iter::once(String::new())
+            .chain(units.iter().cloned())
+            .collect::<Vec<_>>(),
+    ];
+    for (unit, factors) in units.iter().zip(matrix) {
+        rows.push(
+            std::iter::once(unit.clone())
+                .chain(factors.iter().map(f64::to_string))
+                .collect(),
+        );
+    }
+
+    let widths = (0..=units.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    Ok(rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| match column {
+                    0 => format!("{:<width$}", cell),
+                    _ => format!("  {:>width$}", cell),
+                })
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
@@ -0,0 +1,48 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::Deserialize;
+use unit_forge_lib::{Notation, RoundingMode};
+
+pub const CONFIG_FILE_NAME: &str = "unitforge.toml";
+
+/// Defaults for the command-line options, read from `unitforge.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub precision: Option<usize>,
+    pub notation: Option<Notation>,
+    pub rounding: Option<RoundingMode>,
+    /// Directory of the `.ud` files
+    pub definitions: Option<PathBuf>,
+    pub dimensionless: Option<String>,
+    /// Unit results of a category are shown in by category, e.g. `temperature = "degC"`
+    pub preferred: HashMap<String, String>,
+    /// Units of numbers written right after a symbol by symbol, e.g. `"$" = "USD"` for `$5`
+    pub prefix_symbols: HashMap<String, String>,
+}
+
+impl Config {
+    /// Loads the first config found in the current directory or the home directory, defaults if none exists
+    pub fn load() -> Result<Self> {
+        let home_dir = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        let candidates = std::iter::once(PathBuf::from(CONFIG_FILE_NAME))
+            .chain(home_dir.map(|home_dir| Path::new(&home_dir).join(CONFIG_FILE_NAME)));
+
+        for path in candidates {
+            if path.is_file() {
+                return Self::load_from(&path);
+            }
+        }
+        Ok(Self::default())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&content).wrap_err_with(|| format!("Invalid config file {}", path.display()))
+    }
+}
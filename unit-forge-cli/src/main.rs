@@ -1,43 +1,393 @@
-use std::io::{Write, stdout};
+mod commands;
+mod config;
 
-use color_eyre::eyre::Result;
-use unit_forge_lib::{Interpretor, UnitDefinitions};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, IsTerminal, Write, sink, stdin, stdout},
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgAction, Parser};
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use config::Config;
+use unit_forge_lib::{
+    Diagnostic, ExponentStyle, FormatOptions, Interpretor, Notation, RoundingMode, UnitDefinitions,
+    format_echo, format_result,
+};
+
+const VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("UNIT_FORGE_GIT_HASH"),
+    ")"
+);
+
+/// Exit code when evaluating any line failed
+const EXIT_EVAL_ERROR: i32 = 1;
+/// Exit code when the config, the unit definitions or the input and output files can't be loaded
+const EXIT_LOAD_ERROR: i32 = 2;
+/// Exit code for invalid command-line arguments
+const EXIT_USAGE_ERROR: i32 = 3;
+
+/// A calculator for arithmetic with physical units
+#[derive(Debug, Parser)]
+#[command(version = VERSION, about)]
+struct Args {
+    /// Evaluate every line from stdin without a prompt and report a summary (default when stdin is not a terminal)
+    #[arg(long)]
+    batch: bool,
+
+    /// Number notation of the results: decimal or engineering [default: decimal]
+    #[arg(long)]
+    notation: Option<Notation>,
+
+    /// Number of decimal places of the results
+    #[arg(long)]
+    precision: Option<usize>,
+
+    /// How results are rounded to the precision: half-up, half-even, toward-zero, ceil or floor [default: half-even]
+    #[arg(long)]
+    rounding: Option<RoundingMode>,
+
+    /// Separate groups of three digits of the results, with `,` unless another separator is given
+    #[arg(long, num_args = 0..=1, default_missing_value = ",", value_name = "SEPARATOR")]
+    group_digits: Option<char>,
+
+    /// Show results close to a fraction as one, e.g. `1/2 cup`, with denominators up to 16 unless another limit is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "16", value_name = "MAX_DENOMINATOR")]
+    fractions: Option<u64>,
+
+    /// Show unit symbols (e.g. `m³`) instead of unit keys
+    #[arg(long)]
+    symbols: bool,
+
+    /// How exponents of units are written: key (`m3`), caret (`m^3`) or unicode (`m³`) [default: key]
+    #[arg(long)]
+    exponent_style: Option<ExponentStyle>,
+
+    /// Show units derived by a division as the quotient of their operands' symbols, e.g. `m/s`
+    #[arg(long)]
+    per_notation: bool,
+
+    /// Shown in place of the unit of dimensionless results, e.g. `(dimensionless)` [default: nothing]
+    #[arg(long)]
+    dimensionless: Option<String>,
+
+    /// Accept SI prefixes on defined units, e.g. `km` when only `m` is defined
+    #[arg(long)]
+    si_prefixes: bool,
+
+    /// Make numbers without a unit errors, except in powers like `2^10`, lists with a unit and before a unit
+    #[arg(long)]
+    strict_units: bool,
+
+    /// Give results without `>>` in the unit of their leftmost number, e.g. `8 cm` for `5 cm + 3 cm`
+    #[arg(long)]
+    entry_units: bool,
+
+    /// Seed the last result `$` with this value before evaluating, e.g. the output of a previous run like `50 mps`
+    #[arg(long, value_name = "VALUE")]
+    import_last: Option<String>,
+
+    /// Directory of the unit definition (`.ud`) files [default: unit_definitions]
+    #[arg(long)]
+    definitions: Option<PathBuf>,
+
+    /// Single unit definition file to load instead of a directory
+    #[arg(long, conflicts_with = "definitions")]
+    definitions_file: Option<PathBuf>,
+
+    /// Evaluate the lines of this file in batch mode instead of reading stdin
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Write the results to this file instead of stdout, errors are still printed to stderr
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Whether batch mode prints the values of assignments like `x = 2 m`
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    echo_assignments: bool,
+
+    /// Print each result after its input as `input = value unit`, e.g. for audit logs
+    #[arg(long)]
+    echo_input: bool,
+
+    /// Print each category of the loaded definitions with its number of units and exit
+    #[arg(long)]
+    list_categories: bool,
+
+    /// Print the factor and base unit every unit is normalized with and exit
+    #[arg(long)]
+    dump_base_units: bool,
+}
 
 fn main() -> Result<()> {
+    let args = Args::try_parse().unwrap_or_else(|e| {
+        // `--help` and `--version` are reported as errors too, but printed to stdout
+        let code = if e.use_stderr() { EXIT_USAGE_ERROR } else { 0 };
+        let _ = e.print();
+        std::process::exit(code);
+    });
     color_eyre::install()?;
-    let unit_definitions = parse_unit_definitions()?;
-    let mut interpretor = Interpretor::new(&unit_definitions)?;
+    // Command-line options take precedence over the config file
+    let config = exit_on_load_error(Config::load());
+    let unit_definitions = exit_on_load_error(match args.definitions_file {
+        Some(file) => parse_unit_definitions_file(&file),
+        None => {
+            let definitions_dir = args
+                .definitions
+                .or(config.definitions)
+                .unwrap_or_else(|| PathBuf::from("unit_definitions"));
+            parse_unit_definitions(&definitions_dir)
+        }
+    });
+    let mut interpretor = exit_on_load_error(
+        Interpretor::new(&unit_definitions).wrap_err("Invalid unit definitions"),
+    );
+    if args.list_categories {
+        for (category, units) in &interpretor.unit_definitions().categories {
+            println!("{}: {} units", category, units.len());
+        }
+        return Ok(());
+    }
+    if args.dump_base_units {
+        for (unit, (factor, base_unit)) in interpretor.base_units() {
+            println!("{} -> ({}, {})", unit, factor, base_unit);
+        }
+        return Ok(());
+    }
+    interpretor.set_prefix_fallback(args.si_prefixes);
+    interpretor.set_strict_units(args.strict_units);
+    interpretor.set_entry_unit_results(args.entry_units);
+    exit_on_load_error(
+        interpretor
+            .set_preferred_units(config.preferred)
+            .map_err(|e| eyre!(e))
+            .wrap_err("Invalid preferred units in the config"),
+    );
+    exit_on_load_error(
+        interpretor
+            .set_prefix_symbols(config.prefix_symbols)
+            .map_err(|e| eyre!(e))
+            .wrap_err("Invalid prefix symbols in the config"),
+    );
+    if let Some(last) = &args.import_last
+        && let Err(errors) = interpretor.evaluate(last)
+    {
+        for error in errors {
+            eprintln!(
+                "Error: Invalid --import-last value {:?}: {}",
+                last, error.message
+            );
+        }
+        std::process::exit(EXIT_USAGE_ERROR);
+    }
+    let mut format_options = FormatOptions {
+        notation: args.notation.or(config.notation).unwrap_or_default(),
+        precision: args.precision.or(config.precision),
+        rounding: args.rounding.or(config.rounding).unwrap_or_default(),
+        unit_labels: HashMap::new(),
+        exponent_style: args.exponent_style.unwrap_or_default(),
+        dimensionless_label: args
+            .dimensionless
+            .or(config.dimensionless)
+            .unwrap_or_default(),
+        sig_figs: interpretor.unit_sig_figs(),
+        digit_separator: args.group_digits,
+        max_denominator: args.fractions,
+    };
+    if args.symbols {
+        format_options.unit_labels = interpretor.unit_symbols();
+    }
+    if args.per_notation {
+        format_options
+            .unit_labels
+            .extend(interpretor.quotient_symbols());
+    }
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(exit_on_load_error(File::create(path).wrap_err_with(|| {
+                format!("Failed to create output file {}", path.display())
+            })))
+        }
+        None => Box::new(stdout()),
+    };
+
+    if let Some(path) = &args.file {
+        let file = exit_on_load_error(
+            File::open(path)
+                .wrap_err_with(|| format!("Failed to open input file {}", path.display())),
+        );
+        return run_batch(
+            &mut interpretor,
+            BufReader::new(file),
+            &mut output,
+            &format_options,
+            args.echo_assignments,
+            args.echo_input,
+        );
+    }
+    if args.batch || !stdin().is_terminal() {
+        return run_batch(
+            &mut interpretor,
+            stdin().lock(),
+            &mut output,
+            &format_options,
+            args.echo_assignments,
+            args.echo_input,
+        );
+    }
 
     // read expressions from stdin
     loop {
         print!("> ");
         stdout().flush()?;
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        match interpretor.execute_command(&input) {
-            Ok(val) => {
-                println!("{} {}", val.0, val.1);
+        if stdin().read_line(&mut input)? == 0 {
+            return Ok(());
+        }
+        evaluate_line(
+            &mut interpretor,
+            &input,
+            &mut output,
+            &format_options,
+            args.echo_input,
+        )?;
+    }
+}
+
+/// Runs a REPL command or evaluates an expression, writing the result to `output`
+/// and printing the errors and warnings to stderr. Returns whether it succeeded.
+/// With `echo_input`, the result follows the line as `line = value unit`.
+fn evaluate_line(
+    interpretor: &mut Interpretor,
+    line: &str,
+    output: &mut dyn Write,
+    format_options: &FormatOptions,
+    echo_input: bool,
+) -> Result<bool> {
+    if let Some(command_output) = commands::run_command(interpretor, line) {
+        return match command_output {
+            Ok(command_output) => {
+                writeln!(output, "{}", command_output)?;
+                Ok(true)
             }
             Err(e) => {
-                eprintln!("Error: {:?}", e);
+                eprintln!("Error: {}", e);
+                Ok(false)
+            }
+        };
+    }
+
+    if interpretor.is_function_definition(line) {
+        return match interpretor.define_function(line) {
+            Ok(name) => {
+                writeln!(output, "Defined {}", name)?;
+                Ok(true)
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}", Diagnostic::from(error).render(line));
+                }
+                Ok(false)
             }
+        };
+    }
+
+    let (result, diagnostics) = interpretor.evaluate_with_diagnostics(line);
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(line));
+    }
+    match result {
+        Some(result) => {
+            let (value, unit) = if result.requested_unit {
+                (result.value, result.unit_key)
+            } else {
+                interpretor.display_value(result.value, &result.unit_key)
+            };
+            let formatted = if echo_input {
+                format_echo(line, &value, &unit, format_options)
+            } else {
+                format_result(&value, &unit, format_options)
+            };
+            writeln!(output, "{}", formatted)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Evaluates every line of `input`, exiting with a nonzero code if any line failed.
+/// Blank lines and comments starting with `#` are skipped and not counted.
+/// Without `echo_assignments`, assignments are evaluated without printing their values.
+fn run_batch(
+    interpretor: &mut Interpretor,
+    input: impl BufRead,
+    output: &mut dyn Write,
+    format_options: &FormatOptions,
+    echo_assignments: bool,
+    echo_input: bool,
+) -> Result<()> {
+    let mut total = 0;
+    let mut succeeded = 0;
+    for line in input.lines() {
+        let line = line?;
+        if is_comment_or_blank(&line) {
+            continue;
+        }
+        total += 1;
+        let output = if echo_assignments || !interpretor.is_assignment(&line) {
+            &mut *output
+        } else {
+            &mut sink()
+        };
+        if evaluate_line(interpretor, &line, output, format_options, echo_input)? {
+            succeeded += 1;
         }
     }
+    output.flush()?;
+
+    eprintln!("{}/{} succeeded", succeeded, total);
+    if succeeded != total {
+        std::process::exit(EXIT_EVAL_ERROR);
+    }
+    Ok(())
+}
+
+fn is_comment_or_blank(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
 }
 
-fn parse_unit_definitions() -> Result<UnitDefinitions> {
-    let entries = std::fs::read_dir("unit_definitions")?;
+/// Prints the error with its causes on one line and exits with `EXIT_LOAD_ERROR`
+fn exit_on_load_error<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(EXIT_LOAD_ERROR);
+    })
+}
+
+fn parse_unit_definitions(dir: &Path) -> Result<UnitDefinitions> {
+    let entries = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("Failed to read definitions directory {}", dir.display()))?;
 
     let mut all_defs = UnitDefinitions::default();
 
     for entry in entries {
         let path = entry?.path();
         if path.extension().and_then(|s| s.to_str()) == Some("ud") {
-            let content = std::fs::read_to_string(&path)?;
-            let defs: UnitDefinitions = toml::from_str(&content)?;
+            let defs = parse_unit_definitions_file(&path)?;
+            all_defs.dimensions.extend(defs.dimensions);
             all_defs.categories.extend(defs.categories);
         }
     }
 
     Ok(all_defs)
 }
+
+fn parse_unit_definitions_file(path: &Path) -> Result<UnitDefinitions> {
+    Ok(UnitDefinitions::from_file(path)?)
+}
@@ -0,0 +1,637 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn cli() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_unit-forge-cli"));
+    command.current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."));
+    command
+}
+
+/// Creates an empty directory under the system temp directory, e.g. to hold a config file
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("unit-forge-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_with_stdin(command: &mut Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn should_print_version() {
+    for flag in ["--version", "-V"] {
+        let output = cli().arg(flag).output().unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with(&format!("unit-forge-cli {}", env!("CARGO_PKG_VERSION"))));
+    }
+}
+
+#[test]
+fn should_report_batch_summary() {
+    let output = run_with_stdin(
+        cli().arg("--batch"),
+        "x = 2 m\nx + 50 cm\n1 m + 1 sec\nfoo\nx * 2\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "2 m\n2.5 m\n4 m\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("3/5 succeeded\n"));
+}
+
+#[test]
+fn should_skip_comments_and_blank_lines_in_batch() {
+    let output = run_with_stdin(
+        cli().arg("--batch"),
+        "# Lengths\nx = 2 m\n\n   \n  # not evaluated: 1 m + 1 sec\nx * 2\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2 m\n4 m\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("2/2 succeeded\n"));
+}
+
+#[test]
+fn should_echo_assignments_in_batch() {
+    let script = "x = 2 m\ny = x * 3\nz = (y >> cm)\n";
+    let output = run_with_stdin(&mut cli(), script);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2 m\n6 m\n600 cm\n"
+    );
+
+    let output = run_with_stdin(
+        cli().args(["--echo-assignments", "false"]),
+        &format!("{}z + 1 m\n", script),
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7 m\n");
+}
+
+#[test]
+fn should_echo_input() {
+    let output = run_with_stdin(
+        cli().args(["--echo-input", "--symbols", "--per-notation"]),
+        "360 km / 2 hour\n  1 m >> cm\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "360 km / 2 hour = 50 m/s\n1 m >> cm = 100 cm\n"
+    );
+}
+
+#[test]
+fn should_define_functions() {
+    let output = run_with_stdin(
+        &mut cli(),
+        "fn speed(d, t) = d / t\nspeed(360 km, 2 hour) >> kmph\nfn abs_diff(a, b) = a\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Defined speed\n180 kmph\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Cannot redefine built-in function \"abs_diff\""));
+}
+
+#[test]
+fn should_exit_with_documented_codes() {
+    let output = run_with_stdin(&mut cli(), "1 m + 2 cm\n");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = run_with_stdin(&mut cli(), "1 m + 1 sec\n");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_with_stdin(
+        cli().args(["--definitions", "missing_definitions"]),
+        "1 m\n",
+    );
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Error: Failed to read definitions directory missing_definitions: ")
+    );
+    assert_eq!(stderr.lines().count(), 1);
+
+    let output = cli().arg("--no-such-flag").output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn should_exit_successfully_when_batch_succeeds() {
+    let output = run_with_stdin(&mut cli(), "1 m >> cm\n$ + 1 cm\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "100 cm\n1.01 m\n");
+}
+
+#[test]
+fn should_render_errors_and_warnings() {
+    let output = run_with_stdin(&mut cli(), "1e20 m\n1 x\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "100000000000000000000 m\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Warning: 1e20 is too large to be exact"));
+    assert!(stderr.contains("Error: Unknown unit: \"x\"\n  | 1 x\n  | ^^^\n"));
+}
+
+#[test]
+fn should_print_mixed_units() {
+    let output = run_with_stdin(&mut cli(), "90 min >> [hour, min]\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "1 hour 30 min\n");
+}
+
+#[test]
+fn should_chain_runs_with_import_last() {
+    let output = run_with_stdin(&mut cli(), "360 km / 2 hour\n");
+    let last = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(last, "50 mps\n");
+
+    let output = run_with_stdin(
+        cli().args(["--import-last", last.trim()]),
+        "$ >> kmph\n$ * 2\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "180 kmph\n100 mps\n"
+    );
+
+    let output = run_with_stdin(cli().args(["--import-last", "5 x"]), "$\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: Invalid --import-last value \"5 x\": Unknown unit: \"x\"\n"
+    );
+}
+
+#[test]
+fn should_print_each_unit_on_its_own_line() {
+    let output = run_with_stdin(&mut cli(), "50 mps >> {kmph, mps}\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "180 kmph\n50 mps\n");
+}
+
+#[test]
+fn should_search_units() {
+    let output = run_with_stdin(&mut cli(), ":search kilometer\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        "km: kilometer (km)\nkmph: kilometer per hour (km/h)\n"
+    );
+}
+
+#[test]
+fn should_group_digits() {
+    let output = run_with_stdin(cli().arg("--group-digits"), "30 km + 6.602 m\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "30,006.602 m\n");
+
+    let output = run_with_stdin(cli().args(["--group-digits", "'"]), "1234567 m\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1'234'567 m\n");
+}
+
+#[test]
+fn should_show_fractions() {
+    let output = run_with_stdin(cli().arg("--fractions"), "30 min >> hour\n0.37 m\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1/2 hour\n0.37 m\n"
+    );
+
+    let output = run_with_stdin(cli().args(["--fractions", "100"]), "0.37 m\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "37/100 m\n");
+}
+
+#[test]
+fn should_give_results_in_entry_units() {
+    let input = "5 cm + 3 cm\n2 km + 500 m >> m\n";
+    let output = run_with_stdin(&mut cli(), input);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0.08 m\n2500 m\n"
+    );
+
+    let output = run_with_stdin(cli().arg("--entry-units"), input);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "8 cm\n2500 m\n");
+}
+
+#[test]
+fn should_require_units_in_strict_mode() {
+    let output = run_with_stdin(cli().arg("--strict-units"), "5 m + (2^10) cm\nx = 5\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "15.24 m\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Number 5 has no unit, which strict mode requires"));
+}
+
+#[test]
+fn should_dump_base_units() {
+    let output = cli().arg("--dump-base-units").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("L -> (0.001, m3)\ncm -> (0.01, m)\n"));
+    assert!(stdout.contains("\nkm -> (1000, m)\n"));
+    assert!(stdout.contains("\nhour -> (3600, sec)\n"));
+}
+
+#[test]
+fn should_list_categories() {
+    let output = cli()
+        .args(["--definitions", "unit_definitions", "--list-categories"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "length: 3 units\narea: 2 units\nvolume: 3 units\ntime: 4 units\nspeed: 2 units\n"
+    );
+}
+
+#[test]
+fn should_print_stack() {
+    let output = run_with_stdin(&mut cli(), ":stack\n1 m\n2 km >> km\n$2 + $1\n:stack\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "No results yet\n1 m\n2 km\n2001 m\n$1: 2001 m\n$2: 2 km\n$3: 1 m\n"
+    );
+}
+
+#[test]
+fn should_pin_variables() {
+    let output = run_with_stdin(&mut cli(), "x = 2 m\n:pin x\nx = 3 m\n:unpin x\nx = 3 m\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "2 m\nPinned x\nUnpinned x\n3 m\n"
+    );
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Cannot assign to pinned variable \"x\"")
+    );
+}
+
+#[test]
+fn should_assume_unit_of_bare_numbers() {
+    let output = run_with_stdin(&mut cli(), "5 + 3\n:assume m\n5 + 3\n:assume off\n5 + 3\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "8\nAssuming m\n8 m\nNo unit assumed\n8\n"
+    );
+}
+
+#[test]
+fn should_check_unit_definitions() {
+    let output = run_with_stdin(&mut cli(), ":check\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "No issues found\n"
+    );
+
+    let dir = temp_dir("check");
+    let file = dir.join("orphan.ud");
+    std::fs::write(
+        &file,
+        "[length]\nm = { name = \"meter\", symbol = \"m\" }\nkm = { name = \"kilometer\", symbol = \"km\", factor = 1000 }\n\n[luminosity]\ncd = { name = \"candela\", symbol = \"cd\" }\n",
+    )
+    .unwrap();
+    let output = run_with_stdin(cli().arg("--definitions-file").arg(&file), ":check\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Unit \"cd\" is the only unit of its category and no derived unit refers to it, it can't be converted\n"
+    );
+}
+
+#[test]
+fn should_print_conversion_factor() {
+    let output = run_with_stdin(&mut cli(), ":factor km m\n:factor km sec\n:factor kilo m\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1 km = 1000 m\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("they measure different things"));
+    assert!(stderr.contains("Unknown unit \"kilo\", did you mean km, kmph?"));
+}
+
+#[test]
+fn should_print_category_matrix() {
+    let output = run_with_stdin(&mut cli(), ":category time\n:category mass\n");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows = stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(rows[0], ["sec", "min", "hour", "day"]);
+    assert_eq!(rows[3][..3], ["hour", "3600", "60"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unknown category \"mass\""));
+}
+
+#[test]
+fn should_label_dimensionless_results() {
+    let output = run_with_stdin(&mut cli(), "1 + 2 * 3\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7\n");
+
+    let output = run_with_stdin(
+        cli().args(["--dimensionless", "(dimensionless)"]),
+        "1 + 2 * 3\n2 m\n",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "7 (dimensionless)\n2 m\n"
+    );
+}
+
+#[test]
+fn should_print_engineering_notation() {
+    let output = run_with_stdin(
+        cli().args(["--notation", "engineering"]),
+        "12.3 km\n4.7 cm * 0.0001\n",
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "12.3e3 m\n4.7e-6 m\n");
+}
+
+#[test]
+fn should_display_preferred_unit_of_category() {
+    let dir = temp_dir("preferred");
+    std::fs::write(
+        dir.join("temperature.ud"),
+        concat!(
+            "[temperature]\nK = { name = \"kelvin\", symbol = \"K\" }\n",
+            "degC = { name = \"degree Celsius\", symbol = \"°C\", offset = 273.15 }\n",
+        ),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("unitforge.toml"),
+        "definitions = \".\"\nprecision = 1\n[preferred]\ntemperature = \"degC\"\n",
+    )
+    .unwrap();
+
+    let output = run_with_stdin(
+        cli().current_dir(&dir).env("HOME", &dir),
+        "300 K\nx = 300 K\nx >> K\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "26.9 degC\n26.9 degC\n300.0 K\n"
+    );
+    let output = run_with_stdin(
+        cli()
+            .arg("--entry-units")
+            .current_dir(&dir)
+            .env("HOME", &dir),
+        "300 K + 1 K\n",
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "301.0 K\n");
+
+    std::fs::write(
+        dir.join("unitforge.toml"),
+        "definitions = \".\"\n[preferred]\ntemperature = \"m\"\n",
+    )
+    .unwrap();
+    let output = cli().current_dir(&dir).env("HOME", &dir).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn should_read_prefix_symbols() {
+    let dir = temp_dir("prefix-symbols");
+    std::fs::write(
+        dir.join("currency.ud"),
+        "[currency]\nUSD = { name = \"US dollar\", symbol = \"$\" }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("unitforge.toml"),
+        "definitions = \".\"\n[prefix_symbols]\n\"$\" = \"USD\"\n",
+    )
+    .unwrap();
+
+    let output = run_with_stdin(
+        cli().current_dir(&dir).env("HOME", &dir),
+        "$5 + $3\n$ * 2\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "8 USD\n16 USD\n");
+}
+
+#[test]
+fn should_apply_rounding_mode() {
+    let output = run_with_stdin(cli().args(["--precision", "0"]), "2.5 m\n3.5 m\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2 m\n4 m\n");
+
+    let output = run_with_stdin(
+        cli().args(["--precision", "0", "--rounding", "half-up"]),
+        "2.5 m\n3.5 m\n",
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3 m\n4 m\n");
+
+    let output = cli().args(["--rounding", "nearest"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn should_apply_config_file() {
+    let dir = temp_dir("config");
+    let definitions = concat!(env!("CARGO_MANIFEST_DIR"), "/../unit_definitions");
+    std::fs::write(
+        dir.join("unitforge.toml"),
+        format!("precision = 2\ndefinitions = {:?}\n", definitions),
+    )
+    .unwrap();
+
+    let output = run_with_stdin(cli().current_dir(&dir).env("HOME", &dir), "1 m / 3\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0.33 m\n");
+
+    // Command-line options override the config
+    let output = run_with_stdin(
+        cli()
+            .current_dir(&dir)
+            .env("HOME", &dir)
+            .args(["--precision", "1"]),
+        "1 m / 3\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0.3 m\n");
+}
+
+#[test]
+fn should_save_definitions() {
+    let dir = temp_dir("save");
+    let file = dir.join("saved.ud");
+    let output = run_with_stdin(&mut cli(), &format!(":save {}\n:save\n", file.display()));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("Saved unit definitions to {}\n", file.display())
+    );
+
+    let output = run_with_stdin(
+        cli().arg("--definitions-file").arg(&file),
+        "360 km / 2 hour >> kmph\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "180 kmph\n");
+}
+
+#[test]
+fn should_display_results_as_configured_unit() {
+    let dir = temp_dir("display-as");
+    let file = dir.join("speed.ud");
+    std::fs::write(
+        &file,
+        concat!(
+            "[length]\nm = { name = \"meter\", symbol = \"m\" }\n",
+            "[time]\nsec = { name = \"second\", symbol = \"s\" }\n",
+            "[speed]\nmps = { name = \"meter per second\", symbol = \"m/s\", derived = \"m / sec\", display_as = \"kmph\" }\n",
+            "kmph = { name = \"kilometer per hour\", symbol = \"km/h\", factor = 0.25 }\n",
+        ),
+    )
+    .unwrap();
+
+    let output = run_with_stdin(
+        cli().arg("--definitions-file").arg(&file),
+        "100 m / 10 sec\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "40 kmph\n");
+}
+
+#[test]
+fn should_round_to_significant_figures_of_unit() {
+    let dir = temp_dir("sig-figs");
+    let file = dir.join("length.ud");
+    std::fs::write(
+        &file,
+        concat!(
+            "[length]\nm = { name = \"meter\", symbol = \"m\" }\n",
+            "ft = { name = \"foot\", symbol = \"ft\", factor = 0.3048, sig_figs = 3 }\n",
+        ),
+    )
+    .unwrap();
+
+    let output = run_with_stdin(
+        cli()
+            .arg("--definitions-file")
+            .arg(&file)
+            .args(["--precision", "1"]),
+        "10 m >> ft\n10 ft >> m\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "32.8 ft\n3.0 m\n"
+    );
+}
+
+#[test]
+fn should_name_malformed_definitions_file() {
+    let dir = temp_dir("malformed");
+    let file = dir.join("broken.ud");
+    std::fs::write(&file, "[length]\nm = { name = \"meter\", symbol = \"m\"\n").unwrap();
+
+    let output = cli().arg("--definitions-file").arg(&file).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.starts_with(&format!(
+            "Error: Invalid definitions file {} at line 2, column ",
+            file.display()
+        )),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn should_load_single_definitions_file() {
+    let dir = temp_dir("definitions-file");
+    let file = dir.join("all.ud");
+    std::fs::write(
+        &file,
+        "[length]\nm = { name = \"meter\", symbol = \"m\" }\nft = { name = \"foot\", symbol = \"ft\", factor = 0.3048 }\n",
+    )
+    .unwrap();
+
+    let output = run_with_stdin(
+        cli().arg("--definitions-file").arg(&file),
+        "10 ft >> m\n1 km\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3.048 m\n");
+
+    let output = cli()
+        .arg("--definitions-file")
+        .arg(&file)
+        .args(["--definitions", "unit_definitions"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot be used with"));
+}
+
+#[test]
+fn should_write_results_to_output_file() {
+    let dir = temp_dir("output");
+    let input = dir.join("input.calc");
+    let output_file = dir.join("results.txt");
+    std::fs::write(&input, "1 km >> m\n1 x\n2 m * 3 m\n").unwrap();
+    std::fs::write(&output_file, "stale content\n").unwrap();
+
+    let output = cli()
+        .arg("--output")
+        .arg(&output_file)
+        .arg("--file")
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Unknown unit")
+    );
+    assert_eq!(
+        std::fs::read_to_string(&output_file).unwrap(),
+        "1000 m\n6 m2\n"
+    );
+}
@@ -0,0 +1,68 @@
+//! Compares evaluating an expression made of heavily repeated sub-expressions with and without
+//! memoization. Run with `cargo bench -p unit-forge-lib --bench memoization`.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use unit_forge_lib::{Interpretor, UnitDefinitions};
+
+const ITERATIONS: u32 = 20;
+
+/// Number of elements of the list the expressions compute with, so evaluating a sub-expression
+/// costs more than looking it up
+const LIST_LEN: usize = 10_000;
+
+/// `(xs * 2 + xs)` doubled `depth` times as `((a) + (a)) * 0.5` with `a` being the previous step,
+/// so the expression has `2^depth` copies of the innermost one but only `depth` distinct sub-expressions
+fn repeated_expression(depth: usize) -> String {
+    let mut expr = "(xs * 2 + xs)".to_string();
+    for _ in 0..depth {
+        expr = format!("(({}) + ({})) * 0.5", expr, expr);
+    }
+    expr
+}
+
+fn time(interpretor: &mut Interpretor, expr: &str) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        interpretor.execute_command(expr).unwrap();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../unit_definitions/basic.ud");
+    let unit_definitions = UnitDefinitions::from_file(&path).unwrap();
+    let mut plain = Interpretor::new(&unit_definitions).unwrap();
+    let mut memoized = Interpretor::new(&unit_definitions).unwrap();
+    memoized.set_memoization(true);
+    let list = (1..=LIST_LEN)
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    for interpretor in [&mut plain, &mut memoized] {
+        interpretor
+            .execute_command(&format!("xs = [{}] km", list))
+            .unwrap();
+    }
+
+    for depth in [4, 8, 10] {
+        let expr = repeated_expression(depth);
+        assert_eq!(
+            plain.execute_command(&expr),
+            memoized.execute_command(&expr)
+        );
+        let plain_time = time(&mut plain, &expr);
+        let memoized_time = time(&mut memoized, &expr);
+        println!(
+            "depth {:>2} ({:>7} bytes): {:>10.3?} plain, {:>10.3?} memoized ({:.1}x)",
+            depth,
+            expr.len(),
+            plain_time,
+            memoized_time,
+            plain_time.as_secs_f64() / memoized_time.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+//! Compares evaluating numbers without units, which need no unit lookups, with the same numbers
+//! given a unit. Run with `cargo bench -p unit-forge-lib --bench numeric`.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use unit_forge_lib::{Interpretor, UnitDefinitions};
+
+const ITERATIONS: u32 = 20;
+
+/// Number of numbers evaluated, as lines of their own and as one list
+const NUMBERS: usize = 50_000;
+
+/// Lines of a single number each, followed by `unit`
+fn number_lines(unit: &str) -> Vec<String> {
+    (1..=NUMBERS).map(|i| format!("{}{}", i, unit)).collect()
+}
+
+/// A list of the numbers, each followed by `unit`, scaled and shifted
+fn number_list(unit: &str) -> String {
+    format!(
+        "[{}] * 2 + 1{}",
+        (1..=NUMBERS)
+            .map(|i| format!("{}{}", i, unit))
+            .collect::<Vec<_>>()
+            .join(", "),
+        unit
+    )
+}
+
+fn time(interpretor: &mut Interpretor, lines: &[String]) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        for line in lines {
+            interpretor.execute_command(line).unwrap();
+        }
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../unit_definitions/basic.ud");
+    let unit_definitions = UnitDefinitions::from_file(&path).unwrap();
+    let mut interpretor = Interpretor::new(&unit_definitions).unwrap();
+
+    for (name, unitless, with_unit) in [
+        (
+            format!("{} lines of a number", NUMBERS),
+            number_lines(""),
+            number_lines(" m"),
+        ),
+        (
+            format!("list of {} numbers", NUMBERS),
+            vec![number_list("")],
+            vec![number_list(" m")],
+        ),
+    ] {
+        let with_unit_time = time(&mut interpretor, &with_unit);
+        let unitless_time = time(&mut interpretor, &unitless);
+        println!(
+            "{:>24}: {:>10.3?} with a unit, {:>10.3?} without ({:.1}x)",
+            name,
+            with_unit_time,
+            unitless_time,
+            with_unit_time.as_secs_f64() / unitless_time.as_secs_f64()
+        );
+    }
+}
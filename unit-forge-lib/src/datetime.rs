@@ -0,0 +1,166 @@
+/// Unit of the values of date literals like `2024-01-01`, in seconds since 1970-01-01 (UTC)
+pub const DATETIME_UNIT: &str = "datetime";
+
+/// Category of the durations added to dates, its base unit is assumed to be the second
+pub const TIME_CATEGORY: &str = "time";
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Parses a `YYYY-MM-DD` date with an optional `THH:MM` or `THH:MM:SS` time into seconds since the epoch,
+/// `None` if it's not a valid date
+pub(crate) fn parse_datetime(literal: &str) -> Option<f64> {
+    let (date, time) = match literal.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (literal, None),
+    };
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let seconds_of_day = match time {
+        Some(time) => {
+            let mut time_parts = time.splitn(3, ':');
+            let hour: i64 = time_parts.next()?.parse().ok()?;
+            let minute: i64 = time_parts.next()?.parse().ok()?;
+            let second: i64 = time_parts.next().map_or(Some(0), |s| s.parse().ok())?;
+            if hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            hour * 3600 + minute * 60 + second
+        }
+        None => 0,
+    };
+
+    Some((days_from_civil(year, month, day) * SECONDS_PER_DAY + seconds_of_day) as f64)
+}
+
+/// Whether `format_datetime` can write a date, i.e. it falls within the years 0000 to 9999
+pub(crate) fn is_representable_datetime(seconds: f64) -> bool {
+    let first = days_from_civil(0, 1, 1) * SECONDS_PER_DAY;
+    let last = days_from_civil(10000, 1, 1) * SECONDS_PER_DAY - 1;
+    seconds.is_finite() && (first as f64..=last as f64).contains(&seconds.round())
+}
+
+/// Formats seconds since the epoch as `YYYY-MM-DD`, followed by `THH:MM:SS` unless it's midnight
+pub fn format_datetime(seconds: f64) -> String {
+    if !seconds.is_finite() {
+        return seconds.to_string();
+    }
+
+    let seconds = seconds.round() as i64;
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let seconds_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    if seconds_of_day == 0 {
+        date
+    } else {
+        format!(
+            "{}T{:02}:{:02}:{:02}",
+            date,
+            seconds_of_day / 3600,
+            seconds_of_day % 3600 / 60,
+            seconds_of_day % 60
+        )
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Counts years from March, so the leap day is the last day of a year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_datetime() {
+        assert_eq!(parse_datetime("1970-01-01"), Some(0.0));
+        assert_eq!(parse_datetime("1970-01-02T01:02:03"), Some(90123.0));
+        assert_eq!(parse_datetime("2000-03-01T12:30"), Some(951913800.0));
+        assert_eq!(parse_datetime("1969-12-31"), Some(-86400.0));
+        assert_eq!(parse_datetime("2024-02-29"), Some(1709164800.0));
+
+        for invalid in ["2023-02-29", "2024-13-01", "2024-00-10", "2024-01-01T24:00"] {
+            assert_eq!(parse_datetime(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_representable_datetime() {
+        for date in ["0000-01-01", "1970-01-01", "9999-12-31T23:59:59"] {
+            assert!(
+                is_representable_datetime(parse_datetime(date).unwrap()),
+                "{}",
+                date
+            );
+        }
+        let last = parse_datetime("9999-12-31T23:59:59").unwrap();
+        assert!(!is_representable_datetime(last + 1.0));
+        assert!(!is_representable_datetime(
+            parse_datetime("0000-01-01").unwrap() - 1.0
+        ));
+        assert!(!is_representable_datetime(1e300));
+        assert!(!is_representable_datetime(f64::NAN));
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(format_datetime(0.0), "1970-01-01");
+        assert_eq!(format_datetime(90123.0), "1970-01-02T01:02:03");
+        assert_eq!(format_datetime(-86400.0), "1969-12-31");
+        for date in [
+            "1600-02-29",
+            "2024-02-29",
+            "2100-03-01",
+            "9999-12-31T23:59:59",
+        ] {
+            assert_eq!(format_datetime(parse_datetime(date).unwrap()), date);
+        }
+    }
+}
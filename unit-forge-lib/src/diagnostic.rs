@@ -0,0 +1,125 @@
+use std::{fmt::Display, ops::Range};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, EvalError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Severity {
+    /// The input couldn't be evaluated
+    Error,
+    /// The input was evaluated, but the result may not be what was expected
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "Error"),
+            Severity::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+/// A message about a byte range of the input, see `Interpretor::evaluate_with_diagnostics`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Diagnostic {
+    pub span: Range<usize>,
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// Renders the message followed by the input with the span underlined, e.g.
+    /// ```text
+    /// Error: Unknown unit: "x"
+    ///   | 1 x
+    ///   | ^^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let source = source.trim_end_matches(['\r', '\n']);
+        let column = |offset: usize| {
+            source
+                .get(..offset.min(source.len()))
+                .map_or(offset, |prefix| prefix.chars().count())
+        };
+        let start = column(self.span.start);
+        let width = column(self.span.end).saturating_sub(start).max(1);
+
+        format!(
+            "{}: {}\n  | {}\n  | {}{}",
+            self.severity,
+            self.message,
+            source,
+            " ".repeat(start),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from((span, message): Error) -> Self {
+        Self {
+            span,
+            message,
+            severity: Severity::Error,
+        }
+    }
+}
+
+impl From<EvalError> for Diagnostic {
+    fn from(EvalError { span, message }: EvalError) -> Self {
+        Self {
+            span,
+            message,
+            severity: Severity::Error,
+        }
+    }
+}
+
+impl From<Diagnostic> for Error {
+    fn from(diagnostic: Diagnostic) -> Self {
+        (diagnostic.span, diagnostic.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let diagnostic = Diagnostic {
+            span: 4..7,
+            message: "Unknown unit: \"Ωx\"".to_string(),
+            severity: Severity::Error,
+        };
+        assert_eq!(
+            diagnostic.render("2 * Ωx\n"),
+            "Error: Unknown unit: \"Ωx\"\n  | 2 * Ωx\n  |     ^^"
+        );
+
+        let diagnostic = Diagnostic {
+            span: 7..7,
+            message: "found end of input".to_string(),
+            severity: Severity::Warning,
+        };
+        assert_eq!(
+            diagnostic.render("1 + 2 *"),
+            "Warning: found end of input\n  | 1 + 2 *\n  |        ^"
+        );
+    }
+
+    #[test]
+    fn test_tuple_conversion() {
+        let diagnostic = Diagnostic::from((0..3, "Unknown unit: \"x\"".to_string()));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            Error::from(diagnostic),
+            (0..3, "Unknown unit: \"x\"".to_string())
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Value};
+
+/// Above 2^53 not every integer is representable, so digits of larger magnitudes are approximations
+const MAX_EXACT_MAGNITUDE: f64 = 9_007_199_254_740_992.0;
+
+/// A successful evaluation of `Interpretor::evaluate`, with the unit described for display
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EvalResult {
+    pub value: Value,
+    /// Key of the unit as used in expressions, empty for dimensionless results
+    pub unit_key: String,
+    pub symbol: String,
+    /// Category the unit is defined in, empty if the unit doesn't belong to one
+    pub category: String,
+    /// Notes about the reliability of the value, e.g. precision lost to extreme magnitudes
+    pub warnings: Vec<String>,
+    /// Whether the unit was asked for, with `>>` or as the unit the input was entered in (see
+    /// `Interpretor::set_entry_unit_results`), so it's kept as is rather than displayed in another unit
+    pub requested_unit: bool,
+}
+
+/// The unit an expression results in regardless of its value, see `Interpretor::dimension_of`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Dimension {
+    /// Key of the unit, empty for unitless expressions
+    pub unit_key: String,
+    /// Category the unit is defined in, empty if the unit doesn't belong to one
+    pub category: String,
+}
+
+/// A unit conversion applied while evaluating an expression, see `Interpretor::trace`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TraceStep {
+    pub operation: TraceOperation,
+    pub input_units: Vec<String>,
+    pub output_unit: String,
+    /// What the value in the input unit is multiplied by to give it in the output unit, before any offset
+    pub factor: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TraceOperation {
+    /// A number is given a unit, e.g. `2 km`, and normalized to the base unit
+    ApplyUnit,
+    /// A value in a non-base unit, e.g. the result of `>> cm`, is normalized to the base unit
+    Normalize,
+    /// Two units are multiplied into the derived unit of the product
+    Multiply,
+    /// Two units are divided into the derived unit of the quotient
+    Divide,
+    /// A value is converted to the target of `>>`
+    Convert,
+}
+
+/// A parse or evaluation error located by its byte range in the input
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EvalError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl From<Error> for EvalError {
+    fn from((span, message): Error) -> Self {
+        Self { span, message }
+    }
+}
+
+/// Warns about numbers of `value` whose magnitude is beyond the range `f64` represents precisely
+pub(crate) fn precision_warnings(value: &Value) -> Vec<String> {
+    let nums = match value {
+        Value::Number(num) => vec![*num],
+        Value::List(list) => list.clone(),
+        Value::Mixed(parts) | Value::Each(parts) => parts.iter().map(|(num, _)| *num).collect(),
+    };
+
+    let mut warnings = Vec::new();
+    for num in nums {
+        let warning = if !num.is_finite() {
+            format!("{} is out of the range of representable numbers", num)
+        } else if num.abs() > MAX_EXACT_MAGNITUDE {
+            format!(
+                "{:e} is too large to be exact, only about 15 significant digits are reliable",
+                num
+            )
+        } else if num != 0.0 && num.abs() < f64::MIN_POSITIVE {
+            format!("{:e} is too small to be precise", num)
+        } else {
+            continue;
+        };
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precision_warnings() {
+        assert!(precision_warnings(&Value::Number(1.5e15)).is_empty());
+        assert!(precision_warnings(&Value::Number(-1e-300)).is_empty());
+        assert_eq!(
+            precision_warnings(&Value::List(vec![1.0, 9.4607e24])),
+            vec![
+                "9.4607e24 is too large to be exact, only about 15 significant digits are reliable"
+            ]
+        );
+        assert_eq!(
+            precision_warnings(&Value::Number(1e-310)),
+            vec!["1e-310 is too small to be precise"]
+        );
+        assert_eq!(
+            precision_warnings(&Value::Number(f64::INFINITY)),
+            vec!["inf is out of the range of representable numbers"]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_eval_result_serde_round_trip() {
+        let result = EvalResult {
+            value: Value::List(vec![1.5, 2.0]),
+            unit_key: "m2".to_string(),
+            symbol: "m²".to_string(),
+            category: "area".to_string(),
+            warnings: vec![],
+            requested_unit: false,
+        };
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(
+            json,
+            r#"{"value":{"List":[1.5,2.0]},"unit_key":"m2","symbol":"m²","category":"area","warnings":[],"requested_unit":false}"#
+        );
+        assert_eq!(serde_json::from_str::<EvalResult>(&json).unwrap(), result);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_eval_error_serde_round_trip() {
+        let error = EvalError {
+            span: 0..5,
+            message: "Unknown unit: \"x\"".to_string(),
+        };
+        let json = serde_json::to_string(&error).unwrap();
+        assert_eq!(
+            json,
+            r#"{"span":{"start":0,"end":5},"message":"Unknown unit: \"x\""}"#
+        );
+        assert_eq!(serde_json::from_str::<EvalError>(&json).unwrap(), error);
+    }
+}
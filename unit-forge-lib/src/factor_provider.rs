@@ -0,0 +1,19 @@
+use std::collections::HashMap;
+
+/// Supplies the factors of the units of the `currency` category at runtime, e.g. exchange rates of a
+/// remote service, see `Interpretor::set_factor_provider`.
+///
+/// Factors are only queried when the provider is set and on `Interpretor::reload`, not on every
+/// conversion. A provider backed by an HTTP API would therefore fetch all rates into a map when it's
+/// created and look them up in `factor`, and is set again to refresh them.
+pub trait FactorProvider {
+    /// Value of one `unit` in the base unit of its category, `None` keeps the factor of its definition
+    fn factor(&self, unit: &str) -> Option<f64>;
+}
+
+/// Fixed factors by unit key
+impl FactorProvider for HashMap<String, f64> {
+    fn factor(&self, unit: &str) -> Option<f64> {
+        self.get(unit).copied()
+    }
+}
@@ -0,0 +1,758 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::{DATETIME_UNIT, Value, format_datetime};
+
+/// How numbers are written in formatted results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Notation {
+    #[default]
+    Decimal,
+    /// Scientific notation whose exponent is always a multiple of 3, e.g. `12.3e3`
+    Engineering,
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decimal" => Ok(Notation::Decimal),
+            "engineering" => Ok(Notation::Engineering),
+            _ => Err(format!(
+                "Unknown notation \"{}\", expected \"decimal\" or \"engineering\"",
+                s
+            )),
+        }
+    }
+}
+
+/// How exponents in units are written, e.g. of `m3`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExponentStyle {
+    /// As in the unit key or label, e.g. `m3`
+    #[default]
+    Key,
+    /// `m^3`
+    Caret,
+    /// `m³`
+    Unicode,
+}
+
+impl FromStr for ExponentStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(ExponentStyle::Key),
+            "caret" => Ok(ExponentStyle::Caret),
+            "unicode" => Ok(ExponentStyle::Unicode),
+            _ => Err(format!(
+                "Unknown exponent style \"{}\", expected \"key\", \"caret\" or \"unicode\"",
+                s
+            )),
+        }
+    }
+}
+
+/// How numbers are rounded to the precision or significant figures they're shown with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoundingMode {
+    /// Ties away from zero, e.g. `2.5` -> `3`
+    HalfUp,
+    /// Ties to the even neighbor, e.g. `2.5` -> `2` and `3.5` -> `4`
+    #[default]
+    HalfEven,
+    TowardZero,
+    Ceil,
+    Floor,
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-up" => Ok(RoundingMode::HalfUp),
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "toward-zero" => Ok(RoundingMode::TowardZero),
+            "ceil" => Ok(RoundingMode::Ceil),
+            "floor" => Ok(RoundingMode::Floor),
+            _ => Err(format!(
+                "Unknown rounding mode \"{}\", expected \"half-up\", \"half-even\", \"toward-zero\", \"ceil\" or \"floor\"",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    pub notation: Notation,
+    /// Number of decimal places, of the mantissa in engineering notation
+    pub precision: Option<usize>,
+    /// Applied when rounding to `precision` or `sig_figs`
+    pub rounding: RoundingMode,
+    /// Labels shown instead of the unit keys, e.g. `Interpretor::unit_symbols`. Keys are shown when empty.
+    pub unit_labels: HashMap<String, String>,
+    /// Applied to the unit labels
+    pub exponent_style: ExponentStyle,
+    /// Shown in place of the unit of dimensionless results, e.g. `(dimensionless)`. Nothing is shown when empty.
+    pub dimensionless_label: String,
+    /// Significant figures of values in a unit by unit key, e.g. `Interpretor::unit_sig_figs`.
+    /// They take precedence over `precision`.
+    pub sig_figs: HashMap<String, usize>,
+    /// Inserted between groups of three digits of the integer part, e.g. `,` for `30,006.602`
+    pub digit_separator: Option<char>,
+    /// Values within rounding error of a fraction whose denominator is up to this are shown as one,
+    /// e.g. `1/2` or `1 1/3`, other values as set by the other options
+    pub max_denominator: Option<u64>,
+}
+
+/// How far a value may be from a fraction to be shown as it, to tolerate floating point error
+const FRACTION_TOLERANCE: f64 = 1e-9;
+
+/// Formats the input of an evaluation followed by its result, as `input = value unit`,
+/// e.g. `360 km / 2 hour = 50 m/s` to log what each result came from
+pub fn format_echo(input: &str, value: &Value, unit: &str, options: &FormatOptions) -> String {
+    format!("{} = {}", input.trim(), format_result(value, unit, options))
+}
+
+/// Formats an evaluation result as `value unit`, with `dimensionless_label` as the unit when it's empty.
+/// Dates are formatted as `2024-01-01` without a unit.
+pub fn format_result(value: &Value, unit: &str, options: &FormatOptions) -> String {
+    if unit == DATETIME_UNIT {
+        return match value {
+            Value::Number(seconds) => format_datetime(*seconds),
+            Value::List(list) => {
+                let items = list
+                    .iter()
+                    .map(|seconds| format_datetime(*seconds))
+                    .collect::<Vec<_>>();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Mixed(_) | Value::Each(_) => format_value(value, options),
+        };
+    }
+    let is_mixed = matches!(value, Value::Mixed(_) | Value::Each(_));
+    let value = format_value_in(value, unit, options);
+    if unit.is_empty() {
+        // Mixed values carry the units of their parts
+        if is_mixed || options.dimensionless_label.is_empty() {
+            value
+        } else {
+            format!("{} {}", value, options.dimensionless_label)
+        }
+    } else {
+        format!("{} {}", value, format_unit(unit, options))
+    }
+}
+
+pub fn format_unit(unit: &str, options: &FormatOptions) -> String {
+    let label = options.unit_labels.get(unit).map_or(unit, String::as_str);
+    match options.exponent_style {
+        ExponentStyle::Key => label.to_string(),
+        ExponentStyle::Caret => caret_exponents(label),
+        ExponentStyle::Unicode => superscript_exponents(label),
+    }
+}
+
+/// Rewrites exponents with a caret, e.g. `m3` -> `m^3` and `m³` -> `m^3`
+pub fn caret_exponents(unit: &str) -> String {
+    let mut result = String::with_capacity(unit.len());
+    let mut in_exponent = false;
+    let mut prev: Option<char> = None;
+    for c in unit.chars() {
+        let follows_unit = prev.is_some_and(|prev| prev.is_alphabetic() || prev == ')');
+        let digit = match c {
+            '0'..='9' if in_exponent || follows_unit => Some(c),
+            _ => normal_digit(c),
+        };
+        match digit {
+            Some(digit) => {
+                if !in_exponent {
+                    result.push('^');
+                }
+                result.push(digit);
+                in_exponent = true;
+            }
+            None if c == '⁻' => {
+                result.push_str("^-");
+                in_exponent = true;
+            }
+            None => {
+                result.push(c);
+                in_exponent = c == '^';
+            }
+        }
+        prev = Some(c);
+    }
+    result
+}
+
+/// The digit of a superscript digit, e.g. `³` -> `3`
+fn normal_digit(superscript: char) -> Option<char> {
+    let digit = match superscript {
+        '⁰' => '0',
+        '¹' => '1',
+        '²' => '2',
+        '³' => '3',
+        '⁴' => '4',
+        '⁵' => '5',
+        '⁶' => '6',
+        '⁷' => '7',
+        '⁸' => '8',
+        '⁹' => '9',
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// Rewrites exponents as Unicode superscripts, e.g. `m2` -> `m²` and `m/s^2` -> `m/s²`
+pub fn superscript_exponents(unit: &str) -> String {
+    let mut result = String::with_capacity(unit.len());
+    let mut in_exponent = false;
+    let mut prev: Option<char> = None;
+    let mut chars = unit.chars().peekable();
+    while let Some(c) = chars.next() {
+        let follows_unit = prev.is_some_and(|prev| prev.is_alphabetic() || prev == ')');
+        match c {
+            '^' if chars
+                .peek()
+                .is_some_and(|next| next.is_ascii_digit() || *next == '-') =>
+            {
+                in_exponent = true
+            }
+            '-' if prev == Some('^') => result.push('⁻'),
+            '0'..='9' if in_exponent || follows_unit => {
+                result.push(superscript_digit(c));
+                in_exponent = true;
+            }
+            _ => {
+                result.push(c);
+                in_exponent = false;
+            }
+        }
+        prev = Some(c);
+    }
+    result
+}
+
+fn superscript_digit(digit: char) -> char {
+    match digit {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        _ => '⁹',
+    }
+}
+
+pub fn format_value(value: &Value, options: &FormatOptions) -> String {
+    format_value_in(value, "", options)
+}
+
+/// Like `format_value`, for a value in `unit`
+fn format_value_in(value: &Value, unit: &str, options: &FormatOptions) -> String {
+    match value {
+        Value::Number(num) => format_number_in(*num, unit, options),
+        Value::List(list) => {
+            let items = list
+                .iter()
+                .map(|num| format_number_in(*num, unit, options))
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Mixed(parts) => format_parts(parts, " ", options),
+        // One line per unit
+        Value::Each(parts) => format_parts(parts, "\n", options),
+    }
+}
+
+fn format_parts(parts: &[(f64, String)], separator: &str, options: &FormatOptions) -> String {
+    parts
+        .iter()
+        .map(|(num, unit)| {
+            format!(
+                "{} {}",
+                format_number_in(*num, unit, options),
+                format_unit(unit, options)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+pub fn format_number(num: f64, options: &FormatOptions) -> String {
+    match options.notation {
+        Notation::Decimal => match options.precision {
+            Some(precision) => format!(
+                "{:.*}",
+                precision,
+                round_decimal(num, precision as i32, options.rounding)
+            ),
+            None => num.to_string(),
+        },
+        Notation::Engineering => format_engineering(num, options.precision, options.rounding),
+    }
+}
+
+/// Like `format_number`, with the significant figures of `unit` if it has any and the digits grouped
+fn format_number_in(num: f64, unit: &str, options: &FormatOptions) -> String {
+    if let Some(fraction) = options
+        .max_denominator
+        .and_then(|max_denominator| format_fraction(num, max_denominator))
+    {
+        return fraction;
+    }
+    let formatted = match options.sig_figs.get(unit) {
+        Some(&sig_figs) => format_significant(num, sig_figs, options),
+        None => format_number(num, options),
+    };
+    match options.digit_separator {
+        Some(separator) => group_digits(&formatted, separator),
+        None => formatted,
+    }
+}
+
+/// Writes `num` as a fraction with the smallest denominator up to `max_denominator` it's close to,
+/// with the whole part before it, e.g. `1 1/2` for `1.5`. `None` for integers and other values.
+fn format_fraction(num: f64, max_denominator: u64) -> Option<String> {
+    if !num.is_finite() {
+        return None;
+    }
+    let whole = num.abs().trunc();
+    let fractional = num.abs() - whole;
+    let (numerator, denominator) = (2..=max_denominator).find_map(|denominator| {
+        let numerator = (fractional * denominator as f64).round();
+        let is_close = (fractional - numerator / denominator as f64).abs() < FRACTION_TOLERANCE;
+        (is_close && numerator > 0.0 && numerator < denominator as f64)
+            .then_some((numerator, denominator))
+    })?;
+
+    let sign = if num < 0.0 { "-" } else { "" };
+    Some(if whole == 0.0 {
+        format!("{}{}/{}", sign, numerator, denominator)
+    } else {
+        format!("{}{} {}/{}", sign, whole, numerator, denominator)
+    })
+}
+
+/// Inserts `separator` between groups of three digits of the integer part of a formatted number,
+/// e.g. `-1234567.891` to `-1,234,567.891`
+fn group_digits(formatted: &str, separator: char) -> String {
+    let (sign, unsigned) = match formatted.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", formatted),
+    };
+    let integer_len = unsigned
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(unsigned.len());
+    let (integer, rest) = unsigned.split_at(integer_len);
+
+    let mut grouped = sign.to_string();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer_len - i) % 3 == 0 {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(rest);
+    grouped
+}
+
+/// Rounds `num` to `sig_figs` significant figures, e.g. `1234.5` to `1230` and `0.012345` to `0.0123` with 3
+fn format_significant(num: f64, sig_figs: usize, options: &FormatOptions) -> String {
+    if num == 0.0 || !num.is_finite() || sig_figs == 0 {
+        return format_number(
+            num,
+            &FormatOptions {
+                notation: options.notation,
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut decimals = sig_figs as i32 - 1 - num.abs().log10().floor() as i32;
+    let rounded = round_decimal(num, decimals, options.rounding);
+    // Rounding may carry over to the next digit, e.g. 9.99 -> 10.0 with 2
+    if rounded != 0.0 && rounded.abs().log10().floor() > num.abs().log10().floor() {
+        decimals -= 1;
+    }
+    match options.notation {
+        Notation::Decimal => format!("{:.*}", decimals.max(0) as usize, rounded),
+        Notation::Engineering => format_engineering(rounded, None, options.rounding),
+    }
+}
+
+/// Rounds `num` to `decimals` decimal places, or to tens, hundreds etc. if it's negative.
+/// Ties are decided on the shortest decimal representation of `num`, so `2.675` is a tie
+/// even though its binary value is slightly less.
+fn round_decimal(num: f64, decimals: i32, mode: RoundingMode) -> f64 {
+    if num == 0.0 || !num.is_finite() {
+        return num;
+    }
+    let scientific = format!("{:e}", num.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let digits: Vec<u8> = mantissa
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .map(|digit| digit - b'0')
+        .collect();
+
+    // Number of leading digits that are kept, the rest is dropped
+    let kept = exponent + 1 + decimals;
+    if kept >= digits.len() as i32 {
+        return num;
+    }
+    let (kept_digits, dropped) = digits.split_at(kept.max(0) as usize);
+    let kept_value = kept_digits
+        .iter()
+        .fold(0u64, |value, digit| value * 10 + *digit as u64);
+    // How the dropped digits compare to half a unit of the last kept digit, they're never all zero
+    let half = if kept < 0 {
+        std::cmp::Ordering::Less
+    } else {
+        dropped[0].cmp(&5).then_with(|| {
+            if dropped[1..].iter().any(|digit| *digit != 0) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+    };
+    let round_up = match mode {
+        RoundingMode::HalfUp => half.is_ge(),
+        RoundingMode::HalfEven => half.is_gt() || (half.is_eq() && kept_value % 2 == 1),
+        RoundingMode::TowardZero => false,
+        RoundingMode::Ceil => num > 0.0,
+        RoundingMode::Floor => num < 0.0,
+    };
+    let magnitude = kept_value + u64::from(round_up);
+    // Parsing the decimal literal gives the nearest `f64`, unlike multiplying by a power of 10
+    let rounded: f64 = format!("{}e{}", magnitude, -decimals)
+        .parse()
+        .unwrap_or(num.abs());
+    rounded.copysign(num)
+}
+
+fn format_engineering(num: f64, precision: Option<usize>, rounding: RoundingMode) -> String {
+    if num == 0.0 || !num.is_finite() {
+        return format_number(
+            num,
+            &FormatOptions {
+                precision,
+                ..Default::default()
+            },
+        );
+    }
+
+    let (mantissa, exponent) = match precision {
+        Some(precision) => {
+            let mut exponent = (num.abs().log10() / 3.0).floor() as i32 * 3;
+            let mut mantissa = num / 10f64.powi(exponent);
+            // Rounding may carry the mantissa over to the next step, e.g. 999.96 -> 1000.0
+            if round_decimal(mantissa, precision as i32, rounding).abs() >= 1000.0 {
+                mantissa /= 1000.0;
+                exponent += 3;
+            }
+            let mantissa = round_decimal(mantissa, precision as i32, rounding);
+            (format!("{:.*}", precision, mantissa), exponent)
+        }
+        None => shift_scientific(&format!("{:e}", num)),
+    };
+
+    if exponent == 0 {
+        mantissa
+    } else {
+        format!("{}e{}", mantissa, exponent)
+    }
+}
+
+/// Moves the decimal point of a scientific literal like `1.23e4` so the exponent is a multiple of 3.
+/// Works on the digits directly so no floating point noise is introduced.
+fn shift_scientific(scientific: &str) -> (String, i32) {
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+
+    let shift = exponent.rem_euclid(3) as usize;
+    while digits.len() < shift + 1 {
+        digits.push('0');
+    }
+    let (int_part, frac_part) = digits.split_at(shift + 1);
+    let mantissa = if frac_part.is_empty() {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, frac_part)
+    };
+
+    (mantissa, exponent - shift as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engineering_notation() {
+        let options = FormatOptions {
+            notation: Notation::Engineering,
+            ..Default::default()
+        };
+        assert_eq!(format_number(12300.0, &options), "12.3e3");
+        assert_eq!(format_number(0.0000047, &options), "4.7e-6");
+        assert_eq!(format_number(-0.012, &options), "-12e-3");
+        assert_eq!(format_number(1234567.0, &options), "1.234567e6");
+        assert_eq!(format_number(123.4, &options), "123.4");
+        assert_eq!(format_number(0.0, &options), "0");
+    }
+
+    #[test]
+    fn test_engineering_notation_with_precision() {
+        let options = FormatOptions {
+            notation: Notation::Engineering,
+            precision: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(format_number(12346.0, &options), "12.35e3");
+        assert_eq!(format_number(0.0000047, &options), "4.70e-6");
+        assert_eq!(format_number(999999.0, &options), "1.00e6");
+    }
+
+    #[test]
+    fn test_fractions() {
+        let options = FormatOptions {
+            max_denominator: Some(16),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_result(&Value::Number(0.5), "cup", &options),
+            "1/2 cup"
+        );
+        assert_eq!(format_value(&Value::Number(1.0 / 3.0), &options), "1/3");
+        assert_eq!(format_value(&Value::Number(0.37), &options), "0.37");
+        assert_eq!(format_value(&Value::Number(-1.75), &options), "-1 3/4");
+        assert_eq!(format_value(&Value::Number(2.0), &options), "2");
+        assert_eq!(
+            format_value(&Value::List(vec![0.25, 0.2]), &options),
+            "[1/4, 1/5]"
+        );
+
+        let options = FormatOptions {
+            max_denominator: Some(4),
+            ..FormatOptions::default()
+        };
+        assert_eq!(format_value(&Value::Number(0.2), &options), "0.2");
+    }
+
+    #[test]
+    fn test_format_echo() {
+        let options = FormatOptions {
+            unit_labels: HashMap::from([("mps".to_string(), "m/s".to_string())]),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_echo("360 km / 2 hour\n", &Value::Number(50.0), "mps", &options),
+            "360 km / 2 hour = 50 m/s"
+        );
+        assert_eq!(
+            format_echo("2 km / 500 m", &Value::Number(4.0), "", &options),
+            "2 km / 500 m = 4"
+        );
+    }
+
+    #[test]
+    fn test_superscript_exponents() {
+        assert_eq!(superscript_exponents("m3"), "m³");
+        assert_eq!(superscript_exponents("m²"), "m²");
+        assert_eq!(superscript_exponents("m/s^2"), "m/s²");
+        assert_eq!(superscript_exponents("s^-1"), "s⁻¹");
+        assert_eq!(superscript_exponents("km10"), "km¹⁰");
+        assert_eq!(superscript_exponents("kg*m"), "kg*m");
+    }
+
+    #[test]
+    fn test_caret_exponents() {
+        assert_eq!(caret_exponents("m3"), "m^3");
+        assert_eq!(caret_exponents("m³"), "m^3");
+        assert_eq!(caret_exponents("m/s^2"), "m/s^2");
+        assert_eq!(caret_exponents("s⁻¹"), "s^-1");
+        assert_eq!(caret_exponents("km10"), "km^10");
+        assert_eq!(caret_exponents("kg*m"), "kg*m");
+    }
+
+    #[test]
+    fn test_exponent_styles() {
+        let format = |exponent_style| {
+            let options = FormatOptions {
+                exponent_style,
+                ..Default::default()
+            };
+            format_result(&Value::Number(2.0), "m3", &options)
+        };
+        assert_eq!(format(ExponentStyle::Key), "2 m3");
+        assert_eq!(format(ExponentStyle::Caret), "2 m^3");
+        assert_eq!(format(ExponentStyle::Unicode), "2 m³");
+    }
+
+    #[test]
+    fn test_format_result() {
+        let options = FormatOptions {
+            notation: Notation::Engineering,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_result(&Value::List(vec![1500.0, 0.25]), "m", &options),
+            "[1.5e3, 250e-3] m"
+        );
+        assert_eq!(
+            format_result(&Value::Number(7.0), "", &FormatOptions::default()),
+            "7"
+        );
+    }
+
+    #[test]
+    fn test_dimensionless_label() {
+        let options = FormatOptions {
+            dimensionless_label: "(dimensionless)".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_result(&Value::Number(7.0), "", &options),
+            "7 (dimensionless)"
+        );
+        assert_eq!(format_result(&Value::Number(7.0), "m", &options), "7 m");
+        assert_eq!(
+            format_result(
+                &Value::Mixed(vec![(1.0, "h".to_string()), (30.0, "min".to_string())]),
+                "",
+                &options
+            ),
+            "1 h 30 min"
+        );
+    }
+
+    #[test]
+    fn test_digit_separator() {
+        let options = FormatOptions {
+            digit_separator: Some(','),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_result(&Value::Number(30006.602), "m", &options),
+            "30,006.602 m"
+        );
+        assert_eq!(
+            format_result(&Value::List(vec![-1234567.0, 999.5]), "", &options),
+            "[-1,234,567, 999.5]"
+        );
+        assert_eq!(format_value(&Value::Number(f64::INFINITY), &options), "inf");
+
+        let options = FormatOptions {
+            digit_separator: Some(' '),
+            precision: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_value(&Value::Number(1234567.891), &options),
+            "1 234 567.89"
+        );
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let format = |num, precision, rounding| {
+            format_number(
+                num,
+                &FormatOptions {
+                    precision: Some(precision),
+                    rounding,
+                    ..Default::default()
+                },
+            )
+        };
+        for (rounding, expected) in [
+            (RoundingMode::HalfUp, ["3", "-3", "4", "2.68"]),
+            (RoundingMode::HalfEven, ["2", "-2", "4", "2.68"]),
+            (RoundingMode::TowardZero, ["2", "-2", "3", "2.67"]),
+            (RoundingMode::Ceil, ["3", "-2", "4", "2.68"]),
+            (RoundingMode::Floor, ["2", "-3", "3", "2.67"]),
+        ] {
+            let formatted = [
+                format(2.5, 0, rounding),
+                format(-2.5, 0, rounding),
+                format(3.5, 0, rounding),
+                format(2.675, 2, rounding),
+            ];
+            assert_eq!(formatted, expected, "{:?}", rounding);
+        }
+        assert_eq!(format(0.004, 2, RoundingMode::Ceil), "0.01");
+        assert_eq!(format(0.004, 2, RoundingMode::HalfUp), "0.00");
+        assert_eq!(format(-0.004, 2, RoundingMode::Floor), "-0.01");
+
+        let options = FormatOptions {
+            sig_figs: HashMap::from([("g".to_string(), 2)]),
+            rounding: RoundingMode::HalfUp,
+            ..Default::default()
+        };
+        assert_eq!(format_result(&Value::Number(125.0), "g", &options), "130 g");
+        let options = FormatOptions {
+            notation: Notation::Engineering,
+            precision: Some(1),
+            rounding: RoundingMode::Floor,
+            ..Default::default()
+        };
+        assert_eq!(format_number(12399.0, &options), "12.3e3");
+    }
+
+    #[test]
+    fn test_sig_figs() {
+        let options = FormatOptions {
+            precision: Some(5),
+            sig_figs: HashMap::from([("g".to_string(), 3), ("h".to_string(), 2)]),
+            ..Default::default()
+        };
+        let format = |num, unit| format_result(&Value::Number(num), unit, &options);
+        assert_eq!(format(1234.5678, "g"), "1230 g");
+        assert_eq!(format(0.012345, "g"), "0.0123 g");
+        assert_eq!(format(-2.0, "g"), "-2.00 g");
+        assert_eq!(format(9.996, "g"), "10.0 g");
+        assert_eq!(format(1234.5678, "m"), "1234.56780 m");
+        assert_eq!(
+            format_result(&Value::List(vec![1.0, 22.22]), "g", &options),
+            "[1.00, 22.2] g"
+        );
+        assert_eq!(
+            format_result(
+                &Value::Mixed(vec![(1.0, "h".to_string()), (30.0, "min".to_string())]),
+                "",
+                &options
+            ),
+            "1.0 h 30.00000 min"
+        );
+
+        let options = FormatOptions {
+            notation: Notation::Engineering,
+            ..options
+        };
+        assert_eq!(
+            format_result(&Value::Number(12345.0), "g", &options),
+            "12.3e3 g"
+        );
+    }
+}
@@ -1,324 +1,5214 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, Write},
+};
 
-use chumsky::{extra::Err, number::format::RUST_LITERAL, prelude::*};
+use chumsky::{
+    error::LabelError, extra::Err, number::format::RUST_LITERAL, prelude::*, util::MaybeRef,
+};
 
-use crate::{DefinitionError, unit::UnitTable, unit_definition::UnitDefinitions};
+use crate::{
+    DATETIME_UNIT, DefinitionError, Diagnostic, Dimension, EvalError, EvalResult, Factor,
+    FactorProvider, FormatOptions, Severity, StoredVar, TIME_CATEGORY, TraceOperation, TraceStep,
+    Value,
+    datetime::{format_datetime, is_representable_datetime, parse_datetime},
+    eval_result::precision_warnings,
+    format_result,
+    ratio::Ratio,
+    unit::UnitTable,
+    unit_definition::UnitDefinitions,
+};
 
 pub type Error = (std::ops::Range<usize>, String);
 
+/// An error of the parser, the unexpected input of a `Simple` error unless it has a message of its own
+#[derive(Debug)]
+struct ParseError<'src> {
+    error: Simple<'src, char>,
+    message: Option<&'static str>,
+}
+
+impl<'src> From<Simple<'src, char>> for ParseError<'src> {
+    fn from(error: Simple<'src, char>) -> Self {
+        ParseError {
+            error,
+            message: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.message {
+            Some(message) => f.write_str(message),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+impl<'src> chumsky::error::Error<'src, &'src str> for ParseError<'src> {
+    fn merge(self, other: Self) -> Self {
+        // Keep the message of either error at the same position
+        if self.message.is_none() && other.message.is_some() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+impl<'src, L> LabelError<'src, &'src str, L> for ParseError<'src> {
+    fn expected_found<E: IntoIterator<Item = L>>(
+        _expected: E,
+        found: Option<MaybeRef<'src, char>>,
+        span: SimpleSpan,
+    ) -> Self {
+        Simple::new(found, span).into()
+    }
+}
+
+/// Variables by name, with their values and units
+type Vars = HashMap<String, (Value, String)>;
+
+/// What an expression is evaluated with: the variables, the steps of a `trace` if one is taken,
+/// and the results of sub-expressions if they're memoized
+struct Scope<'a> {
+    vars: &'a mut Vars,
+    trace: Option<&'a mut Vec<TraceStep>>,
+    memo: Option<Memo>,
+    /// Number of nested calls of user functions, bounded by `MAX_CALL_DEPTH`
+    calls: usize,
+}
+
+impl Scope<'_> {
+    /// Forgets the memoized results, which may depend on the variables that just changed
+    fn vars_changed(&mut self) {
+        if let Some(memo) = &mut self.memo {
+            memo.results.clear();
+            memo.generation += 1;
+        }
+    }
+
+    fn record(
+        &mut self,
+        operation: TraceOperation,
+        input_units: &[&str],
+        output_unit: &str,
+        factor: f64,
+    ) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceStep {
+                operation,
+                input_units: input_units.iter().map(|unit| unit.to_string()).collect(),
+                output_unit: output_unit.to_string(),
+                factor,
+            });
+        }
+    }
+}
+
+/// What the unit of an expression is found with by `Interpretor::unit_of` without evaluating it
+struct UnitScope<'a> {
+    vars: &'a Vars,
+    /// Units of the names bound while walking the expression, innermost last, `None` where unknown
+    bound: Vec<(String, Option<String>)>,
+    /// User functions whose bodies are being walked, their calls inside them have an unknown unit
+    calling: Vec<String>,
+}
+
+/// Results of the sub-expressions of one evaluation, shared by identical sub-expressions,
+/// see `Interpretor::set_memoization`
+struct Memo {
+    /// Structural id of each node by its address, identical sub-expressions get the same id
+    ids: HashMap<*const (), usize>,
+    results: HashMap<usize, (Value, String)>,
+    /// Incremented whenever the variables change, results computed across a change aren't kept
+    generation: usize,
+}
+
+impl Memo {
+    fn new(expr: &Expr) -> Self {
+        let mut ids = HashMap::new();
+        intern(expr, &mut HashMap::new(), &mut ids);
+        Self {
+            ids,
+            results: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    fn id(&self, expr: &Expr) -> Option<usize> {
+        self.ids.get(&node_address(expr)).copied()
+    }
+}
+
+/// Assigns structural ids to `expr` and its descendants bottom-up, a node is identified by its label
+/// and the ids of its children, so identical sub-expressions are found without comparing them again
+fn intern(
+    expr: &Expr,
+    interned: &mut HashMap<(String, Vec<usize>), usize>,
+    ids: &mut HashMap<*const (), usize>,
+) -> usize {
+    let children = expr
+        .children()
+        .into_iter()
+        .map(|child| intern(child, interned, ids))
+        .collect();
+    let next_id = interned.len();
+    let id = *interned.entry((expr.label(), children)).or_insert(next_id);
+    ids.insert(node_address(expr), id);
+    id
+}
+
+/// Identifies a node of a parsed expression, which doesn't move while it's evaluated
+fn node_address(expr: &Expr) -> *const () {
+    (expr as *const Expr).cast()
+}
+
+const KEYWORDS: [&str; 3] = ["let", "in", "fn"];
+
+/// Functions provided by `Interpretor::call`, which user functions can't replace
+const BUILTIN_FUNCTIONS: [&str; 2] = ["abs_diff", "rel_diff"];
+
+/// How deeply calls of user functions may nest, so a function calling itself fails instead of overflowing the stack
+const MAX_CALL_DEPTH: usize = 32;
+
+/// Number of recent results kept for `$N` and `Interpretor::stack`
+const HISTORY_LIMIT: usize = 100;
+
+/// Another name of the last result like `$`, as in many calculators
+const LAST_RESULT_ALIAS: &str = "ans";
+
+/// Symbols which aren't letters but are commonly part of unit keys, e.g. `°C`
+const UNIT_SYMBOL_CHARS: [char; 3] = ['°', '′', '″'];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || UNIT_SYMBOL_CHARS.contains(&c)
+}
+
+fn is_ident_continue(c: char) -> bool {
+    is_ident_start(c) || c.is_alphanumeric()
+}
+
+/// Category whose factors can be replaced at runtime by exchange rates, see `Interpretor::set_rates`
+pub const CURRENCY_CATEGORY: &str = "currency";
+
+/// SI prefixes recognized by `Interpretor::set_prefix_fallback`, `da` before `d` so it takes precedence
+const SI_PREFIXES: [(&str, f64); 21] = [
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("µ", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+    ("y", 1e-24),
+];
+
 #[derive(Debug)]
 enum Expr<'src> {
     Num(f64, &'src str), // Store the unit as a string alongside the number
+    // A number followed by a name without brackets, e.g. `5 s`: the name is a unit if it's known,
+    // otherwise a variable the number is multiplied by
+    NumOrVar(f64, &'src str),
+    // A number after a symbol like `$5`, the symbol is mapped to its unit by `set_prefix_symbols`
+    Prefixed(f64, &'src str),
+    Date(f64),    // Seconds since the epoch
+    Percent(f64), // e.g. `10%`, which is `0.1` unless it's added to or subtracted from a value
     Var(&'src str),
+    List(Vec<Expr<'src>>, &'src str), // Elements alongside the unit applied to the whole list
+    WithUnit(Box<Expr<'src>>, &'src str), // A unit applied to a unitless sub-expression, e.g. `(3 + 4) m`
 
     Neg(Box<Expr<'src>>),
     Add(Box<Expr<'src>>, Box<Expr<'src>>),
     Sub(Box<Expr<'src>>, Box<Expr<'src>>),
     Mul(Box<Expr<'src>>, Box<Expr<'src>>),
     Div(Box<Expr<'src>>, Box<Expr<'src>>),
+    Pow(Box<Expr<'src>>, Box<Expr<'src>>), // The exponent is dimensionless, e.g. `2^10` or `(3 m)^2`
+    Cmp(CmpOp, Box<Expr<'src>>, Box<Expr<'src>>), // Evaluates to 1 when true, 0 otherwise
+    Call(&'src str, Vec<Expr<'src>>),      // e.g. `abs_diff(5 m, 4.9 m)`
 
     Assign {
         name: &'src str,
         rhs: Box<Expr<'src>>,
     },
+    If {
+        cond: Box<Expr<'src>>,
+        then: Box<Expr<'src>>,
+        otherwise: Box<Expr<'src>>,
+    },
+    Let {
+        name: &'src str,
+        value: Box<Expr<'src>>,
+        body: Box<Expr<'src>>,
+    },
+    // `fn NAME(PARAMS) = BODY`, the body is kept by `Interpretor::define_function` for later commands
+    Define {
+        name: &'src str,
+        params: Vec<&'src str>,
+        body: Box<Expr<'src>>,
+    },
+
+    To(Box<Expr<'src>>, Option<Target<'src>>),
+}
+
+impl<'src> Expr<'src> {
+    /// Writes the expression as an indented tree, one node per line with its children below it
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(self.label().trim_end());
+        out.push('\n');
+
+        for child in self.children() {
+            child.write_tree(depth + 1, out);
+        }
+    }
+
+    /// Describes the node itself without its children, e.g. `Num 5 m` or `Add`
+    fn label(&self) -> String {
+        match self {
+            Expr::Num(num, unit) => format!("Num {} {}", num, unit),
+            Expr::NumOrVar(num, name) => format!("NumOrVar {} {}", num, name),
+            Expr::Date(seconds) => format!("Date {}", format_datetime(*seconds)),
+            Expr::Percent(percent) => format!("Percent {}", percent),
+            Expr::Prefixed(num, symbol) => format!("Prefixed {} {}", symbol, num),
+            Expr::Var(name) => format!("Var {}", name),
+            Expr::List(_, unit) => format!("List {}", unit),
+            Expr::WithUnit(_, unit) => format!("WithUnit {}", unit),
+            Expr::Neg(_) => "Neg".to_string(),
+            Expr::Add(_, _) => "Add".to_string(),
+            Expr::Sub(_, _) => "Sub".to_string(),
+            Expr::Mul(_, _) => "Mul".to_string(),
+            Expr::Div(_, _) => "Div".to_string(),
+            Expr::Pow(_, _) => "Pow".to_string(),
+            Expr::Cmp(op, _, _) => format!("Cmp {}", op.symbol()),
+            Expr::Call(name, _) => format!("Call {}", name),
+            Expr::Assign { name, .. } => format!("Assign {}", name),
+            Expr::If { .. } => "If".to_string(),
+            Expr::Let { name, .. } => format!("Let {}", name),
+            Expr::Define { name, params, .. } => format!("Define {}({})", name, params.join(", ")),
+            Expr::To(_, None) => "To".to_string(),
+            Expr::To(_, Some(Target::Unit(unit))) => format!("To {}", unit),
+            Expr::To(_, Some(Target::Mixed(units))) => format!("To [{}]", units.join(", ")),
+            Expr::To(_, Some(Target::Each(units))) => format!("To {{{}}}", units.join(", ")),
+            Expr::To(_, Some(Target::Category(category))) => format!("To :{}", category),
+        }
+    }
+
+    /// Collects the units the expression refers to, leaving out names after a number that `is_var`
+    /// says are variables, like `x` in `5 x`
+    fn collect_units(&self, is_var: &dyn Fn(&str) -> bool, units: &mut Vec<&'src str>) {
+        match self {
+            Expr::NumOrVar(_, name) if is_var(name) => {}
+            Expr::Num(_, unit)
+            | Expr::NumOrVar(_, unit)
+            | Expr::List(_, unit)
+            | Expr::WithUnit(_, unit) => units.push(unit),
+            Expr::To(_, Some(Target::Unit(unit))) => units.push(unit),
+            Expr::To(_, Some(Target::Mixed(targets) | Target::Each(targets))) => {
+                units.extend(targets)
+            }
+            _ => {}
+        }
+        for child in self.children() {
+            child.collect_units(is_var, units);
+        }
+    }
+
+    fn children(&self) -> Vec<&Expr<'src>> {
+        match self {
+            Expr::Num(_, _)
+            | Expr::NumOrVar(_, _)
+            | Expr::Date(_)
+            | Expr::Percent(_)
+            | Expr::Prefixed(_, _)
+            | Expr::Var(_)
+            | Expr::Define { .. } => vec![],
+            Expr::List(items, _) | Expr::Call(_, items) => items.iter().collect(),
+            Expr::WithUnit(expr, _) | Expr::Neg(expr) | Expr::To(expr, _) => vec![expr],
+            Expr::Add(a, b)
+            | Expr::Sub(a, b)
+            | Expr::Mul(a, b)
+            | Expr::Div(a, b)
+            | Expr::Pow(a, b)
+            | Expr::Cmp(_, a, b) => vec![a, b],
+            Expr::Assign { rhs, .. } => vec![rhs],
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => vec![cond, then, otherwise],
+            Expr::Let { value, body, .. } => vec![value, body],
+        }
+    }
+
+    /// The first number without a unit outside of the contexts that are dimensionless anyway:
+    /// powers, elements of a list with a unit and sub-expressions a unit is applied to like `(3 + 4) m`
+    fn unitless_number(&self) -> Option<f64> {
+        match self {
+            Expr::Num(num, "") => Some(*num),
+            Expr::Pow(_, _) => None,
+            Expr::List(_, unit) | Expr::WithUnit(_, unit) if !unit.is_empty() => None,
+            _ => self.children().into_iter().find_map(Expr::unitless_number),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CmpOp {
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CmpOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            CmpOp::Lt => "<",
+            CmpOp::Gt => ">",
+            CmpOp::Le => "<=",
+            CmpOp::Ge => ">=",
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+        }
+    }
+
+    fn compare(self, a: f64, b: f64) -> bool {
+        match self {
+            CmpOp::Lt => a < b,
+            CmpOp::Gt => a > b,
+            CmpOp::Le => a <= b,
+            CmpOp::Ge => a >= b,
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Target<'src> {
+    Unit(&'src str),
+    Mixed(Vec<&'src str>), // e.g. `[h, min]`, the value is decomposed across the units
+    Each(Vec<&'src str>),  // e.g. `{kmph, mph}`, the value is converted to each of the units
+    Category(&'src str),   // e.g. `:speed`, the base unit of the category
+}
 
-    To(Box<Expr<'src>>, Option<&'src str>),
+pub struct Interpretor {
+    unit_definitions: UnitDefinitions,
+    unit_table: UnitTable,
+    vars: Vars,
+    rates: Option<HashMap<String, f64>>,
+    /// Consulted for the factors of currencies before their definitions, see `set_factor_provider`
+    factor_provider: Option<Box<dyn FactorProvider>>,
+    prefix_fallback: bool,
+    /// Recent results, the most recent last
+    history: Vec<(Value, String)>,
+    /// Unit given to number literals without one, see `set_assumed_unit`
+    assumed_unit: Option<String>,
+    /// How `eval_reader` formats results
+    format_options: FormatOptions,
+    /// Units results of a category are displayed in by category, see `set_preferred_units`
+    preferred_units: HashMap<String, String>,
+    /// Units of numbers written after a symbol like `$5` by symbol, see `set_prefix_symbols`
+    prefix_symbols: HashMap<String, String>,
+    /// Whether identical sub-expressions are evaluated once, see `set_memoization`
+    memoization: bool,
+    /// Variables that can't be assigned to, see `pin_var`
+    pinned: HashSet<String>,
+    /// Functions defined with `define_function` by name
+    functions: HashMap<String, UserFunction>,
+    /// Whether numbers without a unit are errors, see `set_strict_units`
+    strict_units: bool,
+    /// Whether results are given in the unit they were entered in, see `set_entry_unit_results`
+    entry_unit_results: bool,
 }
 
-pub struct Interpretor<'a> {
-    unit_table: UnitTable<'a>,
-    vars: HashMap<String, (f64, String)>,
+/// A function defined with `fn NAME(PARAMS) = BODY`
+struct UserFunction {
+    params: Vec<String>,
+    /// Parsed once when the function is defined, from source that lives as long as the program
+    body: Expr<'static>,
 }
 
-impl<'a> Interpretor<'a> {
-    pub fn new(unit_definitions: &'a UnitDefinitions) -> Result<Self, DefinitionError> {
+impl Interpretor {
+    pub fn new(unit_definitions: &UnitDefinitions) -> Result<Self, DefinitionError> {
         let unit_table = UnitTable::new(unit_definitions)?;
         Ok(Self {
+            unit_definitions: unit_definitions.clone(),
             unit_table,
             vars: HashMap::new(),
+            rates: None,
+            factor_provider: None,
+            prefix_fallback: false,
+            history: Vec::new(),
+            assumed_unit: None,
+            format_options: FormatOptions::default(),
+            preferred_units: HashMap::new(),
+            prefix_symbols: HashMap::new(),
+            pinned: HashSet::new(),
+            functions: HashMap::new(),
+            memoization: false,
+            strict_units: false,
+            entry_unit_results: false,
         })
     }
 
-    pub fn execute_command(&mut self, command: &str) -> Result<(f64, String), Vec<Error>> {
-        let parsed =
-            self.parser()
-                .parse(command)
-                .into_result()
-                .map_err(|errs: Vec<Simple<'_, char>>| {
-                    errs.into_iter()
-                        .map(|err| (err.span().into_range(), err.to_string()))
-                        .collect::<Vec<_>>()
-                })?;
-        let result = self
-            .eval_expr(&parsed)
-            .map_err(|err| vec![(0..command.len(), err)])?;
-
-        self.vars.insert("$".to_string(), result.clone());
-
-        Ok(result)
+    /// Like `execute_command`, but describes the unit of the result with its symbol and category
+    pub fn evaluate(&mut self, input: &str) -> Result<EvalResult, Vec<EvalError>> {
+        let (value, unit_key, requested_unit) = self
+            .execute(input, None)
+            .map_err(|errors| errors.into_iter().map(EvalError::from).collect::<Vec<_>>())?;
+        Ok(self.describe(value, unit_key, requested_unit))
     }
 
-    #[allow(clippy::let_and_return)]
-    fn parser<'src>(&self) -> impl Parser<'src, &'src str, Expr<'src>, Err<Simple<'src, char>>> {
-        let ident = text::ascii::ident().or(just("$")).padded();
-
-        let expr = recursive(|expr| {
-            let int = number::<RUST_LITERAL, &'_ str, f64, Err<Simple<'_, char>>>()
-                .then(ident.or_not())
-                .map(|(num, unit): (f64, Option<&str>)| {
-                    Expr::Num(num, unit.unwrap_or("")) // Default to empty unit if no unit is provided
-                });
-
-            let atom = int
-                .or(expr.delimited_by(just('('), just(')')))
-                .or(ident.map(Expr::Var))
-                .padded();
+    /// Evaluates `input` with `vars` layered over the interpretor's variables, e.g. to embed it,
+    /// without changing any state: assignments and the result are discarded.
+    /// The values of `vars` are in their units, which may be any known unit.
+    pub fn eval_with(
+        &self,
+        input: &str,
+        vars: &HashMap<String, (f64, String)>,
+    ) -> Result<EvalResult, Vec<EvalError>> {
+        let mut layered = self.vars.clone();
+        for (name, (value, unit)) in vars {
+            if !unit.is_empty() && !self.is_known_unit(unit) {
+                return Err(vec![EvalError {
+                    span: 0..0,
+                    message: format!("Unknown unit \"{}\" of variable \"{}\"", unit, name),
+                }]);
+            }
+            layered.insert(name.clone(), (Value::Number(*value), unit.clone()));
+        }
+        let parsed = self
+            .parse(input)
+            .map_err(|errors| errors.into_iter().map(EvalError::from).collect::<Vec<_>>())?;
+        self.check_strict_units(&parsed).map_err(|message| {
+            vec![EvalError {
+                span: 0..input.len(),
+                message,
+            }]
+        })?;
+        let mut scope = Scope {
+            vars: &mut layered,
+            trace: None,
+            memo: self.memoization.then(|| Memo::new(&parsed)),
+            calls: 0,
+        };
+        let (value, unit_key) = self.eval_expr(&parsed, &mut scope).map_err(|message| {
+            vec![EvalError {
+                span: 0..input.len(),
+                message,
+            }]
+        })?;
+        Ok(self.describe(value, unit_key, matches!(parsed, Expr::To(_, Some(_)))))
+    }
 
-            let op = |c| just(c).padded();
+    /// Like `evaluate`, with the factors of units replaced by `overrides` for this evaluation only,
+    /// e.g. `{"g": 1.62}` to compare a result under the gravity of the moon.
+    /// Each factor is relative to the base unit of the unit's category, units relative to an overridden one follow it.
+    pub fn eval_with_overrides(
+        &mut self,
+        input: &str,
+        overrides: &HashMap<String, f64>,
+    ) -> Result<EvalResult, Vec<EvalError>> {
+        let to_errors = |message: String| {
+            vec![EvalError {
+                span: 0..input.len(),
+                message,
+            }]
+        };
+        let mut unit_definitions = self.unit_definitions.clone();
+        for (unit, factor) in overrides {
+            let unit_def = unit_definitions
+                .categories
+                .values_mut()
+                .find_map(|units| units.get_mut(unit))
+                .ok_or_else(|| to_errors(format!("Unknown unit \"{}\" to override", unit)))?;
+            unit_def.factor = Factor::Number(*factor);
+            unit_def.relative_to = None;
+        }
+        let unit_table = build_unit_table(
+            &unit_definitions,
+            self.rates.as_ref(),
+            self.factor_provider.as_deref(),
+        )
+        .map_err(|e| to_errors(e.to_string()))?;
 
-            let unary = op('-')
-                .repeated()
-                .foldr(atom, |_op, rhs| Expr::Neg(Box::new(rhs)));
-
-            let product = unary.clone().foldl(
-                choice((
-                    op('*').to(Expr::Mul as fn(_, _) -> _),
-                    op('/').to(Expr::Div as fn(_, _) -> _),
-                ))
-                .then(unary)
-                .repeated(),
-                |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)),
-            );
+        let unit_table = std::mem::replace(&mut self.unit_table, unit_table);
+        let result = self.evaluate(input);
+        self.unit_table = unit_table;
+        result
+    }
 
-            let sum = product.clone().foldl(
-                choice((
-                    op('+').to(Expr::Add as fn(_, _) -> _),
-                    op('-').to(Expr::Sub as fn(_, _) -> _),
-                ))
-                .then(product)
-                .repeated(),
-                |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)),
-            );
+    /// The unit `input` results in and its category without changing any state, e.g. to validate it.
+    /// Both are empty for unitless expressions.
+    /// Only the units are followed, not the magnitudes, so e.g. `1 m / 0 sec` is still a speed.
+    pub fn dimension_of(&self, input: &str) -> Result<Dimension, Vec<EvalError>> {
+        let whole_input = |message| {
+            vec![EvalError {
+                span: 0..input.len(),
+                message,
+            }]
+        };
+        let parsed = self
+            .parse(input)
+            .map_err(|errors| errors.into_iter().map(EvalError::from).collect::<Vec<_>>())?;
+        self.check_strict_units(&parsed).map_err(whole_input)?;
+        let mut scope = UnitScope {
+            vars: &self.vars,
+            bound: Vec::new(),
+            calling: Vec::new(),
+        };
+        let unit_key = self
+            .unit_of(&parsed, &mut scope)
+            .and_then(|unit| {
+                unit.ok_or_else(|| "The unit depends on a recursive function call".to_string())
+            })
+            .map_err(whole_input)?;
+        Ok(Dimension {
+            category: self.category_of(&unit_key),
+            unit_key,
+        })
+    }
 
-            sum
-        });
+    /// Like `evaluate`, but fails unless the result can be converted to `expected`, e.g. to check
+    /// that an input is a length with `expect_unit(input, "m")`.
+    /// On failure the variables, including `$`, and the history are left as they were.
+    pub fn expect_unit(
+        &mut self,
+        input: &str,
+        expected: &str,
+    ) -> Result<EvalResult, Vec<EvalError>> {
+        let whole_input = |message| {
+            vec![EvalError {
+                span: 0..input.len(),
+                message,
+            }]
+        };
+        self.base_unit_of(expected).map_err(whole_input)?;
+        let (vars, history) = (self.vars.clone(), self.history.clone());
+        let result = self.evaluate(input)?;
+        if !self.are_compatible(&result.unit_key, expected) {
+            self.vars = vars;
+            self.history = history;
+            return Err(whole_input(format!(
+                "Expected a value convertible to \"{}\", got \"{}\"",
+                expected, result.unit_key
+            )));
+        }
+        Ok(result)
+    }
 
-        let assign = ident
-            .then_ignore(just('='))
-            .then(expr.clone())
-            .map(|(name, rhs)| Expr::Assign {
-                name,
-                rhs: Box::new(rhs),
-            });
+    /// Describes the unit of a result with its symbol and category
+    fn describe(&self, value: Value, unit_key: String, requested_unit: bool) -> EvalResult {
+        let symbol = self
+            .unit_table
+            .symbols()
+            .get(&unit_key)
+            .cloned()
+            .unwrap_or_else(|| unit_key.clone());
+        let category = self.category_of(&unit_key);
+        let warnings = precision_warnings(&value);
 
-        let to = expr
-            .then(just(">>").padded().ignore_then(ident).or_not())
-            .map(|(expr, unit)| Expr::To(Box::new(expr), unit));
+        EvalResult {
+            value,
+            unit_key,
+            symbol,
+            category,
+            warnings,
+            requested_unit,
+        }
+    }
 
-        assign.or(to).padded()
+    /// The category `unit_key` is defined in, empty if it's in none
+    fn category_of(&self, unit_key: &str) -> String {
+        self.unit_definitions
+            .categories
+            .iter()
+            .find(|(_, units)| units.contains_key(unit_key))
+            .map(|(category, _)| category.clone())
+            .unwrap_or_default()
     }
 
-    fn eval_expr<'src>(&mut self, expr: &Expr<'src>) -> Result<(f64, String), String> {
-        match expr {
-            Expr::Num(num, unit_str) => match self.unit_table.base_units_map().get(unit_str) {
-                Some(&(factor, base_unit)) => Ok(((*num * factor), base_unit.to_string())),
-                None => Err(format!("Unknown unit: \"{}\"", unit_str)),
-            },
-            Expr::Neg(a) => {
-                let (val, unit) = self.eval_expr(a)?;
-                Ok((-val, unit))
+    /// Like `evaluate`, but reports errors and warnings alike as diagnostics.
+    /// The result is `None` if there is any diagnostic of `Severity::Error`.
+    pub fn evaluate_with_diagnostics(
+        &mut self,
+        input: &str,
+    ) -> (Option<EvalResult>, Vec<Diagnostic>) {
+        match self.evaluate(input) {
+            Ok(result) => {
+                let diagnostics = result
+                    .warnings
+                    .iter()
+                    .map(|warning| Diagnostic {
+                        span: 0..input.len(),
+                        message: warning.clone(),
+                        severity: Severity::Warning,
+                    })
+                    .collect();
+                (Some(result), diagnostics)
             }
-            Expr::Add(a, b) | Expr::Sub(a, b) => {
-                let (val_a, unit_a) = self.eval_expr(a)?;
-                let (val_b, unit_b) = self.eval_expr(b)?;
+            Err(errors) => (None, errors.into_iter().map(Diagnostic::from).collect()),
+        }
+    }
 
-                let op = if matches!(expr, Expr::Add(_, _)) {
-                    "+"
-                } else {
-                    "-"
-                };
-                if unit_a != unit_b {
-                    return Err(format!("Cannot evaluate {:?} {} {:?}", unit_a, op, unit_b));
+    /// Evaluates every line of `reader` as it's read, writing one line to `writer` for each:
+    /// the formatted result or `Error: ` followed by the error messages.
+    /// Returns the number of lines that succeeded and the total number of lines.
+    pub fn eval_reader<R: BufRead, W: Write>(
+        &mut self,
+        reader: R,
+        mut writer: W,
+    ) -> std::io::Result<(usize, usize)> {
+        let mut succeeded = 0;
+        let mut total = 0;
+        for line in reader.lines() {
+            let line = line?;
+            total += 1;
+            match self.evaluate(&line) {
+                Ok(result) => {
+                    succeeded += 1;
+                    writeln!(
+                        writer,
+                        "{}",
+                        format_result(&result.value, &result.unit_key, &self.format_options)
+                    )?;
+                }
+                Err(errors) => {
+                    let messages = errors
+                        .into_iter()
+                        .map(|error| error.message)
+                        .collect::<Vec<_>>();
+                    writeln!(writer, "Error: {}", messages.join("; "))?;
                 }
-                let result = if op == "+" {
-                    val_a + val_b
-                } else {
-                    val_a - val_b
-                };
-
-                Ok((result, unit_b))
             }
-            Expr::Mul(a, b) | Expr::Div(a, b) => {
-                let (val_a, unit_a) = self.eval_expr(a)?;
-                let (val_b, unit_b) = self.eval_expr(b)?;
+        }
+        writer.flush()?;
+        Ok((succeeded, total))
+    }
 
-                let op = if matches!(expr, Expr::Mul(_, _)) {
-                    "*"
+    /// Finds units whose key, name or symbol contains `query` (case-insensitive), best matches first.
+    /// Returns `(key, name, symbol)` of each match.
+    pub fn search_units(&self, query: &str) -> Vec<(String, String, String)> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches = Vec::new();
+        for units in self.unit_definitions.categories.values() {
+            for (unit_key, unit_def) in units.iter() {
+                let key = unit_key.to_lowercase();
+                let symbol = unit_def.symbol.to_lowercase();
+                let name = unit_def.name.to_lowercase();
+                let score = if key == query || symbol == query {
+                    0
+                } else if key.starts_with(&query) || symbol.starts_with(&query) {
+                    1
+                } else if name.starts_with(&query) {
+                    2
+                } else if key.contains(&query) || symbol.contains(&query) || name.contains(&query) {
+                    3
                 } else {
-                    "/"
-                };
-                let new_unit = match self.unit_table.derived_units_map().get(&(
-                    unit_a.as_ref(),
-                    op,
-                    unit_b.as_ref(),
-                )) {
-                    Some(&new_unit) => new_unit.to_string(),
-                    None if unit_a.is_empty() => unit_b,
-                    None if unit_b.is_empty() => unit_a,
-                    None => {
-                        return Err(format!("Cannot evaluate {:?} {} {:?}", unit_a, op, unit_b));
-                    }
+                    continue;
                 };
-                if op == "*" {
-                    Ok((val_a * val_b, new_unit))
-                } else {
-                    Ok((val_a / val_b, new_unit))
-                }
+                matches.push((
+                    score,
+                    (
+                        unit_key.clone(),
+                        unit_def.name.clone(),
+                        unit_def.symbol.clone(),
+                    ),
+                ));
             }
-            Expr::Var(name) => {
-                if let Some(val) = self.vars.get(*name) {
-                    Ok((val.0, val.1.to_string()))
+        }
+        matches.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.0.cmp(&b.1.0)));
+
+        matches.into_iter().map(|(_, unit)| unit).collect()
+    }
+
+    /// The key of the unit `input` refers to by its key, its symbol, e.g. `m/s`, or its name (case-insensitive),
+    /// e.g. `Meter per second`, in that order of precedence. `None` if no unit matches.
+    pub fn canonical_unit(&self, input: &str) -> Option<&str> {
+        let input = input.trim();
+        // Symbols are optional, so an empty input would match units without one
+        if input.is_empty() {
+            return None;
+        }
+        let units = || self.unit_definitions.categories.values().flatten();
+        units()
+            .find(|(key, _)| key.as_str() == input)
+            .or_else(|| units().find(|(_, unit_def)| unit_def.symbol == input))
+            .or_else(|| units().find(|(_, unit_def)| unit_def.name.eq_ignore_ascii_case(input)))
+            .map(|(key, _)| key.as_str())
+    }
+
+    /// Whether values in units `a` and `b` can be converted into each other, `false` for unknown units
+    pub fn are_compatible(&self, a: &str, b: &str) -> bool {
+        self.check_compatible(a, b).unwrap_or(false)
+    }
+
+    /// Like `are_compatible`, but reports an unknown unit as an error rather than as incompatible
+    pub fn check_compatible(&self, a: &str, b: &str) -> Result<bool, String> {
+        let base_a = self.base_unit_of(a)?;
+        let base_b = self.base_unit_of(b)?;
+        Ok(base_a == base_b)
+    }
+
+    /// The number of `to` in one `from`, e.g. `1000` for `km` and `m`, without offsets.
+    /// Unknown units are reported with the units `search_units` finds for them as suggestions.
+    pub fn conversion_factor(&self, from: &str, to: &str) -> Result<f64, String> {
+        for unit in [from, to] {
+            if !self.is_known_unit(unit) {
+                let suggestions = self
+                    .search_units(unit)
+                    .into_iter()
+                    .take(3)
+                    .map(|(key, _, _)| key)
+                    .collect::<Vec<_>>();
+                return Err(if suggestions.is_empty() {
+                    format!("Unknown unit \"{}\"", unit)
                 } else {
-                    Err(format!("Cannot find variable \"{name}\" in scope"))
-                }
+                    format!(
+                        "Unknown unit \"{}\", did you mean {}?",
+                        unit,
+                        suggestions.join(", ")
+                    )
+                });
             }
-            Expr::Assign { name, rhs } => {
-                if *name == "$" {
-                    return Err("Cannot assign to reserved variable \"$\"".to_string());
+        }
+        if !self.check_compatible(from, to)? {
+            return Err(format!(
+                "Cannot convert \"{}\" to \"{}\", they measure different things",
+                from, to
+            ));
+        }
+        Ok(self
+            .exact_conversion(1.0, from, to)
+            .unwrap_or_else(|| self.unit_factor(from) / self.unit_factor(to)))
+    }
+
+    /// The defined unit a unit expression like `kg * m / s2` reduces to, e.g. `N`, to label a quantity
+    /// without evaluating one. The units are taken in the base units of their categories, so `km / hour`
+    /// reduces to `mps`. Every step must be derived by the definitions, e.g. `kg * m / s2` but not
+    /// `kg * m / s / s`. Dimensionless expressions like `Hz * s` reduce to the empty unit.
+    pub fn reduce_unit(&self, expr: &str) -> Result<String, String> {
+        self.unit_table
+            .resolve_unit_expr_with(expr, |unit| self.base_unit_of(unit).map(str::to_string))?
+            .ok_or_else(|| format!("No unit is derived as \"{}\"", expr.trim()))
+    }
+
+    /// Converts `value` in `unit` to the base unit of its category, returned with the base unit key
+    pub fn to_base(&self, value: f64, unit: &str) -> Result<(f64, String), EvalError> {
+        match self.apply_unit(Value::Number(value), unit) {
+            Ok((Value::Number(num), base_unit)) => Ok((num, base_unit)),
+            Ok((value, _)) => unreachable!("a number stays a number, found {:?}", value),
+            Err(message) => Err(EvalError {
+                span: 0..0,
+                message,
+            }),
+        }
+    }
+
+    /// Converts `value` in the base unit of `unit`'s category to `unit`, the inverse of `to_base`
+    pub fn from_base(&self, value: f64, unit: &str) -> Result<(f64, String), EvalError> {
+        let factor = self
+            .base_unit_of(unit)
+            .and_then(|base_unit| self.target_factor(base_unit, unit))
+            .map_err(|message| EvalError {
+                span: 0..0,
+                message,
+            })?;
+        let offset = self.unit_table.offset(unit);
+        Ok(((value - offset) / factor, unit.to_string()))
+    }
+
+    /// Converts a result in `unit` to the unit its definition is displayed as (see `display_as`),
+    /// or else to the preferred unit of its category. Other results, and mixed values, are returned unchanged.
+    /// Results with `requested_unit` set are meant to be shown as they are rather than passed here.
+    pub fn display_value(&self, value: Value, unit: &str) -> (Value, String) {
+        let display_unit = self.unit_table.display_unit(unit).or_else(|| {
+            let (category, _) = self
+                .unit_definitions
+                .categories
+                .iter()
+                .find(|(_, units)| units.contains_key(unit))?;
+            self.preferred_units.get(category).map(String::as_str)
+        });
+        let Some(display_unit) = display_unit.filter(|display_unit| *display_unit != unit) else {
+            return (value, unit.to_string());
+        };
+        let base_units_map = self.unit_table.base_units_map();
+        let (Some((from_factor, _)), Some((to_factor, _))) =
+            (base_units_map.get(unit), base_units_map.get(display_unit))
+        else {
+            return (value, unit.to_string());
+        };
+        let (from_factor, to_factor) = (*from_factor, *to_factor);
+        let (from_offset, to_offset) = (
+            self.unit_table.offset(unit),
+            self.unit_table.offset(display_unit),
+        );
+        match value
+            .clone()
+            .map(|num| (num * from_factor + from_offset - to_offset) / to_factor)
+        {
+            Ok(converted) => (converted, display_unit.to_string()),
+            Err(_) => (value, unit.to_string()),
+        }
+    }
+
+    fn base_unit_of<'u>(&'u self, unit: &'u str) -> Result<&'u str, String> {
+        match self.lookup_unit(unit) {
+            Some((_, base_unit)) => Ok(base_unit),
+            None if self.unit_table.contains(unit) => Ok(unit),
+            None => Err(format!("Unknown unit: \"{}\"", unit)),
+        }
+    }
+
+    /// Every operation a value in `unit` can take part in as the left operand to produce a derived unit,
+    /// as `(op, other, result)` sorted by operator and other unit, e.g. `("*", "m", "m2")` for `m`
+    pub fn operations_for(&self, unit: &str) -> Vec<(String, String, String)> {
+        let mut operations = self
+            .unit_table
+            .derived_units_map()
+            .iter()
+            .filter(|((unit_a, _, _), _)| unit_a == unit)
+            .map(|((_, op, other), result)| (op.clone(), other.clone(), result.clone()))
+            .collect::<Vec<_>>();
+        operations.sort();
+        operations
+    }
+
+    /// The units of `category` and the factors between them, where row `i` column `j` is the number of
+    /// `units[j]` in one `units[i]`. Offsets are not part of the factors.
+    pub fn conversion_matrix(
+        &self,
+        category: &str,
+    ) -> Result<(Vec<String>, Vec<Vec<f64>>), String> {
+        let units = self
+            .unit_definitions
+            .categories
+            .get(category)
+            .ok_or_else(|| format!("Unknown category \"{}\"", category))?;
+        let factors = units
+            .keys()
+            .map(|unit| {
+                self.unit_table
+                    .base_units_map()
+                    .get(unit)
+                    .map(|(factor, _)| *factor)
+                    .ok_or_else(|| format!("Unknown unit: \"{}\"", unit))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let matrix = factors
+            .iter()
+            .map(|from| factors.iter().map(|to| from / to).collect())
+            .collect();
+        Ok((units.keys().cloned().collect(), matrix))
+    }
+
+    /// Significant figures of the units which define `sig_figs`, keyed by unit key
+    pub fn unit_sig_figs(&self) -> HashMap<String, usize> {
+        self.unit_definitions
+            .categories
+            .values()
+            .flat_map(|units| units.iter())
+            .filter_map(|(unit, unit_def)| Some((unit.clone(), unit_def.sig_figs?.get())))
+            .collect()
+    }
+
+    /// The factor and base unit each unit is normalized with, sorted by unit key, e.g. `km -> (1000, m)`.
+    /// The empty unit of dimensionless values is left out.
+    pub fn base_units(&self) -> BTreeMap<String, (f64, String)> {
+        self.unit_table
+            .base_units_map()
+            .iter()
+            .filter(|(unit, _)| !unit.is_empty())
+            .map(|(unit, base)| (unit.clone(), base.clone()))
+            .collect()
+    }
+
+    /// Warnings about loaded units that can never take part in a conversion, e.g. the only unit of a category
+    /// that no derived unit refers to
+    pub fn lint_units(&self) -> Vec<String> {
+        self.unit_table.lint()
+    }
+
+    /// Categories whose base unit is `base_unit` or derived the same way, e.g. both `energy` and `torque`
+    /// for `J` when their base units `J` and `Nm` are both derived as `N * m`, so tools can offer
+    /// the candidates of an ambiguous result
+    pub fn categories_for_base_unit(&self, base_unit: &str) -> Vec<String> {
+        // The unit a derived unit's expression resolves to, which units derived the same way share
+        let dimension = |unit: &str| {
+            self.unit_definitions
+                .categories
+                .values()
+                .find_map(|units| units.get(unit)?.derived.as_deref())
+                .and_then(|derived| self.unit_table.resolve_unit_expr(derived))
+                .unwrap_or_else(|| unit.to_string())
+        };
+        let dimension_of_base = dimension(base_unit);
+        self.unit_definitions
+            .categories
+            .iter()
+            .filter(|(_, units)| {
+                units.first().is_some_and(|(unit, _)| {
+                    unit == base_unit || dimension(unit) == dimension_of_base
+                })
+            })
+            .map(|(category, _)| category.clone())
+            .collect()
+    }
+
+    /// Display symbols of all units keyed by unit key, exponents of derived units use Unicode superscripts
+    pub fn unit_symbols(&self) -> HashMap<String, String> {
+        self.unit_table.symbols().clone()
+    }
+
+    /// Labels of the units derived by a division spelled out from the symbols of their operands,
+    /// e.g. `m/s` for a unit defined as `m / sec`, regardless of its own symbol
+    pub fn quotient_symbols(&self) -> HashMap<String, String> {
+        self.unit_table.quotient_symbols().clone()
+    }
+
+    /// The unit definitions currently in use, e.g. to save them after a `reload`
+    pub fn unit_definitions(&self) -> &UnitDefinitions {
+        &self.unit_definitions
+    }
+
+    /// The assigned variables in their base units, e.g. to persist a session and `restore_vars` it later
+    pub fn stored_vars(&self) -> BTreeMap<String, StoredVar> {
+        self.vars
+            .iter()
+            .filter(|(name, _)| !is_reserved_name(name))
+            .map(|(name, (val, unit))| {
+                let (value, base_unit) = self
+                    .normalize(val.clone(), unit)
+                    .unwrap_or_else(|_| (val.clone(), unit.clone()));
+                (name.clone(), StoredVar { value, base_unit })
+            })
+            .collect()
+    }
+
+    /// Assigns variables stored by `stored_vars`, reattaching their units to the current definitions.
+    /// A unit that is no longer the base unit of its category is converted to the current one.
+    /// Returns the names of the variables dropped because their units no longer exist or they're pinned.
+    pub fn restore_vars(&mut self, vars: BTreeMap<String, StoredVar>) -> Vec<String> {
+        let mut dropped = Vec::new();
+        for (name, StoredVar { value, base_unit }) in vars {
+            let restored = if self.has_known_units(&value, &base_unit) {
+                self.normalize(value, &base_unit).ok()
+            } else {
+                None
+            };
+            match restored {
+                Some(var) if !is_reserved_name(&name) && !self.pinned.contains(&name) => {
+                    self.vars.insert(name, var);
                 }
-                let rhs = self.eval_expr(rhs)?;
-                self.vars.insert(name.to_string(), rhs.clone());
-                Ok(rhs)
+                _ => dropped.push(name),
             }
-            Expr::To(expr, unit) => {
-                let (val, cur_unit) = self.eval_expr(expr)?;
-                if let Some(unit_str) = unit {
-                    if let Some(&(factor, base_unit)) =
-                        self.unit_table.base_units_map().get(unit_str)
-                    {
-                        if cur_unit != base_unit {
-                            Err(format!("Cannot convert to unit \"{}\"", *unit_str))
-                        } else {
-                            Ok((val / factor, unit_str.to_string()))
-                        }
-                    } else {
-                        Err(format!("Unknown unit {}", unit_str))
-                    }
-                } else {
-                    Ok((val, cur_unit))
-                }
+        }
+        dropped
+    }
+
+    /// Rebuilds the unit table from `unit_definitions`, keeping the variables whose units still exist.
+    /// Returns the names of the dropped variables, the interpretor is left untouched on error.
+    pub fn reload(
+        &mut self,
+        unit_definitions: UnitDefinitions,
+    ) -> Result<Vec<String>, DefinitionError> {
+        self.unit_table = build_unit_table(
+            &unit_definitions,
+            self.rates.as_ref(),
+            self.factor_provider.as_deref(),
+        )?;
+        self.unit_definitions = unit_definitions;
+
+        let mut dropped = Vec::new();
+        let mut vars = std::mem::take(&mut self.vars);
+        vars.retain(|name, (val, unit)| {
+            let retained = self.has_known_units(val, unit);
+            if !retained {
+                dropped.push(name.clone());
+            }
+            retained
+        });
+        self.vars = vars;
+        let mut history = std::mem::take(&mut self.history);
+        history.retain(|(val, unit)| self.has_known_units(val, unit));
+        self.history = history;
+        if self
+            .assumed_unit
+            .as_ref()
+            .is_some_and(|unit| !self.is_known_unit(unit))
+        {
+            self.assumed_unit = None;
+        }
+        let categories = &self.unit_definitions.categories;
+        self.preferred_units.retain(|category, unit| {
+            categories
+                .get(category)
+                .is_some_and(|units| units.contains_key(unit))
+        });
+        let mut prefix_symbols = std::mem::take(&mut self.prefix_symbols);
+        prefix_symbols.retain(|_, unit| self.is_known_unit(unit));
+        self.prefix_symbols = prefix_symbols;
+        dropped.sort();
+
+        Ok(dropped)
+    }
+
+    /// Replaces the factors of the units of the `currency` category by exchange rates,
+    /// each rate being the value of one unit in the category's base currency.
+    /// Once rates are set, converting a currency without a rate is an error. Rates are kept across `reload`.
+    pub fn set_rates(&mut self, rates: HashMap<String, f64>) -> Result<(), DefinitionError> {
+        self.unit_table = build_unit_table(
+            &self.unit_definitions,
+            Some(&rates),
+            self.factor_provider.as_deref(),
+        )?;
+        self.rates = Some(rates);
+        Ok(())
+    }
+
+    /// Takes the factors of the units of the `currency` category from `provider` where it has one,
+    /// falling back to the factors of their definitions. Rates of `set_rates` take precedence.
+    /// The provider is queried now and on every `reload`, and kept across `reset`.
+    pub fn set_factor_provider(
+        &mut self,
+        provider: Box<dyn FactorProvider>,
+    ) -> Result<(), DefinitionError> {
+        self.unit_table = build_unit_table(
+            &self.unit_definitions,
+            self.rates.as_ref(),
+            Some(provider.as_ref()),
+        )?;
+        self.factor_provider = Some(provider);
+        Ok(())
+    }
+
+    /// Resolves undefined units made of an SI prefix and a defined unit, e.g. `km` when only `m` is defined.
+    /// Defined units always take precedence, so an explicitly defined `km` keeps its own factor.
+    pub fn set_prefix_fallback(&mut self, enabled: bool) {
+        self.prefix_fallback = enabled;
+    }
+
+    /// Evaluates identical sub-expressions of an input once and reuses the result, e.g. for large
+    /// generated expressions. Results are forgotten whenever a variable is assigned or bound by `let`,
+    /// and a `trace` always evaluates everything to record every step.
+    pub fn set_memoization(&mut self, enabled: bool) {
+        self.memoization = enabled;
+    }
+
+    /// Makes numbers without a unit errors, e.g. `x = 5`, for inputs where a missing unit is a mistake.
+    /// Numbers are still allowed where they're dimensionless anyway, in powers like `2^10`, in lists with a unit
+    /// like `[1, 2] m` and before a unit like `(3 + 4) m`. Numbers get the assumed unit instead if one is set.
+    pub fn set_strict_units(&mut self, enabled: bool) {
+        self.strict_units = enabled;
+    }
+
+    /// Gives results without a `>>` conversion in the unit of the leftmost number with a unit instead of
+    /// the base unit, e.g. `5 cm + 3 cm` as `8 cm` rather than `0.08 m`. Results that can't be converted
+    /// to that unit, e.g. of `5 cm * 3 cm`, stay in the base unit.
+    pub fn set_entry_unit_results(&mut self, enabled: bool) {
+        self.entry_unit_results = enabled;
+    }
+
+    /// Displays results of a category in the unit given for it by `display_value`, e.g. `degC` for `temperature`,
+    /// however they were entered. Replaces the preferred units set before.
+    pub fn set_preferred_units(
+        &mut self,
+        preferred_units: HashMap<String, String>,
+    ) -> Result<(), String> {
+        for (category, unit) in &preferred_units {
+            let units = self
+                .unit_definitions
+                .categories
+                .get(category)
+                .ok_or_else(|| format!("Unknown category \"{}\"", category))?;
+            if !units.contains_key(unit) {
+                return Err(format!(
+                    "Unit \"{}\" is not in category \"{}\"",
+                    unit, category
+                ));
             }
         }
+        self.preferred_units = preferred_units;
+        Ok(())
+    }
+
+    /// Reads numbers written right after one of the symbols as values in the symbol's unit,
+    /// e.g. `$5` as `5 USD` with `"$" = "USD"`. Without symbols (the default) nothing is read this way.
+    /// Taking precedence, a `$` symbol makes `$5` five dollars rather than the fifth most recent result,
+    /// which is still reachable as `$`, `ans` or through a variable.
+    pub fn set_prefix_symbols(
+        &mut self,
+        prefix_symbols: HashMap<String, String>,
+    ) -> Result<(), String> {
+        for (symbol, unit) in &prefix_symbols {
+            if symbol.is_empty()
+                || symbol.contains(|c: char| c.is_whitespace() || c.is_ascii_digit())
+            {
+                return Err(format!("Invalid prefix symbol \"{}\"", symbol));
+            }
+            if !self.is_known_unit(unit) {
+                return Err(format!("Unknown unit: \"{}\"", unit));
+            }
+        }
+        self.prefix_symbols = prefix_symbols;
+        Ok(())
+    }
+
+    /// How `eval_reader` formats results, e.g. their precision and rounding mode
+    pub fn set_format_options(&mut self, options: FormatOptions) {
+        self.format_options = options;
+    }
+
+    /// Makes the variable `name` read-only, assigning to it fails like assigning to `$` until `unpin_var`
+    pub fn pin_var(&mut self, name: &str) -> Result<(), String> {
+        if !self.vars.contains_key(name) {
+            return Err(format!("Cannot find variable \"{}\" in scope", name));
+        }
+        self.pinned.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Allows assigning to a variable pinned with `pin_var` again
+    pub fn unpin_var(&mut self, name: &str) -> Result<(), String> {
+        if !self.pinned.remove(name) {
+            return Err(format!("Variable \"{}\" is not pinned", name));
+        }
+        Ok(())
+    }
+
+    /// Treats number literals without a unit as values in `unit`, e.g. `5 + 3` gives `8 m` with `m`
+    /// assumed. `None` makes them dimensionless again.
+    pub fn set_assumed_unit(&mut self, unit: Option<&str>) -> Result<(), String> {
+        if let Some(unit) = unit
+            && !self.is_known_unit(unit)
+        {
+            return Err(format!("Unknown unit: \"{}\"", unit));
+        }
+        self.assumed_unit = unit.map(str::to_string);
+        Ok(())
+    }
+
+    pub fn assumed_unit(&self) -> Option<&str> {
+        self.assumed_unit.as_deref()
+    }
+
+    /// Drops all runtime state, variables (including `$`), recent results and exchange rates,
+    /// as if the interpretor was just created from its unit definitions
+    pub fn reset(&mut self) {
+        self.vars.clear();
+        self.pinned.clear();
+        self.functions.clear();
+        self.history.clear();
+        self.assumed_unit = None;
+        if self.rates.take().is_some() {
+            // The definitions and the provider's factors were already validated when they were loaded
+            self.unit_table = build_unit_table(
+                &self.unit_definitions,
+                None,
+                self.factor_provider.as_deref(),
+            )
+            .expect("loaded unit definitions should stay valid");
+        }
+    }
+
+    pub fn execute_command(&mut self, command: &str) -> Result<(Value, String), Vec<Error>> {
+        self.execute(command, None)
+            .map(|(value, unit, _)| (value, unit))
+    }
+
+    /// Like `evaluate`, but also returns every unit conversion applied in the evaluation, in order,
+    /// e.g. for tools to show how a result came about
+    pub fn trace(&mut self, input: &str) -> Result<(EvalResult, Vec<TraceStep>), Vec<EvalError>> {
+        let mut steps = Vec::new();
+        let (value, unit_key, requested_unit) = self
+            .execute(input, Some(&mut steps))
+            .map_err(|errors| errors.into_iter().map(EvalError::from).collect::<Vec<_>>())?;
+        Ok((self.describe(value, unit_key, requested_unit), steps))
+    }
+
+    fn execute(
+        &mut self,
+        command: &str,
+        trace: Option<&mut Vec<TraceStep>>,
+    ) -> Result<(Value, String, bool), Vec<Error>> {
+        let parsed = self.parse(command)?;
+        // A definition has no result, it's not recorded as one
+        if let Expr::Define { .. } = parsed {
+            self.define_function(command)?;
+            return Ok((Value::List(Vec::new()), String::new(), false));
+        }
+        self.check_strict_units(&parsed)
+            .map_err(|err| vec![(0..command.len(), err)])?;
+        let mut vars = std::mem::take(&mut self.vars);
+        let memo = (self.memoization && trace.is_none()).then(|| Memo::new(&parsed));
+        let result = self.eval_expr(
+            &parsed,
+            &mut Scope {
+                vars: &mut vars,
+                trace,
+                memo,
+                calls: 0,
+            },
+        );
+        self.vars = vars;
+        let result = result.map_err(|err| vec![(0..command.len(), err)])?;
+        // Results in the unit they were entered in are kept in it like results converted with `>>`
+        let entry_result = self
+            .entry_unit_results
+            .then(|| self.in_entry_unit(&parsed, &result))
+            .flatten();
+        let requested_unit = entry_result.is_some() || matches!(parsed, Expr::To(_, Some(_)));
+        let result = entry_result.unwrap_or(result);
+
+        self.vars.insert("$".to_string(), result.clone());
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(result.clone());
+
+        let (value, unit) = result;
+        Ok((value, unit, requested_unit))
+    }
+
+    /// Recent results, the most recent first, so the `n`th one can be referred to as `$n`.
+    /// Only the last 100 results are kept.
+    pub fn stack(&self) -> impl Iterator<Item = &(Value, String)> {
+        self.history.iter().rev()
+    }
+
+    /// Checks that `command` is syntactically valid without evaluating it,
+    /// so neither the variables nor the units it refers to need to exist.
+    pub fn check_syntax(&self, command: &str) -> Result<(), Vec<Error>> {
+        self.parse(command).map(|_| ())
+    }
+
+    /// Whether `command` assigns to a variable, like `x = 2 m`, without evaluating it
+    pub fn is_assignment(&self, command: &str) -> bool {
+        matches!(self.parse(command), Ok(Expr::Assign { .. }))
+    }
+
+    /// Whether `command` defines a function, like `fn double(x) = 2 * x`, without defining it
+    pub fn is_function_definition(&self, command: &str) -> bool {
+        matches!(self.parse(command), Ok(Expr::Define { .. }))
+    }
+
+    /// Defines a function from `fn NAME(PARAMS) = BODY`, e.g. `fn kinetic(m, v) = 0.5 * m * v^2`,
+    /// which can then be called like `kinetic(2 kg, 3 mps)`. Returns the name of the function.
+    /// The arguments are the only variables the body sees, so it means the same wherever it's called.
+    /// Redefining a function replaces it.
+    pub fn define_function(&mut self, definition: &str) -> Result<String, Vec<Error>> {
+        let span = 0..definition.len();
+        let Expr::Define { name, params, .. } = self.parse(definition)? else {
+            return Err(vec![(
+                span,
+                "Expected a function definition like `fn double(x) = 2 * x`".to_string(),
+            )]);
+        };
+        if BUILTIN_FUNCTIONS.contains(&name) {
+            return Err(vec![(
+                span,
+                format!("Cannot redefine built-in function \"{}\"", name),
+            )]);
+        }
+        for (i, param) in params.iter().enumerate() {
+            if is_reserved_name(param) {
+                return Err(vec![(
+                    span,
+                    format!("Cannot use reserved variable \"{}\" as a parameter", param),
+                )]);
+            }
+            if params[..i].contains(param) {
+                return Err(vec![(span, format!("Parameter \"{}\" is repeated", param))]);
+            }
+        }
+
+        // The parsed body borrows from its source, which is leaked to be kept along with it.
+        // Definitions are short and few, so this is little memory.
+        let source: &'static str = Box::leak(definition.into());
+        let Ok(Expr::Define { body, .. }) = self.parse(source) else {
+            unreachable!("the definition was parsed above");
+        };
+        self.functions.insert(
+            name.to_string(),
+            UserFunction {
+                params: params.iter().map(|param| param.to_string()).collect(),
+                body: *body,
+            },
+        );
+        Ok(name.to_string())
+    }
+
+    /// Checks that every unit `command` refers to is defined without evaluating it,
+    /// reporting all unknown units at once located by their spans
+    pub fn check_units(&self, command: &str) -> Result<(), Vec<Error>> {
+        let mut units = Vec::new();
+        // As when evaluating, a name after a number is a variable if it's one and not a unit
+        let is_var = |name: &str| self.vars.contains_key(name);
+        self.parse(command)?.collect_units(&is_var, &mut units);
+        let mut errors = units
+            .into_iter()
+            .filter(|unit| {
+                !unit.is_empty()
+                    && !self
+                        .resolve_target(unit)
+                        .is_ok_and(|unit| self.is_known_unit(&unit))
+            })
+            .map(|unit| {
+                // Units are slices of `command`, so their offset in it is their position
+                let start = unit.as_ptr() as usize - command.as_ptr() as usize;
+                (
+                    start..start + unit.len(),
+                    format!("Unknown unit: \"{}\"", unit),
+                )
+            })
+            .collect::<Vec<_>>();
+        // Targets are collected before the expression they convert
+        errors.sort_by_key(|(span, _)| span.start);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses `command` without evaluating it and returns its syntax tree as indented text, e.g. for `1 + 2`:
+    /// ```text
+    /// To
+    ///   Add
+    ///     Num 1
+    ///     Num 2
+    /// ```
+    pub fn debug_parse(&self, command: &str) -> Result<String, Vec<Error>> {
+        let mut tree = String::new();
+        self.parse(command)?.write_tree(0, &mut tree);
+        Ok(tree)
+    }
+
+    fn parse<'src>(&self, command: &'src str) -> Result<Expr<'src>, Vec<Error>> {
+        self.parser()
+            .parse(command)
+            .into_result()
+            .map_err(|errs: Vec<ParseError<'_>>| {
+                errs.into_iter()
+                    .map(|err| (err.error.span().into_range(), err.to_string()))
+                    .collect::<Vec<_>>()
+            })
+    }
+
+    #[allow(clippy::let_and_return)]
+    fn parser<'src>(&self) -> impl Parser<'src, &'src str, Expr<'src>, Err<ParseError<'src>>> {
+        // Unicode identifiers, so units like `Ω` or `°C` can be referenced
+        let ident_char = |is_valid: fn(char) -> bool| {
+            any().try_map(move |c: char, span| {
+                if is_valid(c) {
+                    Ok(c)
+                } else {
+                    Err(Simple::new(Some(c.into()), span).into())
+                }
+            })
+        };
+        let ident = ident_char(is_ident_start)
+            .then(ident_char(is_ident_continue).repeated())
+            .to_slice()
+            .filter(|ident: &&str| !KEYWORDS.contains(ident))
+            // `$` is the last result, `$2` the one before and so on
+            .or(just('$').then(text::digits(10).or_not()).to_slice())
+            .padded();
+
+        // A unit, or units combined like `m/s` that resolve to a derived unit
+        let unit_expr = ident
+            .separated_by(one_of("*/").padded())
+            .at_least(1)
+            .to_slice()
+            .map(str::trim);
+        let target = unit_expr
+            .map(Target::Unit)
+            .or(ident
+                .separated_by(just(','))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .delimited_by(just('['), just(']'))
+                .map(Target::Mixed))
+            .or(unit_expr
+                .padded()
+                .separated_by(just(','))
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .delimited_by(just('{'), just('}'))
+                .map(Target::Each))
+            .or(just(':').ignore_then(ident).map(Target::Category));
+        let conversion = just(">>").padded().ignore_then(target.padded());
+
+        let literal = number::<RUST_LITERAL, &'_ str, f64, Err<ParseError<'_>>>()
+            // A literal out of the range of `f64` would silently become infinity, reject it instead
+            .validate(|num: f64, e, emitter| {
+                if !num.is_finite() {
+                    let literal: &str = e.slice();
+                    emitter.emit(ParseError {
+                        error: Simple::new(literal.chars().next().map(Into::into), e.span()),
+                        message: Some("Number out of range"),
+                    });
+                }
+                num
+            })
+            .boxed();
+
+        // Numbers right after a symbol like `$5`, tried before `$N` is taken for a recent result
+        let prefix_symbols: HashSet<String> = self.prefix_symbols.keys().cloned().collect();
+        let prefixed = any()
+            .filter(|c: &char| !c.is_whitespace() && !c.is_ascii_digit())
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .filter(move |symbol: &&str| prefix_symbols.contains(*symbol))
+            .then(literal.clone())
+            .map(|(symbol, num)| Expr::Prefixed(num, symbol));
+
+        let expr = recursive(|expr| {
+            let percent = literal
+                .clone()
+                .then_ignore(just('%').padded())
+                .map(Expr::Percent);
+            let int = literal
+                // A unit in brackets like `5 [s]` is never taken for a variable
+                .then(
+                    ident
+                        .delimited_by(just('[').padded(), just(']'))
+                        .map(|unit| (unit, true))
+                        .or(ident.map(|unit| (unit, false)))
+                        .or_not(),
+                )
+                .map(|(num, unit): (f64, Option<(&str, bool)>)| match unit {
+                    Some((unit, true)) => Expr::Num(num, unit),
+                    Some((name, false)) => Expr::NumOrVar(num, name),
+                    None => Expr::Num(num, ""), // Default to empty unit if no unit is provided
+                });
+
+            // Dates like `2024-01-01` or `2024-01-01T12:30:00`, which would otherwise be subtractions
+            let digits = |count| any().filter(char::is_ascii_digit).repeated().exactly(count);
+            let date = digits(4)
+                .then(just('-'))
+                .then(digits(2))
+                .then(just('-'))
+                .then(digits(2))
+                .then(
+                    just('T')
+                        .then(digits(2))
+                        .then(just(':'))
+                        .then(digits(2))
+                        .then(just(':').then(digits(2)).or_not())
+                        .or_not(),
+                )
+                .to_slice()
+                .validate(|literal: &str, e, emitter| {
+                    parse_datetime(literal).unwrap_or_else(|| {
+                        emitter.emit(
+                            Simple::new(literal.chars().next().map(Into::into), e.span()).into(),
+                        );
+                        0.0
+                    })
+                })
+                .map(Expr::Date);
+
+            let list = expr
+                .clone()
+                .separated_by(just(','))
+                .collect::<Vec<_>>()
+                .delimited_by(just('['), just(']'))
+                .then(ident.or_not())
+                .map(|(items, unit): (Vec<Expr>, Option<&str>)| {
+                    Expr::List(items, unit.unwrap_or(""))
+                });
+
+            let call = ident
+                .then(
+                    expr.clone()
+                        .separated_by(just(','))
+                        .collect::<Vec<_>>()
+                        .delimited_by(just('('), just(')')),
+                )
+                .map(|(name, args)| Expr::Call(name, args));
+
+            let atom = prefixed
+                .clone()
+                .or(date)
+                .or(percent)
+                .or(int)
+                .or(list)
+                .or(call)
+                // `>>` binds loosest, so converting a sub-expression needs parentheses: `(1 m >> cm) + 5 cm`
+                .or(expr
+                    .clone()
+                    .foldl(conversion.repeated(), |expr, target| {
+                        Expr::To(Box::new(expr), Some(target))
+                    })
+                    .delimited_by(just('('), just(')'))
+                    .or(ident.map(Expr::Var))
+                    .then(ident.or_not())
+                    .map(|(expr, unit): (Expr, Option<&str>)| match unit {
+                        Some(unit) => Expr::WithUnit(Box::new(expr), unit),
+                        None => expr,
+                    }))
+                .padded()
+                .boxed();
+
+            let op = |c| just(c).padded();
+
+            // Binds tighter than signs and is right associative, so `-2^2` is `-4` and `2^3^2` is `2^9`
+            let power = recursive(|power| {
+                atom.clone()
+                    .then(op('^').ignore_then(op('-').or_not()).then(power).or_not())
+                    .map(|(base, exponent)| match exponent {
+                        Some((Some(_), exponent)) => {
+                            Expr::Pow(Box::new(base), Box::new(Expr::Neg(Box::new(exponent))))
+                        }
+                        Some((None, exponent)) => Expr::Pow(Box::new(base), Box::new(exponent)),
+                        None => base,
+                    })
+            })
+            .boxed();
+
+            // Unary plus is a no-op, so signs can be mixed like in `+-5`
+            let unary = op('-')
+                .or(op('+'))
+                .repeated()
+                .foldr(power, |op, rhs| match (op, rhs) {
+                    // `-40 degC` is a negative reading, not the negation of 40 degC in the base unit,
+                    // which differ for units with an offset
+                    ('-', Expr::Num(num, unit)) => Expr::Num(-num, unit),
+                    ('-', Expr::NumOrVar(num, name)) => Expr::NumOrVar(-num, name),
+                    ('-', rhs) => Expr::Neg(Box::new(rhs)),
+                    (_, rhs) => rhs,
+                });
+
+            let product = unary
+                .clone()
+                .foldl(
+                    choice((
+                        op('*').to(Expr::Mul as fn(_, _) -> _),
+                        op('/').to(Expr::Div as fn(_, _) -> _),
+                    ))
+                    .then(unary)
+                    .repeated(),
+                    |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)),
+                )
+                .boxed();
+
+            let sum = product
+                .clone()
+                .foldl(
+                    choice((
+                        op('+').to(Expr::Add as fn(_, _) -> _),
+                        op('-').to(Expr::Sub as fn(_, _) -> _),
+                    ))
+                    .then(product)
+                    .repeated(),
+                    |lhs, (op, rhs)| op(Box::new(lhs), Box::new(rhs)),
+                )
+                .boxed();
+
+            // Comparisons don't chain, and a lone `>` must not swallow the `>>` of a conversion
+            let cmp_op = choice((
+                just("<=").to(CmpOp::Le),
+                just(">=").to(CmpOp::Ge),
+                just("==").to(CmpOp::Eq),
+                just("!=").to(CmpOp::Ne),
+                just("<").to(CmpOp::Lt),
+                just(">").then_ignore(just('>').not()).to(CmpOp::Gt),
+            ))
+            .padded();
+
+            let cmp = sum
+                .clone()
+                .then(cmp_op.then(sum).or_not())
+                .map(|(lhs, rhs)| match rhs {
+                    Some((op, rhs)) => Expr::Cmp(op, Box::new(lhs), Box::new(rhs)),
+                    None => lhs,
+                });
+
+            // COND ? A : B, right associative so `a ? b : c ? d : e` nests in the last branch
+            let ternary = cmp
+                .then(
+                    op('?')
+                        .ignore_then(expr.clone())
+                        .then_ignore(op(':'))
+                        .then(expr.clone())
+                        .or_not(),
+                )
+                .map(|(cond, branches)| match branches {
+                    Some((then, otherwise)) => Expr::If {
+                        cond: Box::new(cond),
+                        then: Box::new(then),
+                        otherwise: Box::new(otherwise),
+                    },
+                    None => cond,
+                });
+
+            // let NAME = EXPR in BODY, the binding is only visible in BODY
+            let let_in = text::ascii::keyword("let")
+                .padded()
+                .ignore_then(ident)
+                .then_ignore(just('='))
+                .then(expr.clone())
+                .then_ignore(text::ascii::keyword("in").padded())
+                .then(expr.clone())
+                .map(|((name, value), body)| Expr::Let {
+                    name,
+                    value: Box::new(value),
+                    body: Box::new(body),
+                });
+
+            let_in.or(ternary)
+        });
+
+        // Right-associative, so `a = b = 5 m` binds both names
+        let assign = recursive(|assign| {
+            ident
+                .then_ignore(just('='))
+                .then(assign.or(expr.clone()))
+                .map(|(name, rhs)| Expr::Assign {
+                    name,
+                    rhs: Box::new(rhs),
+                })
+        });
+
+        // fn NAME(PARAMS) = BODY, see `Interpretor::define_function`
+        let define = text::ascii::keyword("fn")
+            .padded()
+            .ignore_then(ident)
+            .then(
+                ident
+                    .separated_by(just(','))
+                    .collect::<Vec<_>>()
+                    .delimited_by(just('('), just(')'))
+                    .padded(),
+            )
+            .then_ignore(just('='))
+            .then(expr.clone())
+            .map(|((name, params), body)| Expr::Define {
+                name,
+                params,
+                body: Box::new(body),
+            });
+
+        let to = expr
+            .then(conversion.or_not())
+            .map(|(expr, target)| Expr::To(Box::new(expr), target));
+
+        define.or(assign).or(to).padded()
+    }
+
+    /// Evaluates `expr`, reusing the result of an identical sub-expression if they're memoized
+    fn eval_expr(&self, expr: &Expr, scope: &mut Scope) -> Result<(Value, String), String> {
+        let Some((id, generation)) = scope
+            .memo
+            .as_ref()
+            .and_then(|memo| Some((memo.id(expr)?, memo.generation)))
+        else {
+            return self.eval_node(expr, scope);
+        };
+        if let Some(result) = scope.memo.as_ref().and_then(|memo| memo.results.get(&id)) {
+            return Ok(result.clone());
+        }
+
+        let result = self.eval_node(expr, scope)?;
+        // A result depending on variables changed while evaluating it, e.g. by an assignment in it, isn't reused
+        if let Some(memo) = &mut scope.memo
+            && memo.generation == generation
+        {
+            memo.results.insert(id, result.clone());
+        }
+        Ok(result)
+    }
+
+    fn eval_node<'src>(
+        &self,
+        expr: &Expr<'src>,
+        scope: &mut Scope,
+    ) -> Result<(Value, String), String> {
+        match expr {
+            Expr::Num(num, unit_str) => {
+                let unit_str = match &self.assumed_unit {
+                    Some(assumed_unit) if unit_str.is_empty() => assumed_unit,
+                    _ => *unit_str,
+                };
+                // Unitless numbers are already in their base unit, so they need no lookups
+                if unit_str.is_empty() {
+                    return Ok((Value::Number(*num), String::new()));
+                }
+                let result = match self.exact_base_value(*num, unit_str) {
+                    Some((num, base_unit)) => (Value::Number(num), base_unit),
+                    None => self.apply_unit(Value::Number(*num), unit_str)?,
+                };
+                scope.record(
+                    TraceOperation::ApplyUnit,
+                    &[unit_str],
+                    &result.1,
+                    self.unit_factor(unit_str),
+                );
+                Ok(result)
+            }
+            Expr::NumOrVar(num, name) => {
+                let expr = if self.is_known_unit(name) || !scope.vars.contains_key(*name) {
+                    Expr::Num(*num, name)
+                } else {
+                    Expr::Mul(Box::new(Expr::Num(*num, "")), Box::new(Expr::Var(name)))
+                };
+                self.eval_expr(&expr, scope)
+            }
+            Expr::Date(seconds) => Ok((Value::Number(*seconds), DATETIME_UNIT.to_string())),
+            Expr::Percent(percent) => Ok((Value::Number(percent / 100.0), String::new())),
+            Expr::Prefixed(num, symbol) => {
+                let unit = self
+                    .prefix_symbols
+                    .get(*symbol)
+                    .ok_or_else(|| format!("Unknown prefix symbol \"{}\"", symbol))?;
+                self.eval_expr(&Expr::Num(*num, unit), scope)
+            }
+            Expr::List(items, unit_str) => {
+                let mut list = Vec::with_capacity(items.len());
+                let mut list_unit: Option<String> = None;
+                for item in items {
+                    let (val, unit) = self.eval_expr(item, scope)?;
+                    let Value::Number(num) = val else {
+                        return Err("Nested lists are not supported".to_string());
+                    };
+                    if list_unit
+                        .as_ref()
+                        .is_some_and(|list_unit| *list_unit != unit)
+                    {
+                        return Err(format!(
+                            "List elements must share the same unit, found {:?}",
+                            unit
+                        ));
+                    }
+                    list_unit = Some(unit);
+                    list.push(num);
+                }
+                let list_unit = list_unit.unwrap_or_default();
+                if unit_str.is_empty() {
+                    return Ok((list.into(), list_unit));
+                }
+                if !list_unit.is_empty() {
+                    return Err(format!(
+                        "Cannot apply unit \"{}\" to a list of {:?}",
+                        unit_str, list_unit
+                    ));
+                }
+                self.apply_unit_in(Value::List(list), unit_str, scope)
+            }
+            Expr::WithUnit(expr, unit_str) => {
+                let (val, unit) = self.eval_expr(expr, scope)?;
+                if !unit.is_empty() {
+                    return Err(format!(
+                        "Cannot apply unit \"{}\" to a value of {:?}",
+                        unit_str, unit
+                    ));
+                }
+                self.apply_unit_in(val, unit_str, scope)
+            }
+            Expr::Neg(a) => {
+                let (val, unit) = self.eval_expr(a, scope)?;
+                reject_dates("-", &[&unit])?;
+                Ok((val.map(|num| -num)?, unit))
+            }
+            // Adding a percentage scales the value, e.g. `x + 10%` is `x * 1.1` in the unit of `x`
+            Expr::Add(a, b) | Expr::Sub(a, b) if matches!(b.as_ref(), Expr::Percent(_)) => {
+                let Expr::Percent(percent) = b.as_ref() else {
+                    unreachable!()
+                };
+                let percent = if matches!(expr, Expr::Add(_, _)) {
+                    *percent
+                } else {
+                    -percent
+                };
+                let (val, unit) = self.eval_operand(a, scope)?;
+                Ok((val.map(|num| num + num * percent / 100.0)?, unit))
+            }
+            Expr::Add(a, b) | Expr::Sub(a, b) => {
+                let (val_a, unit_a) = self.eval_operand(a, scope)?;
+                let (val_b, unit_b) = self.eval_operand(b, scope)?;
+
+                let op = if matches!(expr, Expr::Add(_, _)) {
+                    "+"
+                } else {
+                    "-"
+                };
+                if unit_a == DATETIME_UNIT || unit_b == DATETIME_UNIT {
+                    return self.eval_date_arithmetic(op, (val_a, unit_a), (val_b, unit_b));
+                }
+                if unit_a != unit_b {
+                    return Err(format!(
+                        "Cannot evaluate {} {:?} {} {} {:?}",
+                        val_a, unit_a, op, val_b, unit_b
+                    ));
+                }
+                let result = if op == "+" {
+                    val_a.zip_with(val_b, |a, b| a + b)?
+                } else {
+                    val_a.zip_with(val_b, |a, b| a - b)?
+                };
+
+                Ok((result, unit_b))
+            }
+            Expr::Mul(a, b) | Expr::Div(a, b) => {
+                let (val_a, unit_a) = self.eval_operand(a, scope)?;
+                let (val_b, unit_b) = self.eval_operand(b, scope)?;
+
+                let op = if matches!(expr, Expr::Mul(_, _)) {
+                    "*"
+                } else {
+                    "/"
+                };
+                reject_dates(op, &[&unit_a, &unit_b])?;
+                let new_unit = match self.unit_table.derived_units_map().get(&(
+                    unit_a.clone(),
+                    op.to_string(),
+                    unit_b.clone(),
+                )) {
+                    Some(new_unit) => {
+                        let operation = if op == "*" {
+                            TraceOperation::Multiply
+                        } else {
+                            TraceOperation::Divide
+                        };
+                        // The product or quotient of base units is already in the derived unit
+                        scope.record(operation, &[&unit_a, &unit_b], new_unit, 1.0);
+                        new_unit.clone()
+                    }
+                    None if unit_a.is_empty() => unit_b,
+                    None if unit_b.is_empty() => unit_a,
+                    // A ratio of values in the same unit, both are normalized to the base unit
+                    None if op == "/" && unit_a == unit_b => String::new(),
+                    None => {
+                        return Err(format!(
+                            "Cannot evaluate {} {:?} {} {} {:?}",
+                            val_a, unit_a, op, val_b, unit_b
+                        ));
+                    }
+                };
+                if op == "*" {
+                    Ok((val_a.zip_with(val_b, |a, b| a * b)?, new_unit))
+                } else {
+                    Ok((val_a.zip_with(val_b, |a, b| a / b)?, new_unit))
+                }
+            }
+            Expr::Pow(base, exponent) => {
+                let (val, unit) = self.eval_operand(base, scope)?;
+                let (exponent, exponent_unit) = self.eval_operand(exponent, scope)?;
+                reject_dates("^", &[&unit, &exponent_unit])?;
+                let Value::Number(exponent) = exponent else {
+                    return Err("Exponent must be a single number".to_string());
+                };
+                if !exponent_unit.is_empty() {
+                    return Err(format!(
+                        "Exponent must be dimensionless, found {:?}",
+                        exponent_unit
+                    ));
+                }
+                if unit.is_empty() {
+                    return Ok((val.map(|num| num.powf(exponent))?, unit));
+                }
+
+                // A unit can be raised to a whole power its repeated product is derived as, e.g. `m` to 2 as `m2`
+                let unsupported =
+                    || format!("Cannot raise {:?} to the power of {}", unit, exponent);
+                if exponent < 1.0 || exponent.fract() != 0.0 {
+                    return Err(unsupported());
+                }
+                let mut new_unit = unit.clone();
+                for _ in 1..exponent as usize {
+                    let product = self
+                        .unit_table
+                        .derived_units_map()
+                        .get(&(new_unit.clone(), "*".to_string(), unit.clone()))
+                        .ok_or_else(unsupported)?;
+                    scope.record(TraceOperation::Multiply, &[&new_unit, &unit], product, 1.0);
+                    new_unit = product.clone();
+                }
+                Ok((val.map(|num| num.powf(exponent))?, new_unit))
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval_operand(arg, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match self.functions.get(*name) {
+                    Some(function) => self.call_user_function(name, function, args, scope),
+                    None => self.call(name, args),
+                }
+            }
+            Expr::Cmp(op, a, b) => {
+                let (val_a, unit_a) = self.eval_operand(a, scope)?;
+                let (val_b, unit_b) = self.eval_operand(b, scope)?;
+
+                if unit_a != unit_b {
+                    return Err(format!(
+                        "Cannot evaluate {} {:?} {} {} {:?}",
+                        val_a,
+                        unit_a,
+                        op.symbol(),
+                        val_b,
+                        unit_b
+                    ));
+                }
+                let result =
+                    val_a.zip_with(val_b, |a, b| if op.compare(a, b) { 1.0 } else { 0.0 })?;
+
+                Ok((result, String::new()))
+            }
+            Expr::Var(name) => {
+                let recent = if *name == LAST_RESULT_ALIAS {
+                    self.stack().next()
+                } else {
+                    name.strip_prefix('$')
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .and_then(|n| self.stack().nth(n.checked_sub(1)?))
+                };
+                if let Some((val, unit)) = scope.vars.get(*name).or(recent) {
+                    // A stored value may carry a non-base unit (e.g. the result of `1 m >> cm`),
+                    // normalize it so it can take part in arithmetic and conversions again.
+                    let (val, unit) = (val.clone(), unit.clone());
+                    self.normalize_in(val, &unit, scope)
+                } else {
+                    Err(format!("Cannot find variable \"{name}\" in scope"))
+                }
+            }
+            Expr::Assign { name, rhs } => {
+                if is_reserved_name(name) {
+                    return Err(format!("Cannot assign to reserved variable \"{}\"", name));
+                }
+                if self.pinned.contains(*name) {
+                    return Err(format!("Cannot assign to pinned variable \"{}\"", name));
+                }
+                let rhs = self.eval_expr(rhs, scope)?;
+                scope.vars.insert(name.to_string(), rhs.clone());
+                scope.vars_changed();
+                Ok(rhs)
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                let (cond, _) = self.eval_expr(cond, scope)?;
+                let Value::Number(cond) = cond else {
+                    return Err("Condition must be a single number".to_string());
+                };
+                // Only the selected branch is evaluated, so the other one may e.g. recurse endlessly.
+                // It must still result in the same unit, which is checked without evaluating it.
+                let (taken, untaken) = if cond != 0.0 {
+                    (then, otherwise)
+                } else {
+                    (otherwise, then)
+                };
+                let result = self.eval_expr(taken, scope)?;
+                let mut unit_scope = UnitScope {
+                    vars: scope.vars,
+                    bound: Vec::new(),
+                    calling: Vec::new(),
+                };
+                if let Some(unit) = self.unit_of(untaken, &mut unit_scope)?
+                    && unit != result.1
+                {
+                    let (then_unit, otherwise_unit) = if cond != 0.0 {
+                        (&result.1, &unit)
+                    } else {
+                        (&unit, &result.1)
+                    };
+                    return Err(format!(
+                        "Branches of a condition must share the same unit, found {:?} and {:?}",
+                        then_unit, otherwise_unit
+                    ));
+                }
+                Ok(result)
+            }
+            Expr::Let { name, value, body } => {
+                let value = self.eval_expr(value, scope)?;
+                let shadowed = scope.vars.insert(name.to_string(), value);
+                scope.vars_changed();
+                let result = self.eval_expr(body, scope);
+                match shadowed {
+                    Some(shadowed) => scope.vars.insert(name.to_string(), shadowed),
+                    None => scope.vars.remove(*name),
+                };
+                scope.vars_changed();
+                result
+            }
+            Expr::Define { .. } => Err(
+                "Functions can only be defined with `define_function` or `execute_command`"
+                    .to_string(),
+            ),
+            Expr::To(expr, target) => {
+                if let (Expr::Num(num, from) | Expr::NumOrVar(num, from), Some(Target::Unit(to))) =
+                    (expr.as_ref(), target)
+                    && let Ok(to) = self.resolve_target(to)
+                    && let Some(num) = self.exact_conversion(*num, from, &to)
+                {
+                    let factor = self.unit_factor(from) / self.unit_factor(&to);
+                    scope.record(TraceOperation::Convert, &[from], &to, factor);
+                    return Ok((Value::Number(num), to));
+                }
+                let (val, cur_unit) = self.eval_expr(expr, scope)?;
+                match target {
+                    Some(Target::Unit(unit_str)) => {
+                        let unit_str = &self.resolve_target(unit_str)?;
+                        let factor = self.target_factor(&cur_unit, unit_str)?;
+                        let offset = self.unit_table.offset(unit_str);
+                        scope.record(
+                            TraceOperation::Convert,
+                            &[&cur_unit],
+                            unit_str,
+                            1.0 / factor,
+                        );
+                        Ok((
+                            val.map(|num| (num - offset) / factor)?,
+                            unit_str.to_string(),
+                        ))
+                    }
+                    Some(Target::Mixed(units)) => {
+                        let Value::Number(num) = val else {
+                            return Err(
+                                "Only a single number can be converted to mixed units".to_string()
+                            );
+                        };
+                        let mut units = units
+                            .iter()
+                            .map(|unit_str| {
+                                if self.unit_table.offset(unit_str) != 0.0 {
+                                    return Err(format!(
+                                        "Cannot split into unit \"{}\" which has an offset",
+                                        unit_str
+                                    ));
+                                }
+                                Ok((*unit_str, self.target_factor(&cur_unit, unit_str)?))
+                            })
+                            .collect::<Result<Vec<_>, String>>()?;
+                        units.sort_by(|a, b| b.1.total_cmp(&a.1));
+                        for (unit_str, factor) in &units {
+                            scope.record(
+                                TraceOperation::Convert,
+                                &[&cur_unit],
+                                unit_str,
+                                1.0 / factor,
+                            );
+                        }
+
+                        // Greedily take whole amounts of the larger units, the smallest unit absorbs the remainder
+                        let mut remaining = num.abs();
+                        let mut parts = Vec::with_capacity(units.len());
+                        for (i, (unit_str, factor)) in units.iter().enumerate() {
+                            let mut amount = snap_to_integer(remaining / factor);
+                            if i < units.len() - 1 {
+                                amount = amount.floor();
+                            }
+                            remaining -= amount * factor;
+                            let amount = if num < 0.0 && amount != 0.0 {
+                                -amount
+                            } else {
+                                amount
+                            };
+                            parts.push((amount, unit_str.to_string()));
+                        }
+                        Ok((Value::Mixed(parts), String::new()))
+                    }
+                    Some(Target::Each(units)) => {
+                        let Value::Number(num) = val else {
+                            return Err("Only a single number can be converted to several units"
+                                .to_string());
+                        };
+                        let mut conversions = Vec::with_capacity(units.len());
+                        for unit_str in units {
+                            let unit_str = self.resolve_target(unit_str)?;
+                            let factor = self.target_factor(&cur_unit, &unit_str)?;
+                            let offset = self.unit_table.offset(&unit_str);
+                            scope.record(
+                                TraceOperation::Convert,
+                                &[&cur_unit],
+                                &unit_str,
+                                1.0 / factor,
+                            );
+                            conversions.push(((num - offset) / factor, unit_str));
+                        }
+                        Ok((Value::Each(conversions), String::new()))
+                    }
+                    Some(Target::Category(category)) => {
+                        let base_unit = self
+                            .unit_definitions
+                            .categories
+                            .get(*category)
+                            .and_then(|units| units.keys().next())
+                            .ok_or_else(|| format!("Unknown category \"{}\"", category))?;
+                        let factor = self.target_factor(&cur_unit, base_unit).map_err(|_| {
+                            format!("Cannot convert {:?} to category \"{}\"", cur_unit, category)
+                        })?;
+                        let offset = self.unit_table.offset(base_unit);
+                        scope.record(
+                            TraceOperation::Convert,
+                            &[&cur_unit],
+                            base_unit,
+                            1.0 / factor,
+                        );
+                        Ok((val.map(|num| (num - offset) / factor)?, base_unit.clone()))
+                    }
+                    None => Ok((val, cur_unit)),
+                }
+            }
+        }
+    }
+
+    /// The unit `expr` evaluates to, found by following the rules of `eval_node` for units only,
+    /// so magnitudes like a division by zero or dates out of range can't make it fail.
+    /// Nothing is assigned. `None` if the unit depends on a recursive call of a user function.
+    fn unit_of(&self, expr: &Expr, scope: &mut UnitScope) -> Result<Option<String>, String> {
+        let unit = match expr {
+            Expr::Num(_, unit_str) => {
+                let unit_str = match &self.assumed_unit {
+                    Some(assumed_unit) if unit_str.is_empty() => assumed_unit,
+                    _ => *unit_str,
+                };
+                if unit_str.is_empty() {
+                    return Ok(Some(String::new()));
+                }
+                self.apply_unit(Value::Number(1.0), unit_str)?.1
+            }
+            Expr::NumOrVar(num, name) => {
+                let is_var = scope.bound.iter().any(|(bound, _)| bound == name)
+                    || scope.vars.contains_key(*name);
+                return if self.is_known_unit(name) || !is_var {
+                    self.unit_of(&Expr::Num(*num, name), scope)
+                } else {
+                    self.unit_of(&Expr::Var(name), scope)
+                };
+            }
+            Expr::Date(_) => DATETIME_UNIT.to_string(),
+            Expr::Percent(_) => String::new(),
+            Expr::Prefixed(num, symbol) => {
+                let unit = self
+                    .prefix_symbols
+                    .get(*symbol)
+                    .ok_or_else(|| format!("Unknown prefix symbol \"{}\"", symbol))?;
+                return self.unit_of(&Expr::Num(*num, unit), scope);
+            }
+            Expr::List(items, unit_str) => {
+                let mut list_unit: Option<Option<String>> = None;
+                for item in items {
+                    if matches!(item, Expr::List(..)) {
+                        return Err("Nested lists are not supported".to_string());
+                    }
+                    let unit = self.unit_of(item, scope)?;
+                    if let (Some(Some(list_unit)), Some(unit)) = (&list_unit, &unit)
+                        && list_unit != unit
+                    {
+                        return Err(format!(
+                            "List elements must share the same unit, found {:?}",
+                            unit
+                        ));
+                    }
+                    if list_unit.as_ref().is_none_or(Option::is_none) {
+                        list_unit = Some(unit);
+                    }
+                }
+                let list_unit = list_unit.unwrap_or(Some(String::new()));
+                if unit_str.is_empty() {
+                    return Ok(list_unit);
+                }
+                if let Some(list_unit) = list_unit.filter(|unit| !unit.is_empty()) {
+                    return Err(format!(
+                        "Cannot apply unit \"{}\" to a list of {:?}",
+                        unit_str, list_unit
+                    ));
+                }
+                self.apply_unit(Value::Number(1.0), unit_str)?.1
+            }
+            Expr::WithUnit(expr, unit_str) => {
+                if let Some(unit) = self.unit_of(expr, scope)?.filter(|unit| !unit.is_empty()) {
+                    return Err(format!(
+                        "Cannot apply unit \"{}\" to a value of {:?}",
+                        unit_str, unit
+                    ));
+                }
+                self.apply_unit(Value::Number(1.0), unit_str)?.1
+            }
+            Expr::Neg(a) => {
+                let unit = self.unit_of(a, scope)?;
+                reject_dates("-", &[unit.as_deref().unwrap_or_default()])?;
+                return Ok(unit);
+            }
+            Expr::Add(a, b) | Expr::Sub(a, b) if matches!(b.as_ref(), Expr::Percent(_)) => {
+                return self.operand_unit_of(a, scope);
+            }
+            Expr::Add(a, b) | Expr::Sub(a, b) => {
+                let op = if matches!(expr, Expr::Add(_, _)) {
+                    "+"
+                } else {
+                    "-"
+                };
+                let (unit_a, unit_b) = (
+                    self.operand_unit_of(a, scope)?,
+                    self.operand_unit_of(b, scope)?,
+                );
+                let (Some(unit_a), Some(unit_b)) = (&unit_a, &unit_b) else {
+                    // The sum is in the known unit, unless it's a date which depends on the other operand
+                    return Ok(unit_a.or(unit_b).filter(|unit| unit != DATETIME_UNIT));
+                };
+                if unit_a == DATETIME_UNIT || unit_b == DATETIME_UNIT {
+                    return self
+                        .date_arithmetic_unit(unit_a, op, unit_b)?
+                        .map(Some)
+                        .ok_or_else(|| {
+                            format!("Cannot evaluate {:?} {} {:?}", unit_a, op, unit_b)
+                        });
+                }
+                if unit_a != unit_b {
+                    return Err(format!("Cannot evaluate {:?} {} {:?}", unit_a, op, unit_b));
+                }
+                unit_a.clone()
+            }
+            Expr::Mul(a, b) | Expr::Div(a, b) => {
+                let op = if matches!(expr, Expr::Mul(_, _)) {
+                    "*"
+                } else {
+                    "/"
+                };
+                let (unit_a, unit_b) = (
+                    self.operand_unit_of(a, scope)?,
+                    self.operand_unit_of(b, scope)?,
+                );
+                reject_dates(
+                    op,
+                    &[
+                        unit_a.as_deref().unwrap_or_default(),
+                        unit_b.as_deref().unwrap_or_default(),
+                    ],
+                )?;
+                let (Some(unit_a), Some(unit_b)) = (unit_a, unit_b) else {
+                    return Ok(None);
+                };
+                match self.unit_table.derived_units_map().get(&(
+                    unit_a.clone(),
+                    op.to_string(),
+                    unit_b.clone(),
+                )) {
+                    Some(new_unit) => new_unit.clone(),
+                    None if unit_a.is_empty() => unit_b,
+                    None if unit_b.is_empty() => unit_a,
+                    None if op == "/" && unit_a == unit_b => String::new(),
+                    None => {
+                        return Err(format!("Cannot evaluate {:?} {} {:?}", unit_a, op, unit_b));
+                    }
+                }
+            }
+            Expr::Pow(base, exponent) => {
+                let unit = self.operand_unit_of(base, scope)?;
+                let exponent_unit = self.operand_unit_of(exponent, scope)?;
+                let (unit, exponent_unit) = (
+                    unit.as_deref().unwrap_or_default(),
+                    exponent_unit.unwrap_or_default(),
+                );
+                reject_dates("^", &[unit, &exponent_unit])?;
+                if !exponent_unit.is_empty() {
+                    return Err(format!(
+                        "Exponent must be dimensionless, found {:?}",
+                        exponent_unit
+                    ));
+                }
+                if unit.is_empty() {
+                    return Ok(Some(String::new()));
+                }
+
+                // The unit of a power depends on the exponent, which is evaluated for it unless it
+                // depends on names bound in the walk
+                let mut vars = scope.vars.clone();
+                for (name, _) in &scope.bound {
+                    vars.remove(name);
+                }
+                let mut exponent_scope = Scope {
+                    vars: &mut vars,
+                    trace: None,
+                    memo: None,
+                    calls: 0,
+                };
+                let Ok((exponent, _)) = self.eval_operand(exponent, &mut exponent_scope) else {
+                    return Ok(None);
+                };
+                let Value::Number(exponent) = exponent else {
+                    return Err("Exponent must be a single number".to_string());
+                };
+                let unsupported =
+                    || format!("Cannot raise {:?} to the power of {}", unit, exponent);
+                if exponent < 1.0 || exponent.fract() != 0.0 {
+                    return Err(unsupported());
+                }
+                let mut new_unit = unit.to_string();
+                for _ in 1..exponent as usize {
+                    new_unit = self
+                        .unit_table
+                        .derived_units_map()
+                        .get(&(new_unit, "*".to_string(), unit.to_string()))
+                        .ok_or_else(unsupported)?
+                        .clone();
+                }
+                new_unit
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.operand_unit_of(arg, scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let Some(function) = self.functions.get(*name) else {
+                    let [unit_a, unit_b] = <[_; 2]>::try_from(args).map_err(|args| {
+                        format!(
+                            "Function \"{}\" takes 2 arguments, got {}",
+                            name,
+                            args.len()
+                        )
+                    })?;
+                    if !matches!(*name, "abs_diff" | "rel_diff") {
+                        return Err(format!("Unknown function \"{}\"", name));
+                    }
+                    if let (Some(unit_a), Some(unit_b)) = (&unit_a, &unit_b)
+                        && unit_a != unit_b
+                    {
+                        return Err(format!(
+                            "Cannot evaluate {}({:?}, {:?})",
+                            name, unit_a, unit_b
+                        ));
+                    }
+                    return Ok(if *name == "abs_diff" {
+                        unit_a.or(unit_b)
+                    } else {
+                        Some(String::new())
+                    });
+                };
+                if args.len() != function.params.len() {
+                    return Err(format!(
+                        "Function \"{}\" takes {} arguments, got {}",
+                        name,
+                        function.params.len(),
+                        args.len()
+                    ));
+                }
+                if scope.calling.iter().any(|calling| calling == name) {
+                    return Ok(None);
+                }
+                self.check_strict_units(&function.body)?;
+
+                let vars = Vars::new();
+                let mut body_scope = UnitScope {
+                    vars: &vars,
+                    bound: function.params.iter().cloned().zip(args).collect(),
+                    calling: std::mem::take(&mut scope.calling),
+                };
+                body_scope.calling.push(name.to_string());
+                let unit = self.unit_of(&function.body, &mut body_scope);
+                body_scope.calling.pop();
+                scope.calling = body_scope.calling;
+                return unit;
+            }
+            Expr::Cmp(op, a, b) => {
+                let (unit_a, unit_b) = (
+                    self.operand_unit_of(a, scope)?,
+                    self.operand_unit_of(b, scope)?,
+                );
+                if let (Some(unit_a), Some(unit_b)) = (&unit_a, &unit_b)
+                    && unit_a != unit_b
+                {
+                    return Err(format!(
+                        "Cannot evaluate {:?} {} {:?}",
+                        unit_a,
+                        op.symbol(),
+                        unit_b
+                    ));
+                }
+                String::new()
+            }
+            Expr::Var(name) => {
+                if let Some((_, unit)) = scope.bound.iter().rev().find(|(bound, _)| bound == name) {
+                    return Ok(unit.clone());
+                }
+                let recent = if *name == LAST_RESULT_ALIAS {
+                    self.stack().next()
+                } else {
+                    name.strip_prefix('$')
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .and_then(|n| self.stack().nth(n.checked_sub(1)?))
+                };
+                let Some((_, unit)) = scope.vars.get(*name).or(recent) else {
+                    return Err(format!("Cannot find variable \"{name}\" in scope"));
+                };
+                match self.lookup_unit(unit) {
+                    Some((_, base_unit)) => base_unit.to_string(),
+                    None => unit.clone(),
+                }
+            }
+            Expr::Assign { name, rhs } => {
+                if is_reserved_name(name) {
+                    return Err(format!("Cannot assign to reserved variable \"{}\"", name));
+                }
+                if self.pinned.contains(*name) {
+                    return Err(format!("Cannot assign to pinned variable \"{}\"", name));
+                }
+                let unit = self.unit_of(rhs, scope)?;
+                scope.bound.push((name.to_string(), unit.clone()));
+                return Ok(unit);
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.unit_of(cond, scope)?;
+                let then = self.unit_of(then, scope)?;
+                let otherwise = self.unit_of(otherwise, scope)?;
+                if let (Some(then), Some(otherwise)) = (&then, &otherwise)
+                    && then != otherwise
+                {
+                    return Err(format!(
+                        "Branches of a condition must share the same unit, found {:?} and {:?}",
+                        then, otherwise
+                    ));
+                }
+                return Ok(then.or(otherwise));
+            }
+            Expr::Let { name, value, body } => {
+                let value = self.unit_of(value, scope)?;
+                let depth = scope.bound.len();
+                scope.bound.push((name.to_string(), value));
+                let unit = self.unit_of(body, scope);
+                scope.bound.truncate(depth);
+                return unit;
+            }
+            Expr::Define { .. } => {
+                return Err(
+                    "Functions can only be defined with `define_function` or `execute_command`"
+                        .to_string(),
+                );
+            }
+            Expr::To(expr, target) => {
+                let cur_unit = self.unit_of(expr, scope)?;
+                let check = |unit_str: &str| match &cur_unit {
+                    Some(cur_unit) => self.target_factor(cur_unit, unit_str).map(|_| ()),
+                    None => self.check_rate(unit_str),
+                };
+                match target {
+                    Some(Target::Unit(unit_str)) => {
+                        let unit_str = self.resolve_target(unit_str)?;
+                        check(&unit_str)?;
+                        unit_str
+                    }
+                    Some(Target::Mixed(units)) => {
+                        for unit_str in units {
+                            if self.unit_table.offset(unit_str) != 0.0 {
+                                return Err(format!(
+                                    "Cannot split into unit \"{}\" which has an offset",
+                                    unit_str
+                                ));
+                            }
+                            check(unit_str)?;
+                        }
+                        String::new()
+                    }
+                    Some(Target::Each(units)) => {
+                        for unit_str in units {
+                            check(&self.resolve_target(unit_str)?)?;
+                        }
+                        String::new()
+                    }
+                    Some(Target::Category(category)) => {
+                        let base_unit = self
+                            .unit_definitions
+                            .categories
+                            .get(*category)
+                            .and_then(|units| units.keys().next())
+                            .ok_or_else(|| format!("Unknown category \"{}\"", category))?;
+                        check(base_unit).map_err(|_| {
+                            format!("Cannot convert {:?} to category \"{}\"", cur_unit, category)
+                        })?;
+                        base_unit.clone()
+                    }
+                    None => return Ok(cur_unit),
+                }
+            }
+        };
+        Ok(Some(unit))
+    }
+
+    /// `unit_of` an operand of arithmetic, normalized to its base unit like by `eval_operand`
+    fn operand_unit_of(
+        &self,
+        expr: &Expr,
+        scope: &mut UnitScope,
+    ) -> Result<Option<String>, String> {
+        Ok(self
+            .unit_of(expr, scope)?
+            .map(|unit| match self.lookup_unit(&unit) {
+                Some((_, base_unit)) => base_unit.to_string(),
+                None => unit,
+            }))
+    }
+
+    /// Resolves a conversion target like `m/s` to the key of the derived unit it results in, e.g. `mps`.
+    /// A single unit is returned as is.
+    fn resolve_target(&self, target: &str) -> Result<String, String> {
+        if !target.contains(['*', '/']) {
+            return Ok(target.to_string());
+        }
+        self.unit_table
+            .resolve_unit_expr(target)
+            .ok_or_else(|| format!("No unit is derived as \"{}\"", target))
+    }
+
+    /// Applies the function `name` to the evaluated arguments, which are in base units:
+    /// - `abs_diff(a, b)`: the absolute difference `|a - b|` in the unit of both
+    /// - `rel_diff(a, b)`: the difference relative to `b`, `|a - b| / |b|`, which is dimensionless
+    fn call(&self, name: &str, args: Vec<(Value, String)>) -> Result<(Value, String), String> {
+        match name {
+            "abs_diff" | "rel_diff" => {
+                let [(a, unit_a), (b, unit_b)] = <[_; 2]>::try_from(args).map_err(|args| {
+                    format!(
+                        "Function \"{}\" takes 2 arguments, got {}",
+                        name,
+                        args.len()
+                    )
+                })?;
+                if unit_a != unit_b {
+                    return Err(format!(
+                        "Cannot evaluate {}({:?}, {:?})",
+                        name, unit_a, unit_b
+                    ));
+                }
+                if name == "abs_diff" {
+                    Ok((a.zip_with(b, |a, b| (a - b).abs())?, unit_a))
+                } else {
+                    Ok((a.zip_with(b, |a, b| ((a - b) / b).abs())?, String::new()))
+                }
+            }
+            _ => Err(format!("Unknown function \"{}\"", name)),
+        }
+    }
+
+    /// Converts the result of `expr` to the unit it was entered in if it has no `>>` conversion,
+    /// see `set_entry_unit_results`. `None` if the result can't be converted.
+    fn in_entry_unit(&self, expr: &Expr, (val, unit): &(Value, String)) -> Option<(Value, String)> {
+        let (Expr::To(expr, None) | Expr::Assign { rhs: expr, .. }) = expr else {
+            return None;
+        };
+        let entry_unit = self.entry_unit(expr)?;
+        let (base_val, base_unit) = self.normalize(val.clone(), unit).ok()?;
+        let factor = self.target_factor(&base_unit, entry_unit).ok()?;
+        let offset = self.unit_table.offset(entry_unit);
+        let converted = base_val.map(|num| (num - offset) / factor).ok()?;
+        Some((converted, entry_unit.to_string()))
+    }
+
+    /// The unit of the leftmost number with a unit in `expr`, the assumed unit for numbers without one
+    fn entry_unit<'e>(&'e self, expr: &'e Expr) -> Option<&'e str> {
+        match expr {
+            Expr::Num(_, "") => self.assumed_unit.as_deref(),
+            Expr::Num(_, unit) | Expr::List(_, unit) | Expr::WithUnit(_, unit)
+                if !unit.is_empty() =>
+            {
+                Some(unit)
+            }
+            Expr::NumOrVar(_, name) if self.is_known_unit(name) => Some(name),
+            Expr::Prefixed(_, symbol) => self.prefix_symbols.get(*symbol).map(String::as_str),
+            _ => expr
+                .children()
+                .into_iter()
+                .find_map(|child| self.entry_unit(child)),
+        }
+    }
+
+    /// Fails in strict mode if `expr` has a number without a unit where one is needed, see `set_strict_units`
+    fn check_strict_units(&self, expr: &Expr) -> Result<(), String> {
+        if !self.strict_units || self.assumed_unit.is_some() {
+            return Ok(());
+        }
+        match expr.unitless_number() {
+            Some(num) => Err(format!(
+                "Number {} has no unit, which strict mode requires",
+                num
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Evaluates the body of a user function with its parameters bound to the evaluated arguments
+    /// as the only variables
+    fn call_user_function(
+        &self,
+        name: &str,
+        function: &UserFunction,
+        args: Vec<(Value, String)>,
+        scope: &mut Scope,
+    ) -> Result<(Value, String), String> {
+        if args.len() != function.params.len() {
+            return Err(format!(
+                "Function \"{}\" takes {} arguments, got {}",
+                name,
+                function.params.len(),
+                args.len()
+            ));
+        }
+        if scope.calls == MAX_CALL_DEPTH {
+            return Err(format!(
+                "Calls of function \"{}\" nest deeper than {}",
+                name, MAX_CALL_DEPTH
+            ));
+        }
+        self.check_strict_units(&function.body)?;
+
+        let mut vars = function.params.iter().cloned().zip(args).collect::<Vars>();
+        self.eval_expr(
+            &function.body,
+            &mut Scope {
+                vars: &mut vars,
+                trace: scope.trace.as_deref_mut(),
+                memo: None,
+                calls: scope.calls + 1,
+            },
+        )
+    }
+
+    /// Evaluates an operand of arithmetic, normalizing the result of a conversion like `(1 m >> cm)`
+    fn eval_operand(&self, expr: &Expr, scope: &mut Scope) -> Result<(Value, String), String> {
+        let (val, unit) = self.eval_expr(expr, scope)?;
+        self.normalize_in(val, &unit, scope)
+    }
+
+    /// `normalize`, recording the conversion in the trace of `scope`
+    fn normalize_in(
+        &self,
+        val: Value,
+        unit: &str,
+        scope: &mut Scope,
+    ) -> Result<(Value, String), String> {
+        let (val, base_unit) = self.normalize(val, unit)?;
+        if base_unit != unit {
+            scope.record(
+                TraceOperation::Normalize,
+                &[unit],
+                &base_unit,
+                self.unit_factor(unit),
+            );
+        }
+        Ok((val, base_unit))
+    }
+
+    /// `apply_unit`, recording it in the trace of `scope`
+    fn apply_unit_in(
+        &self,
+        val: Value,
+        unit_str: &str,
+        scope: &mut Scope,
+    ) -> Result<(Value, String), String> {
+        let (val, base_unit) = self.apply_unit(val, unit_str)?;
+        scope.record(
+            TraceOperation::ApplyUnit,
+            &[unit_str],
+            &base_unit,
+            self.unit_factor(unit_str),
+        );
+        Ok((val, base_unit))
+    }
+
+    /// The factor of `unit` to its base unit, 1 for units without one such as dimensionless values
+    fn unit_factor(&self, unit: &str) -> f64 {
+        self.lookup_unit(unit).map_or(1.0, |(factor, _)| factor)
+    }
+
+    /// Converts a value in a non-base unit to its base unit, other values are returned unchanged
+    fn normalize(&self, val: Value, unit: &str) -> Result<(Value, String), String> {
+        match self.lookup_unit(unit) {
+            Some((factor, base_unit)) if base_unit != unit => {
+                let offset = self.unit_table.offset(unit);
+                Ok((val.map(|num| num * factor + offset)?, base_unit.to_string()))
+            }
+            _ => Ok((val, unit.to_string())),
+        }
+    }
+
+    /// Adds a duration to or subtracts it from a date, or subtracts two dates giving a duration.
+    /// Durations are in the base unit of the `time` category, taken as the second, which is why
+    /// loading definitions fails when its factor is not 1.
+    fn eval_date_arithmetic(
+        &self,
+        op: &str,
+        (val_a, unit_a): (Value, String),
+        (val_b, unit_b): (Value, String),
+    ) -> Result<(Value, String), String> {
+        let Some(unit) = self.date_arithmetic_unit(&unit_a, op, &unit_b)? else {
+            return Err(format!(
+                "Cannot evaluate {} {:?} {} {} {:?}",
+                val_a, unit_a, op, val_b, unit_b
+            ));
+        };
+        let result = if op == "+" {
+            val_a.zip_with(val_b, |a, b| a + b)?
+        } else {
+            val_a.zip_with(val_b, |a, b| a - b)?
+        };
+        let dates = match &result {
+            Value::Number(num) if unit == DATETIME_UNIT => vec![*num],
+            Value::List(list) if unit == DATETIME_UNIT => list.clone(),
+            _ => vec![],
+        };
+        if !dates.into_iter().all(is_representable_datetime) {
+            return Err("Date is out of range, dates go from 0000-01-01 to 9999-12-31".to_string());
+        }
+        Ok((result, unit))
+    }
+
+    /// The unit of adding or subtracting values in `unit_a` and `unit_b` where at least one is a date,
+    /// `None` if they can't be added or subtracted
+    fn date_arithmetic_unit(
+        &self,
+        unit_a: &str,
+        op: &str,
+        unit_b: &str,
+    ) -> Result<Option<String>, String> {
+        let time_unit = self
+            .unit_definitions
+            .categories
+            .get(TIME_CATEGORY)
+            .and_then(|units| units.keys().next())
+            .ok_or_else(|| format!("Date arithmetic needs a \"{}\" category", TIME_CATEGORY))?;
+        Ok(match (unit_a, op, unit_b) {
+            (DATETIME_UNIT, "-", DATETIME_UNIT) => Some(time_unit.clone()),
+            (DATETIME_UNIT, "+", unit) | (unit, "+", DATETIME_UNIT) if unit == time_unit => {
+                Some(DATETIME_UNIT.to_string())
+            }
+            (DATETIME_UNIT, "-", unit) if unit == time_unit => Some(DATETIME_UNIT.to_string()),
+            _ => None,
+        })
+    }
+
+    /// Normalizes a number literal to its base unit as an exact fraction, so e.g. `1.1 km` is exactly
+    /// `1100 m`. `None` if the factor is not exact or the unit needs the usual checks of `apply_unit`.
+    fn exact_base_value(&self, num: f64, unit_str: &str) -> Option<(f64, String)> {
+        let (_, base_unit) = self.unit_table.base_units_map().get(unit_str)?;
+        if self.unit_table.offset(unit_str) != 0.0 || self.check_rate(unit_str).is_err() {
+            return None;
+        }
+        let value = Ratio::from_f64(num)?.checked_mul(self.unit_table.exact_factor(unit_str)?)?;
+        Some((value.to_f64()?, base_unit.clone()))
+    }
+
+    /// Converts a number literal from one unit to another as an exact fraction, so the round trip
+    /// through the base unit doesn't add floating point error. `None` if that's not possible.
+    fn exact_conversion(&self, num: f64, from: &str, to: &str) -> Option<f64> {
+        let (_, from_base) = self.unit_table.base_units_map().get(from)?;
+        let (_, to_base) = self.unit_table.base_units_map().get(to)?;
+        if from_base != to_base
+            || self.unit_table.offset(from) != 0.0
+            || self.unit_table.offset(to) != 0.0
+            || self.check_rate(from).is_err()
+            || self.check_rate(to).is_err()
+        {
+            return None;
+        }
+        Ratio::from_f64(num)?
+            .checked_mul(self.unit_table.exact_factor(from)?)?
+            .checked_div(self.unit_table.exact_factor(to)?)?
+            .to_f64()
+    }
+
+    /// Gives a unitless value the unit `unit_str`, normalized to its base unit
+    fn apply_unit(&self, val: Value, unit_str: &str) -> Result<(Value, String), String> {
+        self.check_rate(unit_str)?;
+        match self.lookup_unit(unit_str) {
+            Some((factor, base_unit)) => {
+                let offset = self.unit_table.offset(unit_str);
+                Ok((val.map(|num| num * factor + offset)?, base_unit.to_string()))
+            }
+            None => Err(format!("Unknown unit: \"{}\"", unit_str)),
+        }
+    }
+
+    /// Returns the factor of `unit_str`, checking that a value in `cur_unit` can be converted to it
+    fn target_factor(&self, cur_unit: &str, unit_str: &str) -> Result<f64, String> {
+        self.check_rate(unit_str)?;
+        match self.lookup_unit(unit_str) {
+            Some((factor, base_unit)) if cur_unit == base_unit => Ok(factor),
+            Some(_) => {
+                let compatible_units = self.compatible_units(cur_unit);
+                if compatible_units.is_empty() {
+                    Err(format!("Cannot convert to unit \"{}\"", unit_str))
+                } else {
+                    Err(format!(
+                        "Cannot convert to unit \"{}\", compatible units: {}",
+                        unit_str,
+                        compatible_units.join(", ")
+                    ))
+                }
+            }
+            None => Err(format!("Unknown unit {}", unit_str)),
+        }
+    }
+
+    /// Defined units that a value in `base_unit` can be converted to, in definition order
+    fn compatible_units(&self, base_unit: &str) -> Vec<&str> {
+        self.unit_definitions
+            .categories
+            .values()
+            .flat_map(|units| units.keys())
+            .filter(|unit| {
+                self.unit_table
+                    .base_units_map()
+                    .get(unit.as_str())
+                    .is_some_and(|(_, base)| base == base_unit)
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The factor and base unit of `unit`, falling back to an SI prefix on a defined unit if enabled
+    fn lookup_unit(&self, unit: &str) -> Option<(f64, &str)> {
+        match self.unit_table.base_units_map().get(unit) {
+            Some((factor, base_unit)) => Some((*factor, base_unit)),
+            None => self.prefixed_unit(unit),
+        }
+    }
+
+    /// Whether `unit`, or every unit of a mixed value or of one in several units, is known. Dimensionless values always are.
+    fn has_known_units(&self, val: &Value, unit: &str) -> bool {
+        match val {
+            Value::Mixed(parts) | Value::Each(parts) => {
+                parts.iter().all(|(_, unit)| self.is_known_unit(unit))
+            }
+            _ => unit.is_empty() || self.is_known_unit(unit),
+        }
+    }
+
+    fn is_known_unit(&self, unit: &str) -> bool {
+        unit == DATETIME_UNIT
+            || self.unit_table.contains(unit)
+            || self.prefixed_unit(unit).is_some()
+    }
+
+    /// Resolves an undefined unit like `km` as an SI prefix on a defined unit, e.g. `m`.
+    /// Derived units, units with an offset and currencies don't take prefixes.
+    fn prefixed_unit(&self, unit: &str) -> Option<(f64, &str)> {
+        if !self.prefix_fallback {
+            return None;
+        }
+        SI_PREFIXES.iter().find_map(|(prefix, prefix_factor)| {
+            let rest = unit.strip_prefix(prefix)?;
+            let (category, unit_def) =
+                self.unit_definitions
+                    .categories
+                    .iter()
+                    .find_map(|(category, units)| {
+                        units.get(rest).map(|unit_def| (category, unit_def))
+                    })?;
+            if unit_def.derived.is_some() || unit_def.offset != 0.0 || category == CURRENCY_CATEGORY
+            {
+                return None;
+            }
+            let (factor, base_unit) = self.unit_table.base_units_map().get(rest)?;
+            Some((prefix_factor * factor, base_unit.as_str()))
+        })
+    }
+
+    /// Fails for a currency without exchange rate once rates are set, its static factor may be long outdated
+    fn check_rate(&self, unit_str: &str) -> Result<(), String> {
+        let Some(rates) = &self.rates else {
+            return Ok(());
+        };
+        let Some(currencies) = self.unit_definitions.categories.get(CURRENCY_CATEGORY) else {
+            return Ok(());
+        };
+        match currencies.get_index_of(unit_str) {
+            Some(index) if index > 0 && !rates.contains_key(unit_str) => {
+                Err(format!("No exchange rate for currency \"{}\"", unit_str))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Builds the unit table with the factors of the currencies replaced by those of `provider`,
+/// and then by `rates`
+fn build_unit_table(
+    unit_definitions: &UnitDefinitions,
+    rates: Option<&HashMap<String, f64>>,
+    provider: Option<&dyn FactorProvider>,
+) -> Result<UnitTable, DefinitionError> {
+    if rates.is_none() && provider.is_none() {
+        return UnitTable::new(unit_definitions);
+    }
+
+    let mut unit_definitions = unit_definitions.clone();
+    let currencies = unit_definitions
+        .categories
+        .entry(CURRENCY_CATEGORY.to_string())
+        .or_default();
+    if let Some(provider) = provider {
+        for (unit_key, unit_def) in currencies.iter_mut() {
+            if let Some(factor) = provider.factor(unit_key) {
+                unit_def.factor = Factor::Number(factor);
+                unit_def.relative_to = None;
+            }
+        }
+    }
+    for (unit_key, rate) in rates.into_iter().flatten() {
+        let unit_def = currencies
+            .get_mut(unit_key)
+            .ok_or_else(|| DefinitionError::UnknownCurrency(unit_key.clone()))?;
+        unit_def.factor = Factor::Number(*rate);
+        unit_def.relative_to = None;
+    }
+    if currencies.is_empty() {
+        unit_definitions.categories.shift_remove(CURRENCY_CATEGORY);
+    }
+
+    UnitTable::new(&unit_definitions)
+}
+
+/// Rounds values like `9.999999999999998` which are only off an integer by floating point error
+fn snap_to_integer(num: f64) -> f64 {
+    let rounded = num.round();
+    if (num - rounded).abs() < 1e-9 * num.abs().max(1.0) {
+        rounded
+    } else {
+        num
+    }
+}
+
+/// Dates can only be compared, added to and subtracted from, e.g. `2024-01-01 * 2` means nothing
+fn reject_dates(op: &str, units: &[&str]) -> Result<(), String> {
+    if units.contains(&DATETIME_UNIT) {
+        return Err(format!("Cannot apply \"{}\" to a date", op));
+    }
+    Ok(())
+}
+
+/// Whether `name` refers to a recent result, i.e. `$`, `$N` or `ans`, so it can't be assigned to
+fn is_reserved_name(name: &str) -> bool {
+    name == LAST_RESULT_ALIAS
+        || name
+            .strip_prefix('$')
+            .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FormatOptions, format_result, unit_definition::UnitDefinitions};
+
+    use super::*;
+
+    #[test]
+    fn test_eval_without_unit() {
+        let expr = "1 + 2 * 3";
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert_eq!(result, Ok((Value::Number(7.0), "".to_string())));
+    }
+
+    #[test]
+    fn test_format_dimensionless_result() {
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.evaluate("1 + 2 * 3").unwrap();
+        assert_eq!(result.unit_key, "");
+        assert_eq!(
+            format_result(&result.value, &result.unit_key, &FormatOptions::default()),
+            "7"
+        );
+    }
+
+    #[test]
+    fn test_eval_with_unit_definitions() {
+        let expr = "1 m + 2 cm";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert_eq!(result, Ok((Value::Number(1.02), "m".to_string())));
+    }
+
+    #[test]
+    fn test_eval_with_unit_map() {
+        let expr = "1 cm2 + 2 cm * 3cm";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+cm = { name = "center meter", symbol = "cm" }
+
+[area]
+cm2 = { name = "square center meter", symbol = "cm2", derived = "cm * cm" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert_eq!(result, Ok((Value::Number(7.0), "cm2".to_string())));
+    }
+
+    #[test]
+    fn test_eval_incompatible_units() {
+        let expr = "1 m + 2 sec";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+sec = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "Cannot evaluate 1 \"m\" + 2 \"sec\"");
+    }
+
+    #[test]
+    fn test_eval_invalid_unit_multiplication() {
+        let expr = "2 m * 3 sec";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+sec = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "Cannot evaluate 2 \"m\" * 3 \"sec\"");
+    }
+
+    #[test]
+    fn test_eval_unknown_variable() {
+        let expr = "x + 2";
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "Cannot find variable \"x\" in scope");
+    }
+
+    #[test]
+    fn test_eval_let_with_incompatible_units() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+sec = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 5m").unwrap();
+        let result = interceptor.execute_command("x + 3 sec");
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "Cannot evaluate 5 \"m\" + 3 \"sec\"");
+    }
+
+    #[test]
+    fn test_complex_expressions() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 2 m").unwrap();
+        interceptor.execute_command("y = 3 cm").unwrap();
+        let result = interceptor.execute_command("x + y * 4").unwrap();
+        assert_eq!(result, (Value::Number(2.12), "m".to_string()));
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("((1km + 2cm) * 2 * 3m + 4cm2) * 5m + 6m3");
+        assert_eq!(result, Ok((Value::Number(30006.602), "m3".to_string())));
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("360 km / 2hour");
+        assert_eq!(result, Ok((Value::Number(50.0), "mps".to_string())));
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("+5 m == 5 m");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+        let result = interceptor.execute_command("+-3 == -3");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+        let result = interceptor.execute_command("-+3");
+        assert_eq!(result, Ok((Value::Number(-3.0), "".to_string())));
+        let result = interceptor.execute_command("2 m + +3 m");
+        assert_eq!(result, Ok((Value::Number(5.0), "m".to_string())));
+    }
+
+    #[test]
+    fn should_show_error_for_invalid_expression() {
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("1 + 2 *");
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "found end of input at 7..7");
+    }
+
+    #[test]
+    fn should_show_error_for_out_of_range_number() {
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let digits = "9".repeat(400);
+        let errors = interceptor
+            .execute_command(&format!("1 + {}", digits))
+            .unwrap_err();
+        assert_eq!(errors, vec![(4..404, "Number out of range".to_string())]);
+        let errors = interceptor.execute_command("2e999").unwrap_err();
+        assert_eq!(errors, vec![(0..5, "Number out of range".to_string())]);
+
+        let result = interceptor.execute_command(&format!("0.{}", digits));
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+    }
+
+    #[test]
+    fn test_to_expr() {
+        let expr = "1 m >> cm";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert_eq!(result, Ok((Value::Number(100.0), "cm".to_string())));
+    }
+
+    #[test]
+    fn test_conversion_precedence() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        // The whole left-hand side is converted
+        let result = interceptor.execute_command("1 m + 2 m >> cm");
+        assert_eq!(result, Ok((Value::Number(300.0), "cm".to_string())));
+        let result = interceptor.execute_command("2 m * 3 >> cm");
+        assert_eq!(result, Ok((Value::Number(600.0), "cm".to_string())));
+        let result = interceptor.execute_command("1 m > 50 cm ? 1 km : 2 km >> m");
+        assert_eq!(result, Ok((Value::Number(1000.0), "m".to_string())));
+
+        // A conversion can't be followed by more arithmetic unless it's parenthesized
+        assert!(interceptor.execute_command("1 m >> cm + 2 m").is_err());
+        let result = interceptor.execute_command("(1 m >> cm) + 5 cm");
+        assert_eq!(result, Ok((Value::Number(1.05), "m".to_string())));
+        let result = interceptor.execute_command("(1 km >> m >> cm)");
+        assert_eq!(result, Ok((Value::Number(100000.0), "cm".to_string())));
+        let result = interceptor.execute_command("((1 m >> cm) + 5 cm >> cm)");
+        assert_eq!(result, Ok((Value::Number(105.0), "cm".to_string())));
+    }
+
+    #[test]
+    fn test_compound_target() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        interceptor.execute_command("v = 36 kmph").unwrap();
+        let expected = Ok((Value::Number(10.0), "mps".to_string()));
+        assert_eq!(interceptor.execute_command("v >> mps"), expected);
+        assert_eq!(interceptor.execute_command("v >> m/sec"), expected);
+        assert_eq!(interceptor.execute_command("v >> m / sec"), expected);
+        assert_eq!(
+            interceptor.execute_command("(v >> m/sec) * 2 sec"),
+            Ok((Value::Number(20.0), "m".to_string()))
+        );
+        let result = interceptor.execute_command("2 m * 3 m >> m*m");
+        assert_eq!(result, Ok((Value::Number(6.0), "m2".to_string())));
+
+        let result = interceptor.execute_command("v >> sec/m");
+        assert_eq!(result.unwrap_err()[0].1, "No unit is derived as \"sec/m\"");
+        assert_eq!(interceptor.check_units("1 m >> m/sec"), Ok(()));
+        assert_eq!(
+            interceptor.check_units("1 m >> m/s").unwrap_err()[0].0,
+            7..10
+        );
+    }
+
+    #[test]
+    fn test_to_expr_with_incompatible_units() {
+        let expr = "1 m >> sec";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+sec = { name = "sec", symbol = "s" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr);
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(
+            errors[0].1,
+            "Cannot convert to unit \"sec\", compatible units: m"
+        );
+    }
+
+    #[test]
+    fn test_incompatible_target_lists_compatible_units() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[mass]
+g = { name = "gram", symbol = "g" }
+kg = { name = "kilogram", symbol = "kg", factor = 1000 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("1 m >> g");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot convert to unit \"g\", compatible units: m, cm, km"
+        );
+
+        // Unitless values have nothing to suggest
+        let result = interceptor.execute_command("1 >> g");
+        assert_eq!(result.unwrap_err()[0].1, "Cannot convert to unit \"g\"");
+    }
+
+    #[test]
+    fn test_display_as() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[time]
+sec = { name = "second", symbol = "s" }
+hour = { name = "hour", symbol = "h", factor = 3600 }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / sec", display_as = "kmph" }
+kmph = { name = "kilometer per hour", symbol = "km/h", factor = "1000/3600" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let (value, unit) = interceptor.execute_command("10 m / 1 sec").unwrap();
+        assert_eq!(unit, "mps");
+        assert_eq!(
+            interceptor.display_value(value, &unit),
+            (Value::Number(36.0), "kmph".to_string())
+        );
+        let (value, unit) = interceptor.execute_command("2 km").unwrap();
+        assert_eq!(
+            interceptor.display_value(value, &unit),
+            (Value::Number(2000.0), "m".to_string())
+        );
+        let result = interceptor.evaluate("36 kmph >> mps").unwrap();
+        assert_eq!(
+            (result.value, result.unit_key.as_str()),
+            (Value::Number(10.0), "mps")
+        );
+        assert!(result.requested_unit);
+        assert!(!interceptor.evaluate("36 kmph").unwrap().requested_unit);
+    }
+
+    #[test]
+    fn test_preferred_units() {
+        let unit_definitions = toml::from_str(
+            r#"
+[temperature]
+K = { name = "kelvin", symbol = "K" }
+degC = { name = "degree Celsius", symbol = "°C", offset = 273.15 }
+degF = { name = "degree Fahrenheit", symbol = "°F", factor = "5/9", offset = 255.3722222222222 }
+
+[length]
+m = { name = "meter", symbol = "m" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor
+            .set_preferred_units(HashMap::from([(
+                "temperature".to_string(),
+                "degC".to_string(),
+            )]))
+            .unwrap();
+
+        let (value, unit) = interceptor.execute_command("283.15 K").unwrap();
+        assert_eq!((&value, unit.as_str()), (&Value::Number(283.15), "K"));
+        assert_eq!(
+            interceptor.display_value(value, &unit),
+            (Value::Number(10.0), "degC".to_string())
+        );
+        // Units asked for are kept rather than displayed in the preferred unit
+        let result = interceptor.evaluate("212 degF >> degF").unwrap();
+        let Value::Number(num) = result.value else {
+            panic!("expected a number");
+        };
+        assert!((num - 212.0).abs() < 1e-9);
+        assert_eq!(result.unit_key, "degF");
+        assert!(result.requested_unit);
+        interceptor.set_entry_unit_results(true);
+        let result = interceptor.evaluate("300 K + 1 K").unwrap();
+        assert_eq!(
+            (&result.value, result.unit_key.as_str()),
+            (&Value::Number(301.0), "K")
+        );
+        assert!(result.requested_unit);
+        interceptor.set_entry_unit_results(false);
+        let result = interceptor.execute_command("-10 degC");
+        assert_eq!(result, Ok((Value::Number(263.15), "K".to_string())));
+        let (value, unit) = interceptor.execute_command("3 m").unwrap();
+        assert_eq!(
+            interceptor.display_value(value, &unit),
+            (Value::Number(3.0), "m".to_string())
+        );
+
+        let result = interceptor.set_preferred_units(HashMap::from([(
+            "temperature".to_string(),
+            "m".to_string(),
+        )]));
+        assert_eq!(
+            result,
+            Err("Unit \"m\" is not in category \"temperature\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_display_as_other_category() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m", display_as = "sec" }
+
+[time]
+sec = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            Interpretor::new(&unit_definitions),
+            Err(DefinitionError::DisplayUnitNotFound(unit, display_as))
+                if unit == "m" && display_as == "sec"
+        ));
+    }
+
+    #[test]
+    fn test_float_numbers() {
+        let expr = "1.2 m + 0.45m";
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command(expr).unwrap();
+        assert_eq!(result, (Value::Number(1.65), "m".to_string()));
+    }
+
+    #[test]
+    fn test_convert_last_result() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("360 km / 2hour");
+        assert_eq!(result, Ok((Value::Number(50.0), "mps".to_string())));
+        let result = interceptor.execute_command("$ >> kmph");
+        assert_eq!(result, Ok((Value::Number(180.0), "kmph".to_string())));
+    }
+
+    #[test]
+    fn test_reuse_converted_result() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("1 m >> cm").unwrap();
+        let result = interceptor.execute_command("$ + 1 m");
+        assert_eq!(result, Ok((Value::Number(2.0), "m".to_string())));
+        let result = interceptor.execute_command("$ >> cm");
+        assert_eq!(result, Ok((Value::Number(200.0), "cm".to_string())));
+    }
+
+    #[test]
+    fn test_pin_var() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 2 m").unwrap();
+
+        interceptor.pin_var("x").unwrap();
+        let result = interceptor.execute_command("x = 3 m");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot assign to pinned variable \"x\""
+        );
+        assert!(interceptor.execute_command("y = x = 3 m").is_err());
+        assert!(interceptor.execute_command("y").is_err());
+        let result = interceptor.execute_command("x * 2");
+        assert_eq!(result, Ok((Value::Number(4.0), "m".to_string())));
+
+        interceptor.unpin_var("x").unwrap();
+        let result = interceptor.execute_command("x = 3 m");
+        assert_eq!(result, Ok((Value::Number(3.0), "m".to_string())));
+
+        assert_eq!(
+            interceptor.unpin_var("x"),
+            Err("Variable \"x\" is not pinned".to_string())
+        );
+        assert_eq!(
+            interceptor.pin_var("z"),
+            Err("Cannot find variable \"z\" in scope".to_string())
+        );
+    }
+
+    #[test]
+    fn test_multiple_assignment() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("a = b = 5 m");
+        assert_eq!(result, Ok((Value::Number(5.0), "m".to_string())));
+        let result = interceptor.execute_command("a");
+        assert_eq!(result, Ok((Value::Number(5.0), "m".to_string())));
+        let result = interceptor.execute_command("b");
+        assert_eq!(result, Ok((Value::Number(5.0), "m".to_string())));
+
+        let result = interceptor.execute_command("c = $ = 1 m");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot assign to reserved variable \"$\""
+        );
+        assert!(interceptor.execute_command("c").is_err());
+        let result = interceptor.execute_command("$ = c = 1 m");
+        assert!(result.is_err());
+        assert!(interceptor.execute_command("c").is_err());
+    }
+
+    #[test]
+    fn test_list_conversion() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("[1, 2, 3] m >> cm");
+        assert_eq!(
+            result,
+            Ok((Value::List(vec![100.0, 200.0, 300.0]), "cm".to_string()))
+        );
+
+        let result = interceptor.execute_command("[1 m, 50 cm] + [1, 2] m");
+        assert_eq!(result, Ok((Value::List(vec![2.0, 2.5]), "m".to_string())));
+    }
+
+    #[test]
+    fn test_list_scalar_multiplication() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("[1, 2, 3] m * 2");
+        assert_eq!(
+            result,
+            Ok((Value::List(vec![2.0, 4.0, 6.0]), "m".to_string()))
+        );
+        let result = interceptor.execute_command("2 * [1, 2, 3] m");
+        assert_eq!(
+            result,
+            Ok((Value::List(vec![2.0, 4.0, 6.0]), "m".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_list_length_mismatch() {
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("[1, 2, 3] + [1, 2]");
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(
+            errors[0].1,
+            "Cannot combine lists of different lengths (3 and 2)"
+        );
+    }
+
+    #[test]
+    fn test_incompatible_units_error_shows_values() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+
+[time]
+sec = { name = "second", symbol = "s" }
+min = { name = "minute", symbol = "min", factor = 60 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("(1 m + 50 cm) - 2 min");
+        assert!(result.is_err());
+        let errors = result.unwrap_err();
+        assert_eq!(errors[0].1, "Cannot evaluate 1.5 \"m\" - 120 \"sec\"");
+    }
+
+    #[test]
+    fn test_compound_derived_unit() {
+        let unit_definitions = toml::from_str(
+            r#"
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+s = { name = "second", symbol = "s" }
+
+[time_squared]
+s2 = { name = "square second", symbol = "s²", derived = "s * s" }
+
+[force]
+N = { name = "newton", symbol = "N", derived = "kg * m / s2" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("2 kg * 3 m / (2 s * 1 s)");
+        assert_eq!(result, Ok((Value::Number(3.0), "N".to_string())));
+    }
+
+    #[test]
+    fn test_reciprocal_unit() {
+        let unit_definitions = toml::from_str(
+            r#"
+[time]
+s = { name = "second", symbol = "s" }
+min = { name = "minute", symbol = "min", factor = 60 }
+
+[frequency]
+Hz = { name = "hertz", symbol = "Hz", derived = "1 / s" }
+
+[resistance]
+ohm = { name = "ohm", symbol = "Ω" }
+
+[conductance]
+S = { name = "siemens", symbol = "S", derived = "1 / ohm" }
+"#,
+        )
+        .unwrap();
+
+        let mut interpretor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interpretor.execute_command("1 / 2 s");
+        assert_eq!(result, Ok((Value::Number(0.5), "Hz".to_string())));
+        let result = interpretor.execute_command("120 / 1 min");
+        assert_eq!(result, Ok((Value::Number(2.0), "Hz".to_string())));
+        let result = interpretor.execute_command("10 Hz * 3 s");
+        assert_eq!(result, Ok((Value::Number(30.0), "".to_string())));
+        let result = interpretor.execute_command("1 / 4 Hz");
+        assert_eq!(result, Ok((Value::Number(0.25), "s".to_string())));
+
+        let result = interpretor.execute_command("1 / 5 ohm");
+        assert_eq!(result, Ok((Value::Number(0.2), "S".to_string())));
+        let result = interpretor.execute_command("2 S * 5 ohm");
+        assert_eq!(result, Ok((Value::Number(10.0), "".to_string())));
+    }
+
+    #[test]
+    fn test_reciprocal_cancels_to_dimensionless() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[wavenumber]
+perm = { name = "per meter", symbol = "1/m", derived = "1 / m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interpretor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interpretor.execute_command("(5 m) * (2 perm)");
+        assert_eq!(result, Ok((Value::Number(10.0), "".to_string())));
+        let result = interpretor.execute_command("3 perm * 2 km");
+        assert_eq!(result, Ok((Value::Number(6000.0), "".to_string())));
+        let result = interpretor.execute_command("5 m * (1 / 2 m)");
+        assert_eq!(result, Ok((Value::Number(2.5), "".to_string())));
+        let result = interpretor.execute_command("(5 m) / (2 m)");
+        assert_eq!(result, Ok((Value::Number(2.5), "".to_string())));
+        let result = interpretor.execute_command("2 km / 500 m");
+        assert_eq!(result, Ok((Value::Number(4.0), "".to_string())));
+    }
+
+    #[test]
+    fn test_ratio_factor() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+yd = { name = "yard", symbol = "yd" }
+ft = { name = "foot", symbol = "ft", factor = "1/3" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("1 yd >> ft");
+        assert_eq!(result, Ok((Value::Number(3.0), "ft".to_string())));
+        let result = interceptor.execute_command("6 ft");
+        assert_eq!(result, Ok((Value::Number(2.0), "yd".to_string())));
+    }
+
+    #[test]
+    fn test_exact_conversion() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+mm = { name = "millimeter", symbol = "mm", factor = 0.001 }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
+cm2 = { name = "square centimeter", symbol = "cm²", factor = 0.0001 }
+mm2 = { name = "square millimeter", symbol = "mm²", factor = 0.000001 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        // Plain floating point arithmetic gives 1.2345000000000002 and 1100.0000000000002
+        let result = interceptor.execute_command("12345 cm2 >> m2");
+        assert_eq!(result, Ok((Value::Number(1.2345), "m2".to_string())));
+        let result = interceptor.execute_command("1.1 km");
+        assert_eq!(result, Ok((Value::Number(1100.0), "m".to_string())));
+        let result = interceptor.execute_command("1000000 mm2 >> m2");
+        assert_eq!(result, Ok((Value::Number(1.0), "m2".to_string())));
+        let result = interceptor.execute_command("0.7 mm >> km");
+        assert_eq!(result, Ok((Value::Number(0.0000007), "km".to_string())));
+    }
+
+    #[test]
+    fn test_mixed_units_conversion() {
+        let unit_definitions = toml::from_str(
+            r#"
+[time]
+sec = { name = "second", symbol = "s" }
+min = { name = "minute", symbol = "min", factor = 60 }
+h = { name = "hour", symbol = "h", factor = 3600 }
+
+[length]
+m = { name = "meter", symbol = "m" }
+ft = { name = "foot", symbol = "ft", factor = 0.3048 }
+inch = { name = "inch", symbol = "in", factor = 0.0254 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("90 min >> [h, min]");
+        assert_eq!(
+            result,
+            Ok((
+                Value::Mixed(vec![(1.0, "h".to_string()), (30.0, "min".to_string())]),
+                "".to_string()
+            ))
+        );
+
+        let result = interceptor
+            .execute_command("70 inch >> [inch, ft]")
+            .unwrap();
+        assert_eq!(result.0.to_string(), "5 ft 10 inch");
+
+        let result = interceptor.execute_command("90 min >> [h, m]");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot convert to unit \"m\", compatible units: sec, min, h"
+        );
+    }
+
+    #[test]
+    fn test_conversion_to_several_units() {
+        let unit_definitions = toml::from_str(
+            r#"
+[time]
+sec = { name = "second", symbol = "s" }
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / sec" }
+kmph = { name = "kilometer per hour", symbol = "km/h", factor = "1000/3600" }
+mph = { name = "mile per hour", symbol = "mph", factor = 0.44704 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let (value, unit) = interceptor
+            .execute_command("50 mps >> {kmph, mph}")
+            .unwrap();
+        let Value::Each(conversions) = &value else {
+            panic!("expected a value in several units, got {:?}", value);
+        };
+        assert_eq!(unit, "");
+        assert_eq!(conversions.len(), 2);
+        assert_eq!(conversions[0].1, "kmph");
+        assert!((conversions[0].0 - 180.0).abs() < 1e-9);
+        assert_eq!(conversions[1].1, "mph");
+        assert!((conversions[1].0 - 111.846814603).abs() < 1e-9);
+
+        let result = interceptor
+            .execute_command("50 mps >> {kmph, m/sec}")
+            .unwrap();
+        assert_eq!(result.0.to_string(), "180 kmph\n50 mps");
+
+        let result = interceptor.execute_command("50 mps >> {kmph, sec}");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot convert to unit \"sec\", compatible units: mps, kmph, mph"
+        );
+        let result = interceptor.execute_command("[1, 2] mps >> {kmph, mph}");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Only a single number can be converted to several units"
+        );
+        interceptor
+            .execute_command("50 mps >> {kmph, mph}")
+            .unwrap();
+        let result = interceptor.execute_command("$ * 2");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot compute with a value in several units"
+        );
+    }
+
+    #[test]
+    fn test_base_units() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let base_units = interceptor.base_units();
+        assert_eq!(base_units["km"], (1000.0, "m".to_string()));
+        assert_eq!(base_units["hour"], (3600.0, "sec".to_string()));
+        assert_eq!(base_units["m"], (1.0, "m".to_string()));
+        assert!(!base_units.contains_key(""));
+        assert_eq!(base_units.keys().next().map(String::as_str), Some("L"));
+    }
+
+    #[test]
+    fn test_unit_symbols() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[area]
+m2 = { name = "square meter", symbol = "m2", derived = "m * m" }
+
+[volume]
+m3 = { name = "cubic meter", derived = "m * m * m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let options = FormatOptions {
+            unit_labels: interceptor.unit_symbols(),
+            ..Default::default()
+        };
+        let (value, unit) = interceptor.execute_command("2 m * 3 m * 4 m").unwrap();
+        assert_eq!(format_result(&value, &unit, &options), "24 m³");
+        let (value, unit) = interceptor.execute_command("2 m * 3 m").unwrap();
+        assert_eq!(format_result(&value, &unit, &options), "6 m²");
+    }
+
+    #[test]
+    fn test_are_compatible() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+
+[time]
+s = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        assert!(interceptor.are_compatible("m", "cm"));
+        assert!(!interceptor.are_compatible("m", "s"));
+        assert!(!interceptor.are_compatible("m", "foo"));
+
+        assert_eq!(interceptor.check_compatible("cm", "m"), Ok(true));
+        assert_eq!(interceptor.check_compatible("s", "cm"), Ok(false));
+        assert_eq!(
+            interceptor.check_compatible("m", "foo"),
+            Err("Unknown unit: \"foo\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_let_in() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("let x = 5 m in x * 2");
+        assert_eq!(result, Ok((Value::Number(10.0), "m".to_string())));
+        let result = interceptor.execute_command("x");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot find variable \"x\" in scope"
+        );
+
+        let result = interceptor.execute_command("(let y = 2 in let z = 3 m in y * z) + 1 m");
+        assert_eq!(result, Ok((Value::Number(7.0), "m".to_string())));
+    }
+
+    #[test]
+    fn test_let_in_shadows_variable() {
+        let unit_definitions = UnitDefinitions::default();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 1").unwrap();
+        let result = interceptor.execute_command("let x = 2 in x + 1");
+        assert_eq!(result, Ok((Value::Number(3.0), "".to_string())));
+        let result = interceptor.execute_command("x");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+    }
+
+    #[test]
+    fn test_memoization() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut plain = Interpretor::new(&unit_definitions).unwrap();
+        let mut memoized = Interpretor::new(&unit_definitions).unwrap();
+        memoized.set_memoization(true);
+
+        // Identical sub-expressions share an id, so only the first of them is evaluated
+        let parsed = memoized.parse("(1 m + 2 m) * (1 m + 2 m) + 1 m").unwrap();
+        let memo = Memo::new(&parsed);
+        let product = parsed.children()[0].children()[0];
+        let [a, b] = product.children()[..] else {
+            panic!("expected a product of two operands");
+        };
+        assert_eq!(memo.id(a), memo.id(b));
+        assert_ne!(memo.id(a), memo.id(product));
+
+        for command in [
+            "(1 km + 2 m) * 3 + (1 km + 2 m) * 3",
+            "x = 2 m",
+            "(x + 1 m) * (x + 1 m) >> cm2",
+            // Identical sub-expressions differ where `let` binds a variable in between
+            "x * 2 + (let x = 5 m in x * 2) + x * 2",
+            "let y = 1 m in (y + x) + (let x = 3 m in y + x) + (y + x)",
+            "y = z = x + x",
+            "[1, 2] km + [1, 2] km >> m",
+            "x > 1 m ? x + x : x + x",
+            "$ + $1 + $",
+        ] {
+            assert_eq!(
+                memoized.execute_command(command),
+                plain.execute_command(command),
+                "{}",
+                command
+            );
+        }
+        assert_eq!(
+            memoized.execute_command("let x = 1 m in (x + x) + (let x = 2 m in x + x) + (x + x)"),
+            Ok((Value::Number(8.0), "m".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_comparison() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+
+[time]
+s = { name = "second", symbol = "s" }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("3 m > 200 cm");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+        let result = interceptor.execute_command("1 m + 1 cm <= 100 cm");
+        assert_eq!(result, Ok((Value::Number(0.0), "".to_string())));
+        let result = interceptor.execute_command("[1, 2, 3] m != 2 m");
+        assert_eq!(
+            result,
+            Ok((Value::List(vec![1.0, 0.0, 1.0]), "".to_string()))
+        );
+        let result = interceptor.execute_command("3 m > 2 s");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot evaluate 3 \"m\" > 2 \"s\""
+        );
+        // `>>` is still a conversion
+        let result = interceptor.execute_command("1 m >> cm");
+        assert_eq!(result, Ok((Value::Number(100.0), "cm".to_string())));
+    }
+
+    #[test]
+    fn test_condition() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+g = { name = "gram", symbol = "g", factor = 0.001 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("3 m > 2 m ? 1 kg : 0 kg");
+        assert_eq!(result, Ok((Value::Number(1.0), "kg".to_string())));
+        let result = interceptor.execute_command("3 m < 2 m ? 1 kg : 500 g");
+        assert_eq!(result, Ok((Value::Number(0.5), "kg".to_string())));
+        let result = interceptor.execute_command("0 ? 1 : 2 ? 3 : 4");
+        assert_eq!(result, Ok((Value::Number(3.0), "".to_string())));
+        let result = interceptor.execute_command("1 ? 1 kg : 1 m");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Branches of a condition must share the same unit, found \"kg\" and \"m\""
+        );
+
+        // The branch that isn't taken isn't evaluated, only its unit is checked
+        let result = interceptor.execute_command("0 > 1 ? [1, 2] m + [1, 2, 3] m : 2 m");
+        assert_eq!(result, Ok((Value::Number(2.0), "m".to_string())));
+        let result = interceptor.execute_command("0 ? 1 kg : 1 m");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Branches of a condition must share the same unit, found \"kg\" and \"m\""
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.evaluate("2 m * 3 m").unwrap();
+        assert_eq!(
+            result,
+            EvalResult {
+                value: Value::Number(6.0),
+                unit_key: "m2".to_string(),
+                symbol: "m²".to_string(),
+                category: "area".to_string(),
+                warnings: vec![],
+                requested_unit: false,
+            }
+        );
+        let result = interceptor.evaluate("2 * 3").unwrap();
+        assert_eq!(
+            (result.symbol, result.category),
+            (String::new(), String::new())
+        );
+
+        let errors = interceptor.evaluate("1 x").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![EvalError {
+                span: 0..3,
+                message: "Unknown unit: \"x\"".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        let unit_definitions = UnitDefinitions::default();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        assert_eq!(
+            interceptor.check_syntax("x = 3 furlong * (y + 2 m)"),
+            Ok(())
+        );
+        assert!(interceptor.vars.is_empty());
+
+        let errors = interceptor.check_syntax("1 + 2 *").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 7..7);
+    }
+
+    #[test]
+    fn test_check_units() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert_eq!(
+            interceptor.check_units("1 foo + 2 bar"),
+            Err(vec![
+                (2..5, "Unknown unit: \"foo\"".to_string()),
+                (10..13, "Unknown unit: \"bar\"".to_string()),
+            ])
+        );
+        assert_eq!(
+            interceptor.check_units("(x + 1) furlong >> [km, parsec]"),
+            Err(vec![
+                (8..15, "Unknown unit: \"furlong\"".to_string()),
+                (24..30, "Unknown unit: \"parsec\"".to_string()),
+            ])
+        );
+        // Variables and categories are not units
+        assert_eq!(
+            interceptor.check_units("let x = 1 m in x + y >> :length"),
+            Ok(())
+        );
+        assert_eq!(interceptor.check_units("[1, 2] cm2 >> m2"), Ok(()));
+        assert!(interceptor.vars.is_empty());
+
+        interceptor.execute_command("x = 3 m").unwrap();
+        assert_eq!(interceptor.check_units("5 x + 2 m"), Ok(()));
+        assert_eq!(
+            interceptor.check_units("5 y"),
+            Err(vec![(2..3, "Unknown unit: \"y\"".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_unit_of_sub_expression() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.execute_command("(3 + 4) m == 7 m");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+        let result = interceptor.execute_command("(1 + 1) cm");
+        assert_eq!(result, Ok((Value::Number(0.02), "m".to_string())));
+        interceptor.execute_command("z = 5").unwrap();
+        let result = interceptor.execute_command("z m + 1 m");
+        assert_eq!(result, Ok((Value::Number(6.0), "m".to_string())));
+
+        let result = interceptor.execute_command("(3 m) cm");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot apply unit \"cm\" to a value of \"m\""
+        );
+    }
+
+    #[test]
+    fn test_precision_warning() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+nm = { name = "nanometer", symbol = "nm", factor = 1e-9 }
+ly = { name = "light-year", symbol = "ly", factor = 9460730472580800 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let result = interceptor.evaluate("1 ly >> nm").unwrap();
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("too large to be exact"));
+        let result = interceptor.evaluate("1 ly >> km").unwrap();
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_exchange_rates() {
+        let unit_definitions = toml::from_str(
+            r#"
+[currency]
+USD = { name = "US dollar", symbol = "$" }
+EUR = { name = "euro", symbol = "€", factor = 1.5 }
+JPY = { name = "yen", symbol = "¥", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor
+            .set_rates(HashMap::from([("EUR".to_string(), 1.25)]))
+            .unwrap();
+        let result = interceptor.execute_command("100 USD >> EUR");
+        assert_eq!(result, Ok((Value::Number(80.0), "EUR".to_string())));
+        let result = interceptor.execute_command("100 EUR >> USD");
+        assert_eq!(result, Ok((Value::Number(125.0), "USD".to_string())));
+
+        let result = interceptor.execute_command("100 USD >> JPY");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "No exchange rate for currency \"JPY\""
+        );
+
+        let err = interceptor
+            .set_rates(HashMap::from([("GBP".to_string(), 1.3)]))
+            .unwrap_err();
+        assert!(matches!(err, DefinitionError::UnknownCurrency(unit) if unit == "GBP"));
+        let err = interceptor
+            .set_rates(HashMap::from([("JPY".to_string(), 0.0)]))
+            .unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidFactor(unit, _) if unit == "JPY"));
+    }
+
+    #[test]
+    fn test_prefix_symbols() {
+        let unit_definitions = toml::from_str(
+            r#"
+[currency]
+USD = { name = "US dollar", symbol = "$" }
+GBP = { name = "pound sterling", symbol = "£", factor = 1.25 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("1").unwrap();
+        interceptor.execute_command("2").unwrap();
+        // `$N` is a recent result until `$` is a prefix symbol
+        let result = interceptor.execute_command("$2 + $");
+        assert_eq!(result, Ok((Value::Number(3.0), "".to_string())));
+        assert!(interceptor.execute_command("£10").is_err());
+
+        interceptor
+            .set_prefix_symbols(HashMap::from([
+                ("$".to_string(), "USD".to_string()),
+                ("£".to_string(), "GBP".to_string()),
+            ]))
+            .unwrap();
+        let result = interceptor.execute_command("$5 + $3");
+        assert_eq!(result, Ok((Value::Number(8.0), "USD".to_string())));
+        let result = interceptor.execute_command("$ * 2");
+        assert_eq!(result, Ok((Value::Number(16.0), "USD".to_string())));
+        let result = interceptor.execute_command("(-£10 + $2.5) >> GBP");
+        assert_eq!(result, Ok((Value::Number(-8.0), "GBP".to_string())));
+        let result = interceptor.execute_command("3 USD + $1");
+        assert_eq!(result, Ok((Value::Number(4.0), "USD".to_string())));
+        assert!(interceptor.execute_command("$ 5").is_err());
+
+        assert_eq!(
+            interceptor.set_prefix_symbols(HashMap::from([("€".to_string(), "EUR".to_string())])),
+            Err("Unknown unit: \"EUR\"".to_string())
+        );
+        assert_eq!(
+            interceptor.set_prefix_symbols(HashMap::from([("5".to_string(), "USD".to_string())])),
+            Err("Invalid prefix symbol \"5\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_factor_provider() {
+        struct MockRates;
+
+        impl FactorProvider for MockRates {
+            fn factor(&self, unit: &str) -> Option<f64> {
+                (unit == "EUR").then_some(1.25)
+            }
+        }
+
+        let unit_definitions: UnitDefinitions = toml::from_str(
+            r#"
+[currency]
+USD = { name = "US dollar", symbol = "$" }
+EUR = { name = "euro", symbol = "€", factor = 1.5 }
+JPY = { name = "yen", symbol = "¥", factor = 0.01 }
+"#,
+        )
+        .unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor
+            .set_factor_provider(Box::new(MockRates))
+            .unwrap();
+        let result = interceptor.execute_command("100 USD >> EUR");
+        assert_eq!(result, Ok((Value::Number(80.0), "EUR".to_string())));
+        // Units the provider has no factor for keep the one of their definition
+        let result = interceptor.execute_command("100 USD >> JPY");
+        assert_eq!(result, Ok((Value::Number(10000.0), "JPY".to_string())));
+
+        interceptor.reload(unit_definitions.clone()).unwrap();
+        let result = interceptor.execute_command("100 EUR >> USD");
+        assert_eq!(result, Ok((Value::Number(125.0), "USD".to_string())));
+
+        // Rates take precedence over the provider
+        interceptor
+            .set_rates(HashMap::from([("EUR".to_string(), 2.0)]))
+            .unwrap();
+        let result = interceptor.execute_command("100 EUR >> USD");
+        assert_eq!(result, Ok((Value::Number(200.0), "USD".to_string())));
+        interceptor.reset();
+        let result = interceptor.execute_command("100 EUR >> USD");
+        assert_eq!(result, Ok((Value::Number(125.0), "USD".to_string())));
+
+        let err = interceptor
+            .set_factor_provider(Box::new(HashMap::from([("JPY".to_string(), -1.0)])))
+            .unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidFactor(unit, _) if unit == "JPY"));
+        let result = interceptor.execute_command("100 EUR >> USD");
+        assert_eq!(result, Ok((Value::Number(125.0), "USD".to_string())));
+    }
+
+    #[test]
+    fn test_diff_functions() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let result = interceptor.execute_command("abs_diff(2 km, 1500 m)");
+        assert_eq!(result, Ok((Value::Number(500.0), "m".to_string())));
+        let result = interceptor.execute_command("abs_diff(1500 m, 2 km) >> km");
+        assert_eq!(result, Ok((Value::Number(0.5), "km".to_string())));
+        let (value, unit) = interceptor.execute_command("abs_diff(5 m, 4.9 m)").unwrap();
+        assert!(matches!(value, Value::Number(num) if (num - 0.1).abs() < 1e-12));
+        assert_eq!(unit, "m");
+
+        let result = interceptor.execute_command("rel_diff(2 km, 1600 m)");
+        assert_eq!(result, Ok((Value::Number(0.25), "".to_string())));
+        let result = interceptor.execute_command("rel_diff([1, 3] m, 2 m) * 100");
+        assert_eq!(result, Ok((Value::List(vec![50.0, 50.0]), "".to_string())));
+
+        let result = interceptor.execute_command("abs_diff(5 m, 2 sec)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot evaluate abs_diff(\"m\", \"sec\")"
+        );
+        let result = interceptor.execute_command("rel_diff(5 m)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Function \"rel_diff\" takes 2 arguments, got 1"
+        );
+        let result = interceptor.execute_command("foo(5 m)");
+        assert_eq!(result.unwrap_err()[0].1, "Unknown function \"foo\"");
+    }
+
+    #[test]
+    fn test_categories_for_base_unit() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[force]
+N = { name = "newton", symbol = "N" }
+
+[energy]
+J = { name = "joule", symbol = "J", derived = "N * m" }
+
+[torque]
+Nm = { name = "newton meter", symbol = "N·m", derived = "N * m" }
+"#,
+        )
+        .unwrap();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let both = vec!["energy".to_string(), "torque".to_string()];
+        assert_eq!(interceptor.categories_for_base_unit("J"), both);
+        assert_eq!(interceptor.categories_for_base_unit("Nm"), both);
+        assert_eq!(interceptor.categories_for_base_unit("m"), vec!["length"]);
+        assert!(interceptor.categories_for_base_unit("km").is_empty());
+        assert!(interceptor.categories_for_base_unit("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_eval_with_overrides() {
+        let unit_definitions = toml::from_str(
+            r#"
+[acceleration]
+mps2 = { name = "meter per second squared", symbol = "m/s²" }
+g = { name = "standard gravity", symbol = "g", factor = 9.81 }
+half_g = { name = "half gravity", symbol = "g/2", factor = 0.5, relative_to = "g" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let earth = HashMap::from([("g".to_string(), 9.81)]);
+        let moon = HashMap::from([("g".to_string(), 1.62)]);
+        let result = interceptor
+            .eval_with_overrides("2 g >> mps2", &earth)
+            .unwrap();
+        assert_eq!(result.value, Value::Number(19.62));
+        let result = interceptor
+            .eval_with_overrides("2 g >> mps2", &moon)
+            .unwrap();
+        assert_eq!(result.value, Value::Number(3.24));
+        let result = interceptor
+            .eval_with_overrides("2 half_g >> mps2", &moon)
+            .unwrap();
+        assert_eq!(result.value, Value::Number(1.62));
+
+        // The definitions are back in effect afterwards
+        let result = interceptor.evaluate("2 half_g >> mps2").unwrap();
+        assert_eq!(result.value, Value::Number(9.81));
+
+        let unknown = HashMap::from([("G".to_string(), 1.0)]);
+        let errors = interceptor
+            .eval_with_overrides("1 g", &unknown)
+            .unwrap_err();
+        assert_eq!(errors[0].message, "Unknown unit \"G\" to override");
+        let invalid = HashMap::from([("g".to_string(), -1.0)]);
+        assert!(interceptor.eval_with_overrides("1 g", &invalid).is_err());
+    }
+
+    #[test]
+    fn test_unitless_numbers() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        for (input, expected) in [
+            ("1 + 2 * 3", 7.0),
+            ("0.1 + 0.2", 0.1 + 0.2),
+            ("1 / 3 - 4", 1.0 / 3.0 - 4.0),
+            ("1e300 * 1e10", f64::INFINITY),
+        ] {
+            let result = interceptor.execute_command(input);
+            assert_eq!(
+                result,
+                Ok((Value::Number(expected), String::new())),
+                "{}",
+                input
+            );
+        }
+        // The same as applying the empty unit like any other unit
+        for num in [0.5, 1e-300, 123456789.123] {
+            let expected = interceptor.apply_unit(Value::Number(num), "").unwrap();
+            assert_eq!(interceptor.execute_command(&num.to_string()), Ok(expected));
+        }
+
+        interceptor.set_assumed_unit(Some("km")).unwrap();
+        let result = interceptor.execute_command("1.1");
+        assert_eq!(result, Ok((Value::Number(1100.0), "m".to_string())));
+    }
+
+    #[test]
+    fn test_power() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        for (input, expected) in [
+            ("2^10", 1024.0),
+            ("-2^2", -4.0),
+            ("2^3^2", 512.0),
+            ("4^-0.5", 0.5),
+        ] {
+            let result = interceptor.execute_command(input);
+            assert_eq!(
+                result,
+                Ok((Value::Number(expected), String::new())),
+                "{}",
+                input
+            );
+        }
+        let result = interceptor.execute_command("(3 m)^2");
+        assert_eq!(result, Ok((Value::Number(9.0), "m2".to_string())));
+        let result = interceptor.execute_command("(2 km)^2 >> m2");
+        assert_eq!(result, Ok((Value::Number(4e6), "m2".to_string())));
+
+        let result = interceptor.execute_command("(3 m)^4");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot raise \"m\" to the power of 4"
+        );
+        let result = interceptor.execute_command("2^(1 m)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Exponent must be dimensionless, found \"m\""
+        );
+    }
+
+    #[test]
+    fn test_strict_units() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.set_strict_units(true);
+
+        for input in ["5", "x = 5", "2 * 3 m", "[1 m, 2]"] {
+            assert!(interceptor.execute_command(input).is_err(), "{}", input);
+        }
+        let result = interceptor.execute_command("x = 5");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Number 5 has no unit, which strict mode requires"
+        );
+        for input in [
+            "5 m",
+            "2^10",
+            "(3 m)^2",
+            "[1, 2] m",
+            "(3 + 4) m",
+            "5 m + 10%",
+        ] {
+            assert!(interceptor.execute_command(input).is_ok(), "{}", input);
+        }
+        // Exponents are dimensionless, so they need no unit
+        let result = interceptor.execute_command("2^10");
+        assert_eq!(result, Ok((Value::Number(1024.0), String::new())));
+
+        interceptor.set_assumed_unit(Some("m")).unwrap();
+        assert!(interceptor.execute_command("5").is_ok());
+        interceptor.set_assumed_unit(None).unwrap();
+        interceptor.set_strict_units(false);
+        assert!(interceptor.execute_command("5").is_ok());
+    }
+
+    #[test]
+    fn test_canonical_unit() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+M = { name = "mile", symbol = "mi", factor = 1609.344 }
+
+[time]
+s = { name = "second", symbol = "sec" }
+min = { name = "minute", factor = 60 }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / s" }
+"#,
+        )
+        .unwrap();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert_eq!(interceptor.canonical_unit("mps"), Some("mps"));
+        assert_eq!(interceptor.canonical_unit("m/s"), Some("mps"));
+        assert_eq!(interceptor.canonical_unit("Meter per second"), Some("mps"));
+        assert_eq!(interceptor.canonical_unit(" sec "), Some("s"));
+        // Keys take precedence over symbols and names
+        assert_eq!(interceptor.canonical_unit("M"), Some("M"));
+        assert_eq!(interceptor.canonical_unit("mi"), Some("M"));
+        assert_eq!(interceptor.canonical_unit("furlong"), None);
+        assert_eq!(interceptor.canonical_unit(""), None);
+    }
+
+    #[test]
+    fn test_volume_conversions() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        // The derived `m3` is the base of the volume category, `cm3` and `L` are defined relative to it
+        for (input, expected) in [
+            ("1 m3 >> L", (1000.0, "L")),
+            ("1 L >> cm3", (1000.0, "cm3")),
+            ("250 cm3 >> L", (0.25, "L")),
+            ("1 L >> m3", (0.001, "m3")),
+            ("2 m * 3 m * 4 m >> L", (24000.0, "L")),
+            ("(2 m)^3 >> L", (8000.0, "L")),
+            ("1.5 L + 500 cm3 >> L", (2.0, "L")),
+        ] {
+            let result = interceptor.execute_command(input);
+            let expected = (Value::Number(expected.0), expected.1.to_string());
+            assert_eq!(result, Ok(expected), "{}", input);
+        }
+        let result = interceptor.execute_command("3 L / 1 m2 >> cm");
+        let (Value::Number(num), unit) = result.unwrap() else {
+            panic!("expected a number");
+        };
+        assert!((num - 0.3).abs() < 1e-12);
+        assert_eq!(unit, "cm");
+    }
+
+    #[test]
+    fn test_conversion_factor() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert_eq!(interceptor.conversion_factor("km", "m"), Ok(1000.0));
+        assert_eq!(interceptor.conversion_factor("cm", "km"), Ok(0.00001));
+        assert_eq!(interceptor.conversion_factor("L", "cm3"), Ok(1000.0));
+        assert_eq!(
+            interceptor.conversion_factor("km", "sec"),
+            Err("Cannot convert \"km\" to \"sec\", they measure different things".to_string())
+        );
+        assert_eq!(
+            interceptor.conversion_factor("kilo", "m"),
+            Err("Unknown unit \"kilo\", did you mean km, kmph?".to_string())
+        );
+        assert_eq!(
+            interceptor.conversion_factor("m", "furlong"),
+            Err("Unknown unit \"furlong\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reduce_unit() {
+        let unit_definitions = toml::from_str(
+            r#"
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+g = { name = "gram", symbol = "g", factor = 0.001 }
+
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[time]
+s = { name = "second", symbol = "s" }
+hour = { name = "hour", symbol = "h", factor = 3600 }
+
+[time_squared]
+s2 = { name = "square second", symbol = "s²", derived = "s * s" }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / s" }
+
+[force]
+N = { name = "newton", symbol = "N", derived = "kg * m / s2" }
+
+[frequency]
+Hz = { name = "hertz", symbol = "Hz", derived = "1 / s" }
+"#,
+        )
+        .unwrap();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert_eq!(interceptor.reduce_unit("kg * m / s2"), Ok("N".to_string()));
+        assert_eq!(interceptor.reduce_unit("g*km/s2"), Ok("N".to_string()));
+        assert_eq!(interceptor.reduce_unit("m / s"), Ok("mps".to_string()));
+        assert_eq!(interceptor.reduce_unit("km / hour"), Ok("mps".to_string()));
+        assert_eq!(interceptor.reduce_unit("Hz * s"), Ok("".to_string()));
+        assert_eq!(
+            interceptor.reduce_unit("kg * m"),
+            Err("No unit is derived as \"kg * m\"".to_string())
+        );
+        assert_eq!(
+            interceptor.reduce_unit("kg ** m"),
+            Err("No unit is derived as \"kg ** m\"".to_string())
+        );
+        assert_eq!(
+            interceptor.reduce_unit("kg * x"),
+            Err("Unknown unit: \"x\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_entry_unit_results() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let result = interceptor.execute_command("5 cm + 3 cm");
+        assert_eq!(result, Ok((Value::Number(0.08), "m".to_string())));
+
+        interceptor.set_entry_unit_results(true);
+        for (input, expected) in [
+            ("5 cm + 3 cm", (8.0, "cm")),
+            ("2 km + 500 m", (2.5, "km")),
+            ("x = 90 min + 30 min", (120.0, "min")),
+            // Without a unit entered, results stay in the base unit
+            ("x / 2", (3600.0, "sec")),
+            ("5 cm + 3 cm >> m", (0.08, "m")),
+            ("2 m * 3 km", (6000.0, "m2")),
+            ("3 + 4", (7.0, "")),
+        ] {
+            let result = interceptor.execute_command(input);
+            let expected = (Value::Number(expected.0), expected.1.to_string());
+            assert_eq!(result, Ok(expected), "{}", input);
+        }
+        let result = interceptor.execute_command("[1, 2] km + 1 km");
+        assert_eq!(result, Ok((Value::List(vec![2.0, 3.0]), "km".to_string())));
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert!(interceptor.is_function_definition("fn speed(d, t) = d / t"));
+        assert!(!interceptor.is_function_definition("speed(1 m, 1 sec)"));
+        assert_eq!(
+            interceptor.define_function("fn speed(d, t) = d / t"),
+            Ok("speed".to_string())
+        );
+        let result = interceptor.execute_command("speed(360 km, 2 hour) >> kmph");
+        assert_eq!(result, Ok((Value::Number(180.0), "kmph".to_string())));
+        interceptor
+            .define_function("fn area(w, h) = w * h")
+            .unwrap();
+        let result = interceptor.execute_command("area(2 m, 300 cm)");
+        assert_eq!(result, Ok((Value::Number(6.0), "m2".to_string())));
+
+        // The parameters are the only variables the body sees
+        interceptor.execute_command("d = 1 m").unwrap();
+        interceptor
+            .define_function("fn farther(x) = x + d")
+            .unwrap();
+        let result = interceptor.execute_command("farther(2 m)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot find variable \"d\" in scope"
+        );
+        let result = interceptor.execute_command("speed(d, 2 sec)");
+        assert_eq!(result, Ok((Value::Number(0.5), "mps".to_string())));
+        assert_eq!(
+            interceptor.execute_command("d"),
+            Ok((Value::Number(1.0), "m".to_string()))
+        );
+
+        let result = interceptor.execute_command("speed(1 m)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Function \"speed\" takes 2 arguments, got 1"
+        );
+        // A definition is no result
+        interceptor.execute_command("fn twice(x) = 2 * x").unwrap();
+        let result = interceptor.execute_command("twice($)");
+        assert_eq!(result, Ok((Value::Number(2.0), "m".to_string())));
+        let result = interceptor.define_function("fn abs_diff(a, b) = a - b");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot redefine built-in function \"abs_diff\""
+        );
+        let result = interceptor.define_function("fn f(x, x) = x");
+        assert_eq!(result.unwrap_err()[0].1, "Parameter \"x\" is repeated");
+        assert!(interceptor.define_function("1 + 2").is_err());
+
+        interceptor
+            .define_function("fn forever(x) = forever(x)")
+            .unwrap();
+        let result = interceptor.execute_command("forever(1)");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Calls of function \"forever\" nest deeper than 32"
+        );
+        // A condition ends the recursion as only the branch it selects is evaluated
+        interceptor
+            .define_function("fn fact(n) = n > 1 ? n * fact(n - 1) : 1")
+            .unwrap();
+        let result = interceptor.execute_command("fact(5)");
+        assert_eq!(result, Ok((Value::Number(120.0), "".to_string())));
+
+        interceptor.reset();
+        let result = interceptor.execute_command("area(2 m, 3 m)");
+        assert_eq!(result.unwrap_err()[0].1, "Unknown function \"area\"");
+
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+s = { name = "second", symbol = "s" }
+
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / s" }
+
+[specific_energy]
+m2ps2 = { name = "square meter per square second", symbol = "m²/s²", derived = "mps * mps" }
+
+[energy]
+J = { name = "joule", symbol = "J", derived = "kg * m2ps2" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        // The parameter `m` is a variable in the body, though it's also a unit
+        interceptor
+            .execute_command("fn kinetic(m, v) = 0.5 * m * v^2")
+            .unwrap();
+        let result = interceptor.execute_command("kinetic(2 kg, 3 mps)");
+        assert_eq!(result, Ok((Value::Number(9.0), "J".to_string())));
     }
-}
-#[cfg(test)]
-mod tests {
-    use crate::unit_definition::UnitDefinitions;
 
-    use super::*;
+    #[test]
+    fn test_is_assignment() {
+        let unit_definitions = UnitDefinitions::default();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        assert!(interceptor.is_assignment("x = 2"));
+        assert!(interceptor.is_assignment("a = b = 5"));
+        assert!(!interceptor.is_assignment("x == 2"));
+        assert!(!interceptor.is_assignment("let x = 2 in x"));
+        assert!(!interceptor.is_assignment("x ="));
+    }
 
     #[test]
-    fn test_eval_without_unit() {
-        let expr = "1 + 2 * 3";
+    fn test_debug_parse() {
         let unit_definitions = UnitDefinitions::default();
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let tree = interceptor.debug_parse("1 + 2 * 3").unwrap();
+        assert_eq!(
+            tree,
+            "To\n  Add\n    Num 1\n    Mul\n      Num 2\n      Num 3\n"
+        );
+
+        let tree = interceptor.debug_parse("x = -[1, 2] km").unwrap();
+        assert_eq!(
+            tree,
+            "Assign x\n  Neg\n    List km\n      Num 1\n      Num 2\n"
+        );
+        assert!(interceptor.debug_parse("1 +").is_err());
+    }
+
+    #[test]
+    fn test_convert_to_category() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert_eq!(result, Ok((7.0, "".to_string())));
+        let result = interceptor.execute_command("360 km / 2 hour >> :speed");
+        assert_eq!(result, Ok((Value::Number(50.0), "mps".to_string())));
+        let result = interceptor.execute_command("2 km >> :area");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot convert \"m\" to category \"area\""
+        );
+        let result = interceptor.execute_command("2 km >> :mass");
+        assert_eq!(result.unwrap_err()[0].1, "Unknown category \"mass\"");
     }
 
     #[test]
-    fn test_eval_with_unit_definitions() {
-        let expr = "1 m + 2 cm";
+    fn test_stack() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("1 m").unwrap();
+        interceptor.execute_command("2 km >> km").unwrap();
+        assert!(interceptor.execute_command("1 m + 1 sec").is_err());
+        interceptor.execute_command("3 sec").unwrap();
+        assert_eq!(
+            interceptor.stack().cloned().collect::<Vec<_>>(),
+            vec![
+                (Value::Number(3.0), "sec".to_string()),
+                (Value::Number(2.0), "km".to_string()),
+                (Value::Number(1.0), "m".to_string()),
+            ]
+        );
+
+        let result = interceptor.execute_command("$2 + $3");
+        assert_eq!(result, Ok((Value::Number(2001.0), "m".to_string())));
+        let result = interceptor.execute_command("$1 == $");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+        let result = interceptor.execute_command("$9");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot find variable \"$9\" in scope"
+        );
+        let result = interceptor.execute_command("ans");
+        assert_eq!(result, Ok((Value::Number(1.0), "".to_string())));
+
+        for (command, name) in [
+            ("$1 = 5", "$1"),
+            ("$2 = 1 m", "$2"),
+            ("ans = 5", "ans"),
+            ("$ = 5", "$"),
+        ] {
+            let result = interceptor.execute_command(command);
+            assert_eq!(
+                result.unwrap_err()[0].1,
+                format!("Cannot assign to reserved variable \"{}\"", name)
+            );
+        }
+        let result = interceptor.execute_command("answer = 5");
+        assert_eq!(result, Ok((Value::Number(5.0), "".to_string())));
+
+        interceptor.reset();
+        assert_eq!(interceptor.stack().count(), 0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 5 m").unwrap();
+        interceptor.execute_command("x * 2").unwrap();
+        interceptor.reset();
+
+        for name in ["x", "$"] {
+            let result = interceptor.execute_command(name);
+            assert_eq!(
+                result.unwrap_err()[0].1,
+                format!("Cannot find variable \"{}\" in scope", name)
+            );
+        }
+        let result = interceptor.execute_command("1 km >> m");
+        assert_eq!(result, Ok((Value::Number(1000.0), "m".to_string())));
+    }
+
+    #[test]
+    fn test_diagnostics_severity() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
-cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+nm = { name = "nanometer", symbol = "nm", factor = 1e-9 }
+ly = { name = "light-year", symbol = "ly", factor = 9460730472580800 }
 "#,
         )
         .unwrap();
+
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert_eq!(result, Ok((1.02, "m".to_string())));
+        let (result, diagnostics) = interceptor.evaluate_with_diagnostics("1 ly >> nm");
+        assert!(result.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert!(diagnostics[0].render("1 ly >> nm").starts_with("Warning: "));
+
+        let (result, diagnostics) = interceptor.evaluate_with_diagnostics("1 x");
+        assert!(result.is_none());
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].render("1 x"),
+            "Error: Unknown unit: \"x\"\n  | 1 x\n  | ^^^"
+        );
     }
 
     #[test]
-    fn test_eval_with_unit_map() {
-        let expr = "1 cm2 + 2 cm * 3cm";
+    fn test_unicode_units() {
         let unit_definitions = toml::from_str(
             r#"
-[length]
-cm = { name = "center meter", symbol = "cm" }
+[resistance]
+"Ω" = { name = "ohm", symbol = "Ω" }
+"kΩ" = { name = "kiloohm", symbol = "kΩ", factor = 1000 }
+
+[temperature]
+"°C" = { name = "degree Celsius", symbol = "°C" }
 
 [area]
-cm2 = { name = "square center meter", symbol = "cm2", derived = "cm * cm" }
+"m²" = { name = "square meter", symbol = "m²" }
 "#,
         )
         .unwrap();
 
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert_eq!(result, Ok((7.0, "cm2".to_string())));
+        let result = interceptor.execute_command("4.7 kΩ + 300 Ω >> kΩ");
+        assert_eq!(result, Ok((Value::Number(5.0), "kΩ".to_string())));
+        let result = interceptor.execute_command("20°C*2");
+        assert_eq!(result, Ok((Value::Number(40.0), "°C".to_string())));
+        let result = interceptor.execute_command("3 m²-1 m²");
+        assert_eq!(result, Ok((Value::Number(2.0), "m²".to_string())));
+        let result = interceptor.execute_command("größe = 2 Ω");
+        assert_eq!(result, Ok((Value::Number(2.0), "Ω".to_string())));
     }
 
     #[test]
-    fn test_eval_incompatible_units() {
-        let expr = "1 m + 2 sec";
+    fn test_operations_for() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
 
 [time]
-sec = { name = "second", symbol = "s" }
+s = { name = "second", symbol = "s" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
+
+[speed]
+mps = { name = "meter per second", symbol = "m/s", derived = "m / s" }
 "#,
         )
         .unwrap();
 
-        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "Cannot evaluate \"m\" + \"sec\"");
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let operation = |op: &str, other: &str, result: &str| {
+            (op.to_string(), other.to_string(), result.to_string())
+        };
+        assert_eq!(
+            interceptor.operations_for("m"),
+            vec![
+                operation("*", "m", "m2"),
+                operation("/", "mps", "s"),
+                operation("/", "s", "mps"),
+            ]
+        );
+        assert!(interceptor.operations_for("kg").is_empty());
     }
 
     #[test]
-    fn test_eval_invalid_unit_multiplication() {
-        let expr = "2 m * 3 sec";
+    fn test_conversion_matrix() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interpretor = Interpretor::new(&unit_definitions).unwrap();
+
+        let (units, matrix) = interpretor.conversion_matrix("length").unwrap();
+        assert_eq!(units, vec!["m", "cm", "km"]);
+        for (i, row) in matrix.iter().enumerate() {
+            assert_eq!(row.len(), units.len());
+            assert_eq!(row[i], 1.0);
+        }
+        assert_eq!(matrix[0][1], 100.0);
+        assert_eq!(matrix[2][0], 1000.0);
+
+        assert_eq!(
+            interpretor.conversion_matrix("mass").unwrap_err(),
+            "Unknown category \"mass\""
+        );
+
+        // A unit missing from the unit table is an error rather than a panic
+        let length = interpretor
+            .unit_definitions
+            .categories
+            .get_mut("length")
+            .unwrap();
+        let km = length["km"].clone();
+        length.insert("x".to_string(), km);
+        assert_eq!(
+            interpretor.conversion_matrix("length").unwrap_err(),
+            "Unknown unit: \"x\""
+        );
+    }
+
+    #[test]
+    fn test_quotient_symbols() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
 
 [time]
 sec = { name = "second", symbol = "s" }
+hour = { name = "hour", symbol = "h", factor = 3600 }
+
+[speed]
+mps = { name = "meter per second", symbol = "mps", derived = "m / sec" }
 "#,
         )
         .unwrap();
+
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "Cannot evaluate \"m\" * \"sec\"");
+        let (value, unit) = interceptor.execute_command("360 km / 2 hour").unwrap();
+        let options = FormatOptions {
+            unit_labels: interceptor.quotient_symbols(),
+            ..Default::default()
+        };
+        assert_eq!(format_result(&value, &unit, &options), "50 m/s");
     }
 
     #[test]
-    fn test_eval_unknown_variable() {
-        let expr = "x + 2";
-        let unit_definitions = UnitDefinitions::default();
+    fn test_to_base_and_from_base() {
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
+
+[temperature]
+degC = { name = "degree Celsius", symbol = "°C" }
+K = { name = "kelvin", symbol = "K", offset = -273.15 }
+"#,
+        )
+        .unwrap();
+        let interpretor = Interpretor::new(&unit_definitions).unwrap();
+
+        assert_eq!(
+            interpretor.to_base(2.5, "km"),
+            Ok((2500.0, "m".to_string()))
+        );
+        assert_eq!(
+            interpretor.from_base(2500.0, "km"),
+            Ok((2.5, "km".to_string()))
+        );
+        assert_eq!(interpretor.to_base(3.0, "m"), Ok((3.0, "m".to_string())));
+
+        let (base, base_unit) = interpretor.to_base(300.0, "K").unwrap();
+        assert_eq!(base_unit, "degC");
+        let (value, unit) = interpretor.from_base(base, "K").unwrap();
+        assert!((value - 300.0).abs() < 1e-9);
+        assert_eq!(unit, "K");
+
+        assert_eq!(
+            interpretor.to_base(1.0, "parsec").unwrap_err().message,
+            "Unknown unit: \"parsec\""
+        );
+        assert!(interpretor.from_base(1.0, "parsec").is_err());
+    }
+
+    #[test]
+    fn test_offset_conversion() {
+        let unit_definitions = toml::from_str(
+            r#"
+[temperature]
+degC = { name = "degree Celsius", symbol = "°C" }
+degF = { name = "degree Fahrenheit", symbol = "°F", factor = "5/9", offset = -17.77777777777778 }
+K = { name = "kelvin", symbol = "K", offset = -273.15 }
+"#,
+        )
+        .unwrap();
+
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "Cannot find variable \"x\" in scope");
+        let (value, unit) = interceptor.execute_command("212 degF >> degC").unwrap();
+        assert_eq!(
+            (value.to_string(), unit.as_str()),
+            ("100".to_string(), "degC")
+        );
+        let (value, unit) = interceptor.execute_command("-40 degC >> degF").unwrap();
+        assert_eq!(
+            (value.to_string(), unit.as_str()),
+            ("-40".to_string(), "degF")
+        );
+        let (value, _) = interceptor.execute_command("0 K >> degF").unwrap();
+        let Value::Number(num) = value else {
+            panic!("expected a number, found {:?}", value);
+        };
+        assert!((num - -459.67).abs() < 1e-9);
+
+        let result = interceptor.execute_command("-10 degC >> K");
+        assert_eq!(result, Ok((Value::Number(263.15), "K".to_string())));
+        let result = interceptor.execute_command("-40 degF >> degC");
+        assert_eq!(result, Ok((Value::Number(-40.0), "degC".to_string())));
+
+        let result = interceptor.execute_command("300 K >> [degC, K]");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot split into unit \"K\" which has an offset"
+        );
     }
 
     #[test]
-    fn test_eval_let_with_incompatible_units() {
+    fn test_negative_offset_readings() {
+        let unit_definitions = toml::from_str(
+            r#"
+[temperature]
+K = { name = "kelvin", symbol = "K" }
+degC = { name = "degree Celsius", symbol = "°C", offset = 273.15 }
+degF = { name = "degree Fahrenheit", symbol = "°F", factor = "5/9", offset = 255.3722222222222 }
+"#,
+        )
+        .unwrap();
+
+        // Kelvin is the base unit here, so negative readings in the other units are above zero
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let (value, unit) = interceptor.execute_command("-40 degC >> degF").unwrap();
+        let Value::Number(num) = value else {
+            panic!("expected a number");
+        };
+        assert!((num - -40.0).abs() < 1e-9);
+        assert_eq!(unit, "degF");
+        let result = interceptor.execute_command("-10 degC >> K");
+        assert_eq!(result, Ok((Value::Number(263.15), "K".to_string())));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_restore_stored_vars() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 1000 }
 
 [time]
 sec = { name = "second", symbol = "s" }
@@ -326,94 +5216,478 @@ sec = { name = "second", symbol = "s" }
         )
         .unwrap();
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        interceptor.execute_command("x = 5m").unwrap();
-        let result = interceptor.execute_command("x + 3 sec");
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "Cannot evaluate \"m\" + \"sec\"");
+        interceptor.execute_command("x = (2 km >> km)").unwrap();
+        interceptor.execute_command("t = 3 sec").unwrap();
+        interceptor.execute_command("n = 4").unwrap();
+        let stored = interceptor.stored_vars();
+        assert_eq!(
+            stored["x"],
+            StoredVar {
+                value: Value::Number(2000.0),
+                base_unit: "m".to_string()
+            }
+        );
+        assert!(!stored.contains_key("$"));
+        let json = serde_json::to_string(&stored).unwrap();
+
+        // The next session has feet as the base unit and no time category
+        let unit_definitions = toml::from_str(
+            r#"
+[length]
+ft = { name = "foot", symbol = "ft" }
+m = { name = "meter", symbol = "m", factor = "1250/381" }
+km = { name = "kilometer", symbol = "km", factor = 1000, relative_to = "m" }
+"#,
+        )
+        .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let dropped = interceptor.restore_vars(serde_json::from_str(&json).unwrap());
+        assert_eq!(dropped, vec!["t".to_string()]);
+
+        let (value, unit) = interceptor.execute_command("x >> km").unwrap();
+        assert_eq!(unit, "km");
+        assert!(matches!(value, Value::Number(num) if (num - 2.0).abs() < 1e-12));
+        let result = interceptor.execute_command("n * 2");
+        assert_eq!(result, Ok((Value::Number(8.0), "".to_string())));
+        assert!(interceptor.execute_command("t").is_err());
     }
 
     #[test]
-    fn test_complex_expressions() {
+    fn test_eval_with() {
         let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
         let unit_definitions = toml::from_str(&str).unwrap();
-
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
         interceptor.execute_command("x = 2 m").unwrap();
-        interceptor.execute_command("y = 3 cm").unwrap();
-        let result = interceptor.execute_command("x + y * 4").unwrap();
-        assert_eq!(result, (2.12, "m".to_string()));
 
-        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command("((1km + 2cm) * 2 * 3m + 4cm2) * 5m + 6m3");
-        assert_eq!(result, Ok((30006.602, "m3".to_string())));
+        let vars = HashMap::from([
+            ("d".to_string(), (3.0, "km".to_string())),
+            ("x".to_string(), (5.0, "cm".to_string())),
+        ]);
+        let result = interceptor.eval_with("d + x >> m", &vars).unwrap();
+        assert_eq!(result.value, Value::Number(3000.05));
+        assert_eq!(result.unit_key, "m");
+        let result = interceptor.eval_with("y = d * 2", &vars).unwrap();
+        assert_eq!(result.value, Value::Number(6000.0));
 
-        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command("360 km / 2hour");
-        assert_eq!(result, Ok((50.0, "mps".to_string())));
+        // Nothing is stored in the interpretor
+        assert!(interceptor.execute_command("d").is_err());
+        assert!(interceptor.execute_command("y").is_err());
+        let result = interceptor.execute_command("x");
+        assert_eq!(result, Ok((Value::Number(2.0), "m".to_string())));
+
+        let vars = HashMap::from([("d".to_string(), (3.0, "parsec".to_string()))]);
+        let errors = interceptor.eval_with("d", &vars).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Unknown unit \"parsec\" of variable \"d\""
+        );
     }
 
     #[test]
-    fn should_show_error_for_invalid_expression() {
-        let unit_definitions = UnitDefinitions::default();
+    fn test_trace() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command("1 + 2 *");
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "found end of input at 7..7");
+
+        let (result, steps) = interceptor.trace("360 km / 2 hour >> kmph").unwrap();
+        assert_eq!(result.unit_key, "kmph");
+        let step = |operation, input_units: &[&str], output_unit: &str, factor| TraceStep {
+            operation,
+            input_units: input_units.iter().map(|unit| unit.to_string()).collect(),
+            output_unit: output_unit.to_string(),
+            factor,
+        };
+        assert_eq!(
+            steps[..3],
+            [
+                step(TraceOperation::ApplyUnit, &["km"], "m", 1000.0),
+                step(TraceOperation::ApplyUnit, &["hour"], "sec", 3600.0),
+                step(TraceOperation::Divide, &["m", "sec"], "mps", 1.0),
+            ]
+        );
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[3].operation, TraceOperation::Convert);
+        assert_eq!(steps[3].output_unit, "kmph");
+        assert!((steps[3].factor - 3.6).abs() < 1e-12);
+
+        // The trace doesn't change the result, and arithmetic without units has no steps
+        let result = interceptor.execute_command("360 km / 2 hour >> kmph");
+        assert_eq!(result, Ok((Value::Number(180.0), "kmph".to_string())));
+        let (result, steps) = interceptor.trace("2 * 3").unwrap();
+        assert_eq!(result.value, Value::Number(6.0));
+        assert!(steps.is_empty());
     }
 
     #[test]
-    fn test_to_expr() {
-        let expr = "1 m >> cm";
+    fn test_bracketed_unit() {
         let unit_definitions = toml::from_str(
             r#"
-[length]
-m = { name = "meter", symbol = "m" }
-cm = { name = "centimeter", symbol = "cm", factor = 0.01 }
+[time]
+s = { name = "second", symbol = "s" }
+min = { name = "minute", symbol = "min", factor = 60 }
 "#,
         )
         .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("s = 3").unwrap();
+        interceptor.execute_command("k = 2").unwrap();
+
+        // `s` is both a unit and a variable, the unit is preferred
+        let result = interceptor.execute_command("5 s");
+        assert_eq!(result, Ok((Value::Number(5.0), "s".to_string())));
+        let result = interceptor.execute_command("5 [s]");
+        assert_eq!(result, Ok((Value::Number(5.0), "s".to_string())));
+        let result = interceptor.execute_command("5 * s");
+        assert_eq!(result, Ok((Value::Number(15.0), "".to_string())));
+        let result = interceptor.execute_command("2 [ min ] >> s");
+        assert_eq!(result, Ok((Value::Number(120.0), "s".to_string())));
+
+        // `k` is only a variable
+        let result = interceptor.execute_command("5 k");
+        assert_eq!(result, Ok((Value::Number(10.0), "".to_string())));
+        let result = interceptor.execute_command("5 [k]");
+        assert_eq!(result.unwrap_err()[0].1, "Unknown unit: \"k\"");
+        let result = interceptor.execute_command("5 h");
+        assert_eq!(result.unwrap_err()[0].1, "Unknown unit: \"h\"");
+    }
 
+    #[test]
+    fn test_expect_unit() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert_eq!(result, Ok((100.0, "cm".to_string())));
+
+        let result = interceptor.expect_unit("2 km + 30 m", "m").unwrap();
+        assert_eq!(result.value, Value::Number(2030.0));
+        assert_eq!(result.category, "length");
+        assert!(interceptor.expect_unit("5 cm >> cm", "km").is_ok());
+
+        let errors = interceptor.expect_unit("3 hour", "m").unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "Expected a value convertible to \"m\", got \"sec\""
+        );
+        assert!(interceptor.expect_unit("3", "m").is_err());
+
+        // A failed check changes nothing, as if the input wasn't evaluated
+        interceptor.execute_command("x = 5 m").unwrap();
+        assert!(interceptor.expect_unit("x = 2 hour", "m").is_err());
+        assert_eq!(interceptor.stack().count(), 3);
+        assert_eq!(
+            interceptor.execute_command("$"),
+            Ok((Value::Number(5.0), "m".to_string()))
+        );
+        assert_eq!(
+            interceptor.execute_command("x"),
+            Ok((Value::Number(5.0), "m".to_string()))
+        );
+
+        assert_eq!(
+            interceptor.expect_unit("3 m", "foo").unwrap_err()[0].message,
+            "Unknown unit: \"foo\""
+        );
     }
 
     #[test]
-    fn test_to_expr_with_incompatible_units() {
-        let expr = "1 m >> sec";
+    fn test_dimension_of() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 3 m").unwrap();
+
+        let speed = Dimension {
+            unit_key: "mps".to_string(),
+            category: "speed".to_string(),
+        };
+        assert_eq!(
+            interceptor.dimension_of("360 km / 2 hour"),
+            Ok(speed.clone())
+        );
+        assert_eq!(interceptor.dimension_of("x / 0 sec"), Ok(speed.clone()));
+        assert_eq!(interceptor.dimension_of("1 m / 0 sec"), Ok(speed));
+        assert_eq!(
+            interceptor.dimension_of("x * 2 m >> cm2").unwrap().category,
+            "area"
+        );
+        assert_eq!(
+            interceptor.dimension_of("2 * 3"),
+            Ok(Dimension {
+                unit_key: String::new(),
+                category: String::new()
+            })
+        );
+        assert!(interceptor.dimension_of("x + 1 sec").is_err());
+        assert_eq!(
+            interceptor
+                .dimension_of("1e300 m * 1e300 m")
+                .unwrap()
+                .category,
+            "area"
+        );
+        // Values that fail to evaluate still have a dimension
+        assert_eq!(
+            interceptor
+                .dimension_of("[1, 2] m + [1, 2, 3] m")
+                .unwrap()
+                .unit_key,
+            "m"
+        );
+        assert_eq!(
+            interceptor
+                .dimension_of("9999-12-31 + 1e300 sec")
+                .unwrap()
+                .unit_key,
+            DATETIME_UNIT
+        );
+
+        // Nothing is assigned or recorded
+        interceptor.dimension_of("y = 2 m").unwrap();
+        assert!(interceptor.execute_command("y").is_err());
+        assert_eq!(interceptor.stack().count(), 1);
+    }
+
+    #[test]
+    fn test_percent() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        interceptor.execute_command("x = 200 cm").unwrap();
+
+        let result = interceptor.execute_command("x + 10%");
+        assert_eq!(result, Ok((Value::Number(2.2), "m".to_string())));
+        let result = interceptor.execute_command("x - 10% >> cm");
+        assert_eq!(result, Ok((Value::Number(180.0), "cm".to_string())));
+        let result = interceptor.execute_command("200 + 10%");
+        assert_eq!(result, Ok((Value::Number(220.0), "".to_string())));
+        let result = interceptor.execute_command("50 - 10 %");
+        assert_eq!(result, Ok((Value::Number(45.0), "".to_string())));
+
+        // Elsewhere a percentage is just a fraction
+        let result = interceptor.execute_command("x * 25%");
+        assert_eq!(result, Ok((Value::Number(0.5), "m".to_string())));
+        let result = interceptor.execute_command("10% + 1");
+        assert_eq!(result, Ok((Value::Number(1.1), "".to_string())));
+    }
+
+    #[test]
+    fn test_assumed_unit() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let result = interceptor.execute_command("5 + 3");
+        assert_eq!(result, Ok((Value::Number(8.0), "".to_string())));
+
+        interceptor.set_assumed_unit(Some("m")).unwrap();
+        let result = interceptor.execute_command("5 + 3");
+        assert_eq!(result, Ok((Value::Number(8.0), "m".to_string())));
+        let result = interceptor.execute_command("5 + 30 cm");
+        assert_eq!(result, Ok((Value::Number(5.3), "m".to_string())));
+
+        interceptor.set_assumed_unit(None).unwrap();
+        let result = interceptor.execute_command("5 + 3");
+        assert_eq!(result, Ok((Value::Number(8.0), "".to_string())));
+
+        assert_eq!(
+            interceptor.set_assumed_unit(Some("parsec")),
+            Err("Unknown unit: \"parsec\"".to_string())
+        );
+        interceptor.set_assumed_unit(Some("cm")).unwrap();
+        interceptor.reset();
+        assert_eq!(interceptor.assumed_unit(), None);
+    }
+
+    #[test]
+    fn test_prefix_fallback() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
+km = { name = "kilometer", symbol = "km", factor = 999 }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
 
 [time]
-sec = { name = "sec", symbol = "s" }
+s = { name = "second", symbol = "s" }
+min = { name = "minute", symbol = "min", factor = 60 }
 "#,
         )
         .unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+        assert!(interceptor.execute_command("3 mm").is_err());
+
+        interceptor.set_prefix_fallback(true);
+        let result = interceptor.execute_command("3 mm");
+        assert_eq!(result, Ok((Value::Number(0.003), "m".to_string())));
+        let result = interceptor.execute_command("1500 us >> ms");
+        assert_eq!(result, Ok((Value::Number(1.5), "ms".to_string())));
+        let result = interceptor.execute_command("2 Mm >> m");
+        assert_eq!(result, Ok((Value::Number(2e6), "m".to_string())));
+
+        // The explicit definition wins over the prefix
+        let result = interceptor.execute_command("1 km");
+        assert_eq!(result, Ok((Value::Number(999.0), "m".to_string())));
+        let result = interceptor.execute_command("1 min");
+        assert_eq!(result, Ok((Value::Number(60.0), "s".to_string())));
+
+        // Derived units don't take prefixes
+        assert!(interceptor.execute_command("1 km2").is_err());
+        assert_eq!(
+            interceptor.check_units("1 nm + 2 km2").unwrap_err()[0].0,
+            9..12
+        );
+    }
 
+    #[test]
+    fn test_date_arithmetic() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr);
-        assert!(result.is_err());
-        let errors = result.unwrap_err();
-        assert_eq!(errors[0].1, "Cannot convert to unit \"sec\"");
+        let options = FormatOptions::default();
+        let mut eval = |command| {
+            let (value, unit) = interceptor.execute_command(command).unwrap();
+            format_result(&value, &unit, &options)
+        };
+
+        assert_eq!(eval("2024-01-01 + 90 day"), "2024-03-31");
+        assert_eq!(eval("2 hour + 2024-01-01T23:00"), "2024-01-02T01:00:00");
+        assert_eq!(eval("2024-03-01 - 1 day"), "2024-02-29");
+        assert_eq!(eval("2024-03-01 - 2024-02-01 >> day"), "29 day");
+        assert_eq!(eval("9999-12-31 + 86399 sec"), "9999-12-31T23:59:59");
+        assert_eq!(eval("2024-01-01 < 2024-01-02"), "1");
+
+        let result = interceptor.execute_command("2024-01-01 + 1 m");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot evaluate 1704067200 \"datetime\" + 1 \"m\""
+        );
+        assert!(interceptor.execute_command("1 day - 2024-01-01").is_err());
+        let errors = interceptor
+            .execute_command("2023-02-29 + 1 day")
+            .unwrap_err();
+        assert_eq!(errors[0].0, 0..10);
+
+        for (input, op) in [
+            ("2024-01-01 * 2", "*"),
+            ("-2024-01-01", "-"),
+            ("2024-01-02 / 2024-01-01", "/"),
+            ("2024-01-01 ^ 2", "^"),
+        ] {
+            let result = interceptor.execute_command(input);
+            assert_eq!(
+                result.unwrap_err()[0].1,
+                format!("Cannot apply \"{}\" to a date", op),
+                "{}",
+                input
+            );
+        }
+        for input in ["2024-01-01 + 1e300 sec", "2024-01-01 - 3000 * 365 day"] {
+            let result = interceptor.execute_command(input);
+            assert_eq!(
+                result.unwrap_err()[0].1,
+                "Date is out of range, dates go from 0000-01-01 to 9999-12-31",
+                "{}",
+                input
+            );
+        }
     }
 
     #[test]
-    fn test_float_numbers() {
-        let expr = "1.2 m + 0.45m";
+    fn test_eval_reader() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+        let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
+
+        let input = "x = 2 m\nx + 50 cm >> cm\n1 m + 1 sec\n$ * 2\n";
+        let mut output = Vec::new();
+        let summary = interceptor
+            .eval_reader(input.as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(summary, (3, 4));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2 m\n250 cm\nError: Cannot evaluate 1 \"m\" + 1 \"sec\"\n5 m\n"
+        );
+
+        interceptor.set_format_options(FormatOptions {
+            precision: Some(0),
+            rounding: crate::RoundingMode::HalfUp,
+            ..Default::default()
+        });
+        let mut output = Vec::new();
+        interceptor
+            .eval_reader("2.5 m\n-2.5 m\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "3 m\n-3 m\n");
+    }
+
+    #[test]
+    fn test_search_units() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let unit_definitions = toml::from_str(&str).unwrap();
+
+        let interceptor = Interpretor::new(&unit_definitions).unwrap();
+        let keys = interceptor
+            .search_units("meter")
+            .into_iter()
+            .map(|(key, _, _)| key)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec!["m", "mps", "cm", "cm2", "cm3", "km", "kmph", "m2", "m3"]
+        );
+
+        let result = interceptor.search_units("KM");
+        assert_eq!(
+            result[0],
+            ("km".to_string(), "kilometer".to_string(), "km".to_string())
+        );
+        assert!(interceptor.search_units("furlong").is_empty());
+    }
+
+    #[test]
+    fn test_reload() {
         let unit_definitions = toml::from_str(
             r#"
 [length]
 m = { name = "meter", symbol = "m" }
+ft = { name = "foot", symbol = "ft", factor = 0.3 }
+
+[time]
+sec = { name = "second", symbol = "s" }
 "#,
         )
         .unwrap();
 
         let mut interceptor = Interpretor::new(&unit_definitions).unwrap();
-        let result = interceptor.execute_command(expr).unwrap();
-        assert_eq!(result, (1.65, "m".to_string()));
+        interceptor.execute_command("x = 3 m").unwrap();
+        interceptor.execute_command("t = 3 sec").unwrap();
+        let result = interceptor.execute_command("x >> ft");
+        assert_eq!(result, Ok((Value::Number(10.0), "ft".to_string())));
+
+        let dropped = interceptor
+            .reload(
+                toml::from_str(
+                    r#"
+[length]
+m = { name = "meter", symbol = "m" }
+ft = { name = "foot", symbol = "ft", factor = 0.3048 }
+"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(dropped, vec!["t".to_string()]);
+
+        let result = interceptor.execute_command("1.524 m >> ft");
+        assert_eq!(result, Ok((Value::Number(5.0), "ft".to_string())));
+        let result = interceptor.execute_command("x");
+        assert_eq!(result, Ok((Value::Number(3.0), "m".to_string())));
+        let result = interceptor.execute_command("t");
+        assert_eq!(
+            result.unwrap_err()[0].1,
+            "Cannot find variable \"t\" in scope"
+        );
     }
 }
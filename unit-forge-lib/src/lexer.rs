@@ -0,0 +1,51 @@
+use logos::Logos;
+
+/// Tokens of a derived unit expression such as `(kg*m) / s2`, whitespace is insignificant
+#[derive(Logos, Debug, Clone, Copy, PartialEq)]
+#[logos(skip r"\s+")]
+pub(crate) enum DerivedToken<'src> {
+    #[token("*")]
+    Mul,
+    #[token("/")]
+    Div,
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[regex(r"[^\s*/()]+", |lex| lex.slice())]
+    Unit(&'src str),
+}
+
+/// Splits a derived unit expression into its units and operators, `None` if it contains invalid input
+pub(crate) fn tokenize_derived(expr: &str) -> Option<Vec<DerivedToken<'_>>> {
+    DerivedToken::lexer(expr)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_derived() {
+        let expected = Some(vec![
+            DerivedToken::Unit("kg"),
+            DerivedToken::Mul,
+            DerivedToken::Unit("m"),
+            DerivedToken::Div,
+            DerivedToken::Unit("s2"),
+        ]);
+        assert_eq!(tokenize_derived("kg * m / s2"), expected);
+        assert_eq!(tokenize_derived("kg*m/s2"), expected);
+        assert_eq!(tokenize_derived(" kg*  m /s2 "), expected);
+        assert_eq!(
+            tokenize_derived("(m)"),
+            Some(vec![
+                DerivedToken::LParen,
+                DerivedToken::Unit("m"),
+                DerivedToken::RParen,
+            ])
+        );
+    }
+}
@@ -1,10 +1,46 @@
+mod datetime;
+mod diagnostic;
+mod eval_result;
+mod factor_provider;
+mod format;
 mod interpretor;
+mod lexer;
+mod ratio;
+mod stored_var;
 mod unit;
 mod unit_definition;
+mod value;
 
+use std::path::PathBuf;
+
+pub use datetime::{DATETIME_UNIT, TIME_CATEGORY, format_datetime};
+pub use diagnostic::*;
+pub use eval_result::*;
+pub use factor_provider::*;
+pub use format::*;
 pub use interpretor::*;
+pub use stored_var::*;
 use thiserror::Error;
 pub use unit_definition::*;
+pub use value::*;
+
+/// Why a `.ud` file couldn't be loaded by `UnitDefinitions::from_file`
+#[derive(Debug, Error)]
+pub enum DefinitionFileError {
+    #[error("Failed to read definitions file {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Invalid definitions file {} at line {line}, column {column}: {message}", path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
 
 #[derive(Debug, Error)]
 pub enum DefinitionError {
@@ -16,4 +52,26 @@ pub enum DefinitionError {
     InvalidDerivedExpression(String),
     #[error("No units defined in category '{0}'")]
     NoUnitDefined(String),
+    #[error("Malformed factor '{1}' of unit '{0}'")]
+    MalformedFactor(String, String),
+    #[error("Invalid factor '{1}' of unit '{0}', factors must be positive")]
+    InvalidFactor(String, f64),
+    #[error("Unit '{0}' is defined relative to unit '{1}' which is not in its category")]
+    RelativeUnitNotFound(String, String),
+    #[error("Cyclic relative definition of unit '{0}'")]
+    CyclicRelativeUnit(String),
+    #[error("Invalid offset '{1}' of unit '{0}', offsets must be finite")]
+    InvalidOffset(String, f64),
+    #[error("Unit '{0}' is displayed as unit '{1}' which is not in its category")]
+    DisplayUnitNotFound(String, String),
+    #[error("Exchange rate given for '{0}' which is not a unit of the currency category")]
+    UnknownCurrency(String),
+    #[error("Base unit '{0}' of the time category must be the second, found factor {1}")]
+    InvalidTimeUnit(String, f64),
+    #[error(
+        "Invalid dimension '{1}' of category '{0}', expected base dimensions with integer exponents like 'L T^-1'"
+    )]
+    InvalidDimension(String, String),
+    #[error("Dimension given for category '{0}' which is not defined")]
+    UnknownDimensionCategory(String),
 }
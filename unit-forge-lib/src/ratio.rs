@@ -0,0 +1,99 @@
+/// Largest integer up to which every integer is exactly representable as `f64`
+const MAX_EXACT_INTEGER: i128 = 1 << 53;
+
+/// An exact fraction, used to convert values without accumulating floating point error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Ratio {
+    num: i128,
+    den: i128,
+}
+
+impl Ratio {
+    pub(crate) const ONE: Ratio = Ratio { num: 1, den: 1 };
+
+    pub(crate) fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let divisor = gcd(num, den) * den.signum();
+        Some(Self {
+            num: num / divisor,
+            den: den / divisor,
+        })
+    }
+
+    /// The fraction of the shortest decimal that round-trips to `num`, e.g. `0.1` -> `1/10`.
+    /// `None` if it's not finite or doesn't fit.
+    pub(crate) fn from_f64(num: f64) -> Option<Self> {
+        if !num.is_finite() {
+            return None;
+        }
+        let scientific = format!("{:e}", num);
+        let (mantissa, exponent) = scientific.split_once('e')?;
+        let exponent: i32 = exponent.parse().ok()?;
+        let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: i128 = format!("{}{}", int_part, frac_part).parse().ok()?;
+        let exponent = exponent - frac_part.len() as i32;
+        let scale = 10i128.checked_pow(exponent.unsigned_abs())?;
+        if exponent >= 0 {
+            Self::new(digits.checked_mul(scale)?, 1)
+        } else {
+            Self::new(digits, scale)
+        }
+    }
+
+    pub(crate) fn checked_mul(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num.checked_mul(other.num)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    pub(crate) fn checked_div(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num.checked_mul(other.den)?,
+            self.den.checked_mul(other.num)?,
+        )
+    }
+
+    /// The nearest `f64`, `None` if the numerator or denominator can't be represented exactly,
+    /// as the division would no longer be correctly rounded then
+    pub(crate) fn to_f64(self) -> Option<f64> {
+        (self.num.abs() <= MAX_EXACT_INTEGER && self.den <= MAX_EXACT_INTEGER)
+            .then(|| self.num as f64 / self.den as f64)
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_f64() {
+        assert_eq!(Ratio::from_f64(0.1), Ratio::new(1, 10));
+        assert_eq!(Ratio::from_f64(1.1), Ratio::new(11, 10));
+        assert_eq!(Ratio::from_f64(-2500.0), Ratio::new(-2500, 1));
+        assert_eq!(Ratio::from_f64(0.3048), Ratio::new(381, 1250));
+        assert_eq!(Ratio::from_f64(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let value = Ratio::from_f64(12345.0).unwrap();
+        let factor = Ratio::from_f64(0.0001).unwrap();
+        assert_eq!(value.checked_mul(factor).unwrap().to_f64(), Some(1.2345));
+        assert_eq!(12345.0 * 0.0001, 1.2345000000000002);
+
+        let third = Ratio::new(1, 3).unwrap();
+        assert_eq!(third.checked_div(third).unwrap().to_f64(), Some(1.0));
+        assert_eq!(Ratio::new(1, 0), None);
+    }
+}
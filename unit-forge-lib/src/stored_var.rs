@@ -0,0 +1,15 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Value;
+
+/// A variable as persisted between sessions, see `Interpretor::stored_vars`.
+/// The value is always in its base unit, so it can be reattached to the definitions of a later session
+/// even if the unit it was displayed in is gone or the base unit of its category changed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StoredVar {
+    pub value: Value,
+    /// Base unit of the value when it was stored, empty for dimensionless and mixed values
+    pub base_unit: String,
+}
@@ -1,23 +1,53 @@
 use std::collections::HashMap;
 
-use crate::unit_definition::{UnitDefinition, UnitDefinitions};
+use indexmap::IndexMap;
+
+use crate::{
+    lexer::{DerivedToken, tokenize_derived},
+    ratio::Ratio,
+    superscript_exponents,
+    unit_definition::{UnitDefinition, UnitDefinitions},
+};
 
 use super::*;
 
-pub type UnitMapType<'a> = HashMap<(&'a str, &'a str, &'a str), &'a str>;    // (unit_key, op, unit_key) -> unit_key, e.g.: ("m", "*", "m") -> "m2"
-pub type BaseUnitMapType<'a> = HashMap<&'a str, (f64, &'a str)>;    // (unit_key) -> (factor, base_unit_key), e.g.: ("min", (60, "second"))
+pub type UnitMapType = HashMap<(String, String, String), String>; // (unit_key, op, unit_key) -> unit_key, e.g.: ("m", "*", "m") -> "m2"
+pub type BaseUnitMapType = HashMap<String, (f64, String)>; // (unit_key) -> (factor, base_unit_key), e.g.: ("min", (60, "second"))
+
+/// Stands for a dimensionless operand in derived expressions, e.g. `1 / s` of reciprocal units
+const DIMENSIONLESS_OPERAND: &str = "1";
 
 #[derive(Debug)]
-pub struct UnitTable<'a> {
-    derived_units_map: UnitMapType<'a>,
-    base_units_map: BaseUnitMapType<'a>,
+pub struct UnitTable {
+    derived_units_map: UnitMapType,
+    base_units_map: BaseUnitMapType,
+    symbols: HashMap<String, String>,
+    quotient_symbols: HashMap<String, String>,
+    offsets: HashMap<String, f64>,
+    exact_factors: HashMap<String, Ratio>,
+    display_units: HashMap<String, String>,
 }
 
-impl<'a> UnitTable<'a> {
-    pub fn new(unit_definitions: &'a UnitDefinitions) -> Result<Self, DefinitionError> {
+impl UnitTable {
+    pub fn new(unit_definitions: &UnitDefinitions) -> Result<Self, DefinitionError> {
+        validate_dimensions(unit_definitions)?;
         let derived_units_map = construct_unit_translation_map(unit_definitions)?;
         let base_units_map = construct_base_units_map(unit_definitions)?;
-        Ok(Self { derived_units_map, base_units_map })
+        validate_time_unit(unit_definitions, &base_units_map)?;
+        let symbols = construct_symbols_map(unit_definitions);
+        let quotient_symbols = construct_quotient_symbols_map(unit_definitions, &symbols);
+        let offsets = construct_offsets_map(unit_definitions)?;
+        let exact_factors = construct_exact_factors_map(unit_definitions);
+        let display_units = construct_display_units_map(unit_definitions)?;
+        Ok(Self {
+            derived_units_map,
+            base_units_map,
+            symbols,
+            quotient_symbols,
+            offsets,
+            exact_factors,
+            display_units,
+        })
     }
 
     pub fn derived_units_map(&self) -> &UnitMapType {
@@ -27,6 +57,139 @@ impl<'a> UnitTable<'a> {
     pub fn base_units_map(&self) -> &BaseUnitMapType {
         &self.base_units_map
     }
+
+    /// (unit_key) -> symbol for display, e.g.: ("m3", "m³")
+    pub fn symbols(&self) -> &HashMap<String, String> {
+        &self.symbols
+    }
+
+    /// (unit_key) -> symbols of the operands of units derived by a division, e.g.: ("mps", "m/s")
+    pub fn quotient_symbols(&self) -> &HashMap<String, String> {
+        &self.quotient_symbols
+    }
+
+    /// Offset of `unit` in base units, `0` for units sharing the zero of their base unit
+    pub fn offset(&self, unit: &str) -> f64 {
+        self.offsets.get(unit).copied().unwrap_or(0.0)
+    }
+
+    /// The factor of `unit` as an exact fraction, `None` if it can't be represented as one
+    pub(crate) fn exact_factor(&self, unit: &str) -> Option<Ratio> {
+        self.exact_factors.get(unit).copied()
+    }
+
+    /// The unit results in `unit` are displayed in, if its definition sets `display_as`
+    pub fn display_unit(&self, unit: &str) -> Option<&str> {
+        self.display_units.get(unit).map(String::as_str)
+    }
+
+    /// Resolves a unit expression like `m / s` to the key of the unit it results in, e.g. `mps`,
+    /// `None` if the expression is invalid or a step of it isn't derived
+    pub fn resolve_unit_expr(&self, expr: &str) -> Option<String> {
+        self.resolve_unit_expr_with(expr, |unit| Ok::<_, ()>(unit.to_string()))
+            .ok()
+            .flatten()
+    }
+
+    /// Like `resolve_unit_expr`, with each unit of the expression replaced by `map_unit` first, whose
+    /// errors are passed on. The result is never a synthetic unit like `kg*m`, only a defined one.
+    pub(crate) fn resolve_unit_expr_with<E>(
+        &self,
+        expr: &str,
+        map_unit: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<Option<String>, E> {
+        let Some(expr) = tokenize_derived(expr).and_then(|tokens| parse_derived(&tokens)) else {
+            return Ok(None);
+        };
+        Ok(self
+            .resolve_derived(&expr, &map_unit)?
+            .filter(|unit| !is_synthetic(unit)))
+    }
+
+    fn resolve_derived<E>(
+        &self,
+        expr: &DerivedExpr,
+        map_unit: &impl Fn(&str) -> Result<String, E>,
+    ) -> Result<Option<String>, E> {
+        match expr {
+            DerivedExpr::Unit(DIMENSIONLESS_OPERAND) => Ok(Some(String::new())),
+            DerivedExpr::Unit(unit) => map_unit(unit).map(Some),
+            DerivedExpr::Op(lhs, op, rhs) => {
+                let (Some(lhs), Some(rhs)) = (
+                    self.resolve_derived(lhs, map_unit)?,
+                    self.resolve_derived(rhs, map_unit)?,
+                ) else {
+                    return Ok(None);
+                };
+                let key = (lhs, op.to_string(), rhs);
+                Ok(self.derived_units_map.get(&key).cloned())
+            }
+        }
+    }
+
+    /// Warnings about units that can never take part in a conversion, sorted by unit key:
+    /// units with an infinite factor, and the only unit of a category when no derived unit refers to it
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings = self
+            .base_units_map
+            .iter()
+            .filter(|(unit, _)| !unit.is_empty())
+            .filter_map(|(unit, (factor, base_unit))| {
+                if !factor.is_finite() {
+                    return Some((
+                        unit,
+                        format!(
+                            "Unit \"{}\" has the factor {}, no value converts to or from it",
+                            unit, factor
+                        ),
+                    ));
+                }
+                let is_alone = !self
+                    .base_units_map
+                    .iter()
+                    .any(|(other, (_, base))| other != unit && base == base_unit);
+                let is_derived = self
+                    .derived_units_map
+                    .iter()
+                    .any(|((unit_a, _, unit_b), result)| {
+                        unit_a == unit || unit_b == unit || result == unit
+                    });
+                (is_alone && !is_derived).then(|| {
+                    (
+                        unit,
+                        format!(
+                            "Unit \"{}\" is the only unit of its category and no derived unit refers to it, \
+                             it can't be converted",
+                            unit
+                        ),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        warnings.sort();
+        warnings.into_iter().map(|(_, warning)| warning).collect()
+    }
+
+    /// Whether `unit` is a defined unit or a unit produced by a derived expression
+    pub fn contains(&self, unit: &str) -> bool {
+        self.base_units_map.contains_key(unit)
+            || self
+                .derived_units_map
+                .values()
+                .any(|derived| derived == unit)
+    }
+}
+
+fn mapping(
+    unit_a: &str,
+    op: &str,
+    unit_b: &str,
+    result: &str,
+) -> ((String, String, String), String) {
+    (
+        (unit_a.to_string(), op.to_string(), unit_b.to_string()),
+        result.to_string(),
+    )
 }
 
 fn construct_unit_translation_map(
@@ -35,6 +198,7 @@ fn construct_unit_translation_map(
     // (unit_key, op, unit_key) -> unit_key, e.g.:
     // ("m", "*", "m") -> "m2"
     // ("m", "/", "s") -> "mps"
+    // ("", "/", "s") -> "Hz"
     let mut map: UnitMapType = UnitMapType::new();
 
     // First pass: collect all units
@@ -51,89 +215,425 @@ fn construct_unit_translation_map(
         }
     }
 
-    // Second pass: process derived units
+    // Second pass: parse derived units
+    let mut derived_units = Vec::new();
     for (category, units) in definitions.categories.iter() {
         for (unit, unit_def) in units.iter() {
             if let Some(derived_expr) = &unit_def.derived {
-                let parts: Vec<&str> = derived_expr.split_whitespace().collect();
-                
-                // Must have odd number of parts (alternating unit and operator)
-                if parts.len() >= 3 && parts.len() % 2 == 1 {
-                    let mut current_unit = parts[0];
-                    let mut i = 1;
-                    
-                    // Validate first unit exists
-                    if !all_units.contains_key(current_unit) {
-                        return Err(DefinitionError::UnitNotFound(
-                            current_unit.to_string(),
-                            derived_expr.to_string(),
-                            category.to_string(),
-                        ));
-                    }
+                let invalid =
+                    || DefinitionError::InvalidDerivedExpression(derived_expr.to_string());
+                let tokens = tokenize_derived(derived_expr).ok_or_else(invalid)?;
+                // Must have at least one operation, a lone unit is not a derived unit
+                let Some(expr @ DerivedExpr::Op(..)) = parse_derived(&tokens) else {
+                    return Err(invalid());
+                };
+                derived_units.push((category, unit, derived_expr, expr));
+            }
+        }
+    }
 
-                    while i < parts.len() - 1 {
-                        let op = parts[i];
-                        let next_unit = parts[i + 1];
-                        
-                        // Validate operator
-                        if op != "*" && op != "/" {
-                            return Err(DefinitionError::InvalidDerivedExpression(
-                                derived_expr.to_string(),
-                            ));
-                        }
-                        
-                        // Validate next unit exists
-                        if !all_units.contains_key(next_unit) {
-                            return Err(DefinitionError::UnitNotFound(
-                                next_unit.to_string(),
-                                derived_expr.to_string(),
-                                category.to_string(),
-                            ));
-                        }
-
-                        // For intermediate operations, look up result in map if needed
-                        let result_unit = if i == parts.len() - 2 {
-                            // Last operation, result is our target unit
-                            unit
-                        } else {
-                            // For intermediate operations (e.g., first m * m in m * m * m)
-                            let key = (current_unit, op, next_unit);
-                            if let Some(&result) = map.get(&key) {
-                                result
-                            } else {
-                                return Err(DefinitionError::InvalidDerivedExpression(format!(
-                                    "Cannot find intermediate unit for: {} {} {}",
-                                    current_unit, op, next_unit
-                                )));
-                            }
-                        };
-
-                        // Add mappings for this operation
-                        if op == "*" {
-                            map.insert((current_unit, "*", next_unit), result_unit);
-                            map.insert((next_unit, "*", current_unit), result_unit);
-                            map.insert((result_unit, "/", current_unit), next_unit);
-                            map.insert((result_unit, "/", next_unit), current_unit);
-                        } else { // op == "/"
-                            map.insert((current_unit, "/", next_unit), result_unit);
-                            map.insert((current_unit, "/", result_unit), next_unit);
-                            map.insert((result_unit, "*", next_unit), current_unit);
-                        }
-                        
-                        // Set up for next iteration
-                        current_unit = result_unit;
-                        i += 2;
-                    }
-                } else {
-                    return Err(DefinitionError::InvalidDerivedExpression(
-                        derived_expr.to_string(),
+    // Third pass: register derived units, shorter expressions first so that longer ones reuse them
+    // as intermediate results regardless of where they're defined, e.g. `m2` in `m * m * m`
+    derived_units.sort_by_key(|(_, _, _, expr)| expr.operation_count());
+    for (category, unit, derived_expr, expr) in derived_units {
+        let DerivedExpr::Op(lhs, op, rhs) = expr else {
+            unreachable!("derived units have at least one operation");
+        };
+        let mut resolver = DerivedResolver {
+            map: &mut map,
+            all_units: &all_units,
+            derived_expr,
+            category,
+        };
+        let lhs = resolver.resolve(*lhs)?;
+        let rhs = resolver.resolve(*rhs)?;
+        // The outermost operation results in the derived unit itself
+        insert_operation(&mut map, &lhs, op, &rhs, unit);
+    }
+
+    Ok(map)
+}
+
+/// Adds the mappings of `unit_a op unit_b = result` and of its inverse operations, returns the result,
+/// which differs from `result` if it's a synthetic unit merged into another one
+fn insert_operation(
+    map: &mut UnitMapType,
+    unit_a: &str,
+    op: &str,
+    unit_b: &str,
+    result: &str,
+) -> String {
+    let mut units = [unit_a, unit_b, result].map(str::to_string);
+    // A synthetic unit mapped by the same operation as another unit has the same dimension, so it's
+    // merged into it, otherwise overwriting one mapping would break the inverses of the other, e.g.
+    // the synthetic `m*m` of `m * m / m * m` is merged into the `m2` it defines
+    while let Some((existing, result)) =
+        operation_mappings(&units, op)
+            .into_iter()
+            .find_map(|(key, result)| {
+                let existing = map.get(&key)?;
+                (*existing != result && (is_synthetic(existing) || is_synthetic(&result)))
+                    .then(|| (existing.clone(), result))
+            })
+    {
+        for (from, to) in merge_units(map, existing, result) {
+            for unit in units.iter_mut().filter(|unit| **unit == from) {
+                unit.clone_from(&to);
+            }
+        }
+    }
+    map.extend(operation_mappings(&units, op));
+    units[2].clone()
+}
+
+/// The mappings of `unit_a op unit_b = result` and of its inverse operations
+fn operation_mappings(
+    [unit_a, unit_b, result]: &[String; 3],
+    op: &str,
+) -> [((String, String, String), String); 4] {
+    if op == "*" {
+        [
+            mapping(unit_a, "*", unit_b, result),
+            mapping(unit_b, "*", unit_a, result),
+            mapping(result, "/", unit_a, unit_b),
+            mapping(result, "/", unit_b, unit_a),
+        ]
+    } else {
+        // op == "/"
+        [
+            mapping(unit_a, "/", unit_b, result),
+            mapping(unit_a, "/", result, unit_b),
+            mapping(result, "*", unit_b, unit_a),
+            mapping(unit_b, "*", result, unit_a),
+        ]
+    }
+}
+
+/// Whether a unit is a synthetic one carrying an intermediate result, which are named after their
+/// operation, e.g. `kg*m`
+fn is_synthetic(unit: &str) -> bool {
+    unit.contains(['*', '/'])
+}
+
+/// Merges a synthetic unit into another unit of the same dimension by renaming it in all the mappings,
+/// along with the units that then turn out to be the same as well since the renamed mappings map the
+/// same operation to them. Returns the renames in the order they were done.
+fn merge_units(map: &mut UnitMapType, unit_a: String, unit_b: String) -> Vec<(String, String)> {
+    let mut renames: Vec<(String, String)> = Vec::new();
+    let mut pending = vec![(unit_a, unit_b)];
+    while let Some(units) = pending.pop() {
+        let [unit_a, unit_b] = [units.0, units.1].map(|unit| {
+            renames.iter().fold(
+                unit,
+                |unit, (from, to)| if unit == *from { to.clone() } else { unit },
+            )
+        });
+        // Of two synthetic units the lesser name is kept, so the map doesn't depend on the order
+        // the renamed mappings are visited in
+        let (from, to) = if unit_a == unit_b {
+            continue;
+        } else if is_synthetic(&unit_a) && (!is_synthetic(&unit_b) || unit_a > unit_b) {
+            (unit_a, unit_b)
+        } else if is_synthetic(&unit_b) {
+            (unit_b, unit_a)
+        } else {
+            // Two units defined by the same operation, only one of them can be mapped
+            continue;
+        };
+
+        let rename = |unit: String| if unit == from { to.clone() } else { unit };
+        for ((unit_a, op, unit_b), result) in std::mem::take(map) {
+            let key = (rename(unit_a), op, rename(unit_b));
+            let result = rename(result);
+            if let Some(existing) = map.get(&key)
+                && *existing != result
+            {
+                pending.push((existing.clone(), result.clone()));
+            }
+            map.insert(key, result);
+        }
+        renames.push((from, to));
+    }
+    renames
+}
+
+/// A derived unit expression, operations of the same level are grouped from the left
+#[derive(Debug, PartialEq)]
+enum DerivedExpr<'a> {
+    Unit(&'a str),
+    Op(Box<DerivedExpr<'a>>, &'static str, Box<DerivedExpr<'a>>),
+}
+
+impl DerivedExpr<'_> {
+    fn operation_count(&self) -> usize {
+        match self {
+            DerivedExpr::Unit(_) => 0,
+            DerivedExpr::Op(lhs, _, rhs) => 1 + lhs.operation_count() + rhs.operation_count(),
+        }
+    }
+}
+
+/// Parses the tokens of a derived expression, `None` if they don't form a valid expression
+fn parse_derived<'a>(tokens: &[DerivedToken<'a>]) -> Option<DerivedExpr<'a>> {
+    let mut pos = 0;
+    let expr = parse_derived_chain(tokens, &mut pos)?;
+    (pos == tokens.len()).then_some(expr)
+}
+
+fn parse_derived_chain<'a>(
+    tokens: &[DerivedToken<'a>],
+    pos: &mut usize,
+) -> Option<DerivedExpr<'a>> {
+    let mut lhs = parse_derived_operand(tokens, pos)?;
+    loop {
+        let op = match tokens.get(*pos) {
+            Some(DerivedToken::Mul) => "*",
+            Some(DerivedToken::Div) => "/",
+            _ => return Some(lhs),
+        };
+        *pos += 1;
+        let rhs = parse_derived_operand(tokens, pos)?;
+        lhs = DerivedExpr::Op(Box::new(lhs), op, Box::new(rhs));
+    }
+}
+
+fn parse_derived_operand<'a>(
+    tokens: &[DerivedToken<'a>],
+    pos: &mut usize,
+) -> Option<DerivedExpr<'a>> {
+    match tokens.get(*pos)? {
+        DerivedToken::Unit(unit) => {
+            *pos += 1;
+            Some(DerivedExpr::Unit(unit))
+        }
+        DerivedToken::LParen => {
+            *pos += 1;
+            let expr = parse_derived_chain(tokens, pos)?;
+            if tokens.get(*pos) != Some(&DerivedToken::RParen) {
+                return None;
+            }
+            *pos += 1;
+            Some(expr)
+        }
+        _ => None,
+    }
+}
+
+/// Resolves the operands of a derived expression to unit keys, registering the intermediate operations
+struct DerivedResolver<'a, 'm> {
+    map: &'m mut UnitMapType,
+    all_units: &'a HashMap<&'a str, &'a UnitDefinition>,
+    derived_expr: &'a str,
+    category: &'a str,
+}
+
+impl DerivedResolver<'_, '_> {
+    fn resolve(&mut self, expr: DerivedExpr) -> Result<String, DefinitionError> {
+        match expr {
+            // The empty unit key of dimensionless values, so e.g. `1 / s` maps ("", "/", "s")
+            DerivedExpr::Unit(DIMENSIONLESS_OPERAND) => Ok(String::new()),
+            DerivedExpr::Unit(unit) => {
+                if !self.all_units.contains_key(unit) {
+                    return Err(DefinitionError::UnitNotFound(
+                        unit.to_string(),
+                        self.derived_expr.to_string(),
+                        self.category.to_string(),
                     ));
                 }
+                Ok(unit.to_string())
+            }
+            DerivedExpr::Op(lhs, op, rhs) => {
+                let lhs = self.resolve(*lhs)?;
+                let rhs = self.resolve(*rhs)?;
+                // For intermediate operations (e.g., first m * m in m * m * m), reuse the
+                // named unit if there is one, otherwise carry a synthetic unit (e.g. "kg*m")
+                // so chains spanning several categories still resolve to the target unit.
+                // Synthetic operands on the right are grouped so no two chains share a name.
+                let key = (lhs.clone(), op.to_string(), rhs.clone());
+                let result = match self.map.get(&key) {
+                    Some(result) => result.clone(),
+                    None if is_synthetic(&rhs) => format!("{}{}({})", lhs, op, rhs),
+                    None => format!("{}{}{}", lhs, op, rhs),
+                };
+                Ok(insert_operation(self.map, &lhs, op, &rhs, &result))
             }
         }
     }
+}
 
-    Ok(map)
+fn construct_symbols_map(definitions: &UnitDefinitions) -> HashMap<String, String> {
+    let mut symbols = HashMap::new();
+    for units in definitions.categories.values() {
+        for (unit_key, unit_def) in units.iter() {
+            // Fall back to the key when no symbol is given
+            let symbol = if unit_def.symbol.is_empty() {
+                unit_key
+            } else {
+                &unit_def.symbol
+            };
+            let symbol = if unit_def.derived.is_some() {
+                superscript_exponents(symbol)
+            } else {
+                symbol.clone()
+            };
+            symbols.insert(unit_key.clone(), symbol);
+        }
+    }
+    symbols
+}
+
+fn construct_quotient_symbols_map(
+    definitions: &UnitDefinitions,
+    symbols: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut quotient_symbols = HashMap::new();
+    for units in definitions.categories.values() {
+        for (unit_key, unit_def) in units.iter() {
+            let Some(tokens) = unit_def.derived.as_deref().and_then(tokenize_derived) else {
+                continue;
+            };
+            if let Some(expr @ DerivedExpr::Op(_, "/", _)) = parse_derived(&tokens) {
+                quotient_symbols.insert(unit_key.clone(), render_derived(&expr, symbols, false));
+            }
+        }
+    }
+    quotient_symbols
+}
+
+/// Writes a derived expression with the symbols of its units, e.g. `kg·m/s²`
+fn render_derived(expr: &DerivedExpr, symbols: &HashMap<String, String>, grouped: bool) -> String {
+    match expr {
+        DerivedExpr::Unit(unit) => symbols.get(*unit).map_or(unit.to_string(), String::clone),
+        DerivedExpr::Op(lhs, op, rhs) => {
+            let op = if *op == "*" { "·" } else { "/" };
+            let rendered = format!(
+                "{}{}{}",
+                render_derived(lhs, symbols, false),
+                op,
+                render_derived(rhs, symbols, true)
+            );
+            if grouped {
+                format!("({})", rendered)
+            } else {
+                rendered
+            }
+        }
+    }
+}
+
+/// Exact factors of the units whose factors (and those they're relative to) are all exact fractions.
+/// Expects the factors to be already validated by `construct_base_units_map`.
+fn construct_exact_factors_map(definitions: &UnitDefinitions) -> HashMap<String, Ratio> {
+    let mut exact_factors = HashMap::new();
+    for units in definitions.categories.values() {
+        for unit_key in units.keys() {
+            let mut factor = Some(Ratio::ONE);
+            let mut unit_def = &units[unit_key];
+            loop {
+                factor =
+                    factor.and_then(|factor| factor.checked_mul(unit_def.factor.exact_value()?));
+                let Some(relative_to) = &unit_def.relative_to else {
+                    break;
+                };
+                unit_def = &units[relative_to];
+            }
+            if let Some(factor) = factor {
+                exact_factors.insert(unit_key.clone(), factor);
+            }
+        }
+    }
+    exact_factors.insert(String::new(), Ratio::ONE);
+    exact_factors
+}
+
+fn construct_offsets_map(
+    definitions: &UnitDefinitions,
+) -> Result<HashMap<String, f64>, DefinitionError> {
+    let mut offsets = HashMap::new();
+    for units in definitions.categories.values() {
+        for (unit_key, unit_def) in units.iter() {
+            if !unit_def.offset.is_finite() {
+                return Err(DefinitionError::InvalidOffset(
+                    unit_key.clone(),
+                    unit_def.offset,
+                ));
+            }
+            if unit_def.offset != 0.0 {
+                offsets.insert(unit_key.clone(), unit_def.offset);
+            }
+        }
+    }
+    Ok(offsets)
+}
+
+/// Dates are added durations in the base unit of the time category as seconds, so its factor must be 1
+fn validate_time_unit(
+    definitions: &UnitDefinitions,
+    base_units_map: &BaseUnitMapType,
+) -> Result<(), DefinitionError> {
+    let Some((unit_key, _)) = definitions
+        .categories
+        .get(TIME_CATEGORY)
+        .and_then(|units| units.first())
+    else {
+        return Ok(());
+    };
+    let (factor, _) = base_units_map[unit_key];
+    if factor != 1.0 {
+        return Err(DefinitionError::InvalidTimeUnit(unit_key.clone(), factor));
+    }
+    Ok(())
+}
+
+fn validate_dimensions(definitions: &UnitDefinitions) -> Result<(), DefinitionError> {
+    for (category, dimension) in definitions.dimensions.iter() {
+        if !is_valid_dimension(dimension) {
+            return Err(DefinitionError::InvalidDimension(
+                category.clone(),
+                dimension.clone(),
+            ));
+        }
+        if !definitions.categories.contains_key(category) {
+            return Err(DefinitionError::UnknownDimensionCategory(category.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a dimension is made of base dimensions like `L` or `Θ` with optional nonzero integer
+/// exponents, separated by spaces, e.g. `M L^2 T^-2`. `1` is the dimension of dimensionless values.
+fn is_valid_dimension(dimension: &str) -> bool {
+    if dimension.trim() == "1" {
+        return true;
+    }
+    let mut factors = dimension.split_whitespace().peekable();
+    factors.peek().is_some()
+        && factors.all(|factor| {
+            let (symbol, exponent) = factor.split_once('^').unwrap_or((factor, "1"));
+            !symbol.is_empty()
+                && symbol.chars().all(char::is_alphabetic)
+                && exponent.parse::<i32>().is_ok_and(|exponent| exponent != 0)
+        })
+}
+
+fn construct_display_units_map(
+    definitions: &UnitDefinitions,
+) -> Result<HashMap<String, String>, DefinitionError> {
+    let mut display_units = HashMap::new();
+    for units in definitions.categories.values() {
+        for (unit_key, unit_def) in units.iter() {
+            if let Some(display_as) = &unit_def.display_as {
+                if !units.contains_key(display_as) {
+                    return Err(DefinitionError::DisplayUnitNotFound(
+                        unit_key.clone(),
+                        display_as.clone(),
+                    ));
+                }
+                display_units.insert(unit_key.clone(), display_as.clone());
+            }
+        }
+    }
+    Ok(display_units)
 }
 
 fn construct_base_units_map(
@@ -142,25 +642,62 @@ fn construct_base_units_map(
     let mut base_units_map: BaseUnitMapType = BaseUnitMapType::new();
 
     for (category, units) in definitions.categories.iter() {
-        let base_unit = units.first().ok_or_else(|| {
-            DefinitionError::NoUnitDefined(category.to_string())
-        })?.0;
-        for (unit_key, unit_def) in units.iter() {
-            base_units_map.insert(unit_key, (unit_def.factor, base_unit));
+        let base_unit = units
+            .first()
+            .ok_or_else(|| DefinitionError::NoUnitDefined(category.to_string()))?
+            .0;
+        for unit_key in units.keys() {
+            let factor = resolve_factor(units, unit_key, &mut Vec::new())?;
+            base_units_map.insert(unit_key.clone(), (factor, base_unit.clone()));
         }
     }
 
     // Add empty base unit
-    base_units_map.insert("", (1.0, ""));
+    base_units_map.insert("".to_string(), (1.0, "".to_string()));
 
     Ok(base_units_map)
 }
 
+/// Resolves the factor of a unit relative to its category's base unit, following `relative_to` references
+fn resolve_factor<'a>(
+    units: &'a IndexMap<String, UnitDefinition>,
+    unit_key: &'a str,
+    visiting: &mut Vec<&'a str>,
+) -> Result<f64, DefinitionError> {
+    let unit_def = &units[unit_key];
+    let factor = unit_def.factor.value().ok_or_else(|| {
+        DefinitionError::MalformedFactor(unit_key.to_string(), unit_def.factor.to_string())
+    })?;
+    if factor <= 0.0 || factor.is_nan() {
+        return Err(DefinitionError::InvalidFactor(unit_key.to_string(), factor));
+    }
+
+    let Some(relative_to) = &unit_def.relative_to else {
+        return Ok(factor);
+    };
+    if !units.contains_key(relative_to) {
+        return Err(DefinitionError::RelativeUnitNotFound(
+            unit_key.to_string(),
+            relative_to.clone(),
+        ));
+    }
+    if visiting.contains(&relative_to.as_str()) || relative_to == unit_key {
+        return Err(DefinitionError::CyclicRelativeUnit(unit_key.to_string()));
+    }
+    visiting.push(unit_key);
+    Ok(factor * resolve_factor(units, relative_to, visiting)?)
+}
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
 
+    fn key(unit_a: &str, op: &str, unit_b: &str) -> (String, String, String) {
+        (unit_a.to_string(), op.to_string(), unit_b.to_string())
+    }
+
     #[test]
     fn test_unit_translation_map() {
         let toml_str = r#"
@@ -180,13 +717,41 @@ mps = { name = "meters per second", symbol = "m/s", derived = "m / s" }
         let map = construct_unit_translation_map(&definitions).unwrap();
 
         // Test m * m -> m² (area)
-        assert_eq!(map.get(&("m", "*", "m")).unwrap(), &"m2");
-        assert_eq!(map.get(&("m2", "/", "m")).unwrap(), &"m");
+        assert_eq!(map.get(&key("m", "*", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m2", "/", "m")).unwrap(), "m");
 
         // Test m / s -> m/s (speed)
-        assert_eq!(map.get(&("m", "/", "s")).unwrap(), &"mps");
-        assert_eq!(map.get(&("mps", "*", "s")).unwrap(), &"m");
-        assert_eq!(map.get(&("m", "/", "mps")).unwrap(), &"s");
+        assert_eq!(map.get(&key("m", "/", "s")).unwrap(), "mps");
+        assert_eq!(map.get(&key("mps", "*", "s")).unwrap(), "m");
+        assert_eq!(map.get(&key("m", "/", "mps")).unwrap(), "s");
+    }
+
+    #[test]
+    fn test_reciprocal_units() {
+        let toml_str = r#"
+[time]
+s = { name = "second", symbol = "s" }
+
+[frequency]
+Hz = { name = "hertz", symbol = "Hz", derived = "1 / s" }
+
+[resistance]
+ohm = { name = "ohm", symbol = "Ω" }
+
+[conductance]
+S = { name = "siemens", symbol = "S", derived = "1 / ohm" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+
+        assert_eq!(map.get(&key("", "/", "s")).unwrap(), "Hz");
+        assert_eq!(map.get(&key("", "/", "Hz")).unwrap(), "s");
+        assert_eq!(map.get(&key("Hz", "*", "s")).unwrap(), "");
+        assert_eq!(map.get(&key("s", "*", "Hz")).unwrap(), "");
+
+        assert_eq!(map.get(&key("", "/", "ohm")).unwrap(), "S");
+        assert_eq!(map.get(&key("", "/", "S")).unwrap(), "ohm");
+        assert_eq!(map.get(&key("S", "*", "ohm")).unwrap(), "");
     }
 
     #[test]
@@ -205,13 +770,51 @@ m3 = { name = "cubic meter", symbol = "m³", derived = "m * m * m" }
         let map = construct_unit_translation_map(&definitions).unwrap();
 
         // Test basic area operations
-        assert_eq!(map.get(&("m", "*", "m")).unwrap(), &"m2");
-        assert_eq!(map.get(&("m2", "/", "m")).unwrap(), &"m");
+        assert_eq!(map.get(&key("m", "*", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m2", "/", "m")).unwrap(), "m");
 
         // Test volume operations
-        assert_eq!(map.get(&("m2", "*", "m")).unwrap(), &"m3");
-        assert_eq!(map.get(&("m3", "/", "m")).unwrap(), &"m2");
-        assert_eq!(map.get(&("m3", "/", "m2")).unwrap(), &"m");
+        assert_eq!(map.get(&key("m2", "*", "m")).unwrap(), "m3");
+        assert_eq!(map.get(&key("m3", "/", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m3", "/", "m2")).unwrap(), "m");
+    }
+
+    #[test]
+    fn test_derived_operands() {
+        // Derived units used as operands before their own definitions
+        let toml_str = r#"
+[volume]
+m3 = { name = "cubic meter", symbol = "m³", derived = "m2 * m" }
+
+[pressure]
+Pa = { name = "pascal", symbol = "Pa", derived = "N / m2" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
+
+[force]
+N = { name = "newton", symbol = "N", derived = "kg * m / s2" }
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+
+[time_squared]
+s2 = { name = "square second", symbol = "s²" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+
+        assert_eq!(map.get(&key("m2", "*", "m")).unwrap(), "m3");
+        assert_eq!(map.get(&key("m3", "/", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m3", "/", "m2")).unwrap(), "m");
+
+        assert_eq!(map.get(&key("N", "/", "m2")).unwrap(), "Pa");
+        assert_eq!(map.get(&key("Pa", "*", "m2")).unwrap(), "N");
+        assert_eq!(map.get(&key("m2", "*", "Pa")).unwrap(), "N");
+        assert_eq!(map.get(&key("N", "/", "Pa")).unwrap(), "m2");
     }
 
     #[test]
@@ -226,8 +829,10 @@ m = { name = "another meter", symbol = "m" }
         let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
         let err = construct_unit_translation_map(&definitions).unwrap_err();
         println!("Error: {}", err);
-        assert!(matches!(err, DefinitionError::DuplicatedUnit(unit, category) 
-            if unit == "m" && category == "area"));
+        assert!(
+            matches!(err, DefinitionError::DuplicatedUnit(unit, category)
+            if unit == "m" && category == "area")
+        );
     }
 
     #[test]
@@ -241,8 +846,10 @@ m2 = { name = "square meter", symbol = "m²", derived = "m ** m" }
 "#;
         let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
         let err = construct_unit_translation_map(&definitions).unwrap_err();
-        assert!(matches!(err, DefinitionError::InvalidDerivedExpression(expr) 
-            if expr == "m ** m"));
+        assert!(
+            matches!(err, DefinitionError::InvalidDerivedExpression(expr)
+            if expr == "m ** m")
+        );
 
         // Test invalid operator
         let toml_str = r#"
@@ -254,8 +861,10 @@ m2 = { name = "square meter", symbol = "m²", derived = "m + m" }
 "#;
         let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
         let err = construct_unit_translation_map(&definitions).unwrap_err();
-        assert!(matches!(err, DefinitionError::InvalidDerivedExpression(expr) 
-            if expr == "m + m"));
+        assert!(
+            matches!(err, DefinitionError::InvalidDerivedExpression(expr)
+            if expr == "m + m")
+        );
     }
 
     #[test]
@@ -266,8 +875,10 @@ m2 = { name = "square meter", symbol = "m²", derived = "x * x" }
 "#;
         let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
         let err = construct_unit_translation_map(&definitions).unwrap_err();
-        assert!(matches!(err, DefinitionError::UnitNotFound(unit, expr, category) 
-            if unit == "x" && expr == "x * x" && category == "area"));
+        assert!(
+            matches!(err, DefinitionError::UnitNotFound(unit, expr, category)
+            if unit == "x" && expr == "x * x" && category == "area")
+        );
     }
 
     #[test]
@@ -276,4 +887,520 @@ m2 = { name = "square meter", symbol = "m²", derived = "x * x" }
         let unit_table = UnitTable::new(&definitions).unwrap();
         assert!(unit_table.base_units_map().contains_key(""));
     }
+
+    #[test]
+    fn test_chain_across_categories() {
+        let toml_str = r#"
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+s = { name = "second", symbol = "s" }
+
+[time_squared]
+s2 = { name = "square second", symbol = "s²", derived = "s * s" }
+
+[force]
+N = { name = "newton", symbol = "N", derived = "kg * m / s2" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+
+        // kg * m has no named unit, so a synthetic one carries the chain
+        assert_eq!(map.get(&key("kg", "*", "m")).unwrap(), "kg*m");
+        assert_eq!(map.get(&key("kg*m", "/", "s2")).unwrap(), "N");
+        assert_eq!(map.get(&key("N", "*", "s2")).unwrap(), "kg*m");
+        assert_eq!(map.get(&key("kg*m", "/", "N")).unwrap(), "s2");
+    }
+
+    #[test]
+    fn test_synthetic_units_merged() {
+        // Minimized from `test_translation_map_symmetry`, the intermediate `m*m` is the `m2` itself
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m / m * m" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+        assert_eq!(map.get(&key("m", "*", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m2", "/", "m")).unwrap(), "m");
+        assert_symmetric(&map);
+
+        // The inverses of `pm3`'s chain map `pm3 * m` to the synthetic `m/m/m2`, which is `pm2`
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
+
+[inverse_area]
+pm2 = { name = "per square meter", derived = "m * pm3" }
+
+[inverse_volume]
+pm3 = { name = "per cubic meter", derived = "m / m / m2 / m" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+        assert_eq!(map.get(&key("pm3", "*", "m")).unwrap(), "pm2");
+        assert_eq!(map.get(&key("pm2", "/", "m")).unwrap(), "pm3");
+        assert_symmetric(&map);
+
+        // Merging `m*m*m/m` into `m2` merges the `m*m2` it maps `m2 * m` to into `m*m*m` as well
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m * m / m" }
+
+[fifth_power]
+m5 = { name = "meter to the fifth", derived = "m * m2 * m2" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+        assert_eq!(map.get(&key("m", "*", "m")).unwrap(), "m2");
+        assert_eq!(map.get(&key("m", "*", "m2")).unwrap(), "m*m*m");
+        assert_symmetric(&map);
+    }
+
+    #[test]
+    fn test_grouped_synthetic_units() {
+        // Minimized from `test_translation_map_symmetry`, both chains were named `m/s/s`
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+s = { name = "second", symbol = "s" }
+
+[absement]
+ms = { name = "meter second", derived = "m / (s / s) * s" }
+
+[speed]
+mps = { name = "meters per second", derived = "m / s / s * s" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let map = construct_unit_translation_map(&definitions).unwrap();
+        assert_eq!(map.get(&key("m/(s/s)", "*", "s")).unwrap(), "ms");
+        assert_eq!(map.get(&key("m/s/s", "*", "s")).unwrap(), "mps");
+        assert_symmetric(&map);
+    }
+
+    #[test]
+    fn test_derived_expression_spacing() {
+        let derived_map = |derived: &str| {
+            let toml_str = format!(
+                r#"
+[length]
+m = {{ name = "meter", symbol = "m" }}
+
+[volume]
+m3 = {{ name = "cubic meter", symbol = "m³", derived = "{}" }}
+"#,
+                derived
+            );
+            let definitions: UnitDefinitions = toml::from_str(&toml_str).unwrap();
+            construct_unit_translation_map(&definitions).unwrap()
+        };
+
+        let spaced = derived_map("m * m * m");
+        assert_eq!(spaced.get(&key("m*m", "*", "m")).unwrap(), "m3");
+        assert_eq!(derived_map("m*m*m"), spaced);
+        assert_eq!(derived_map("m*  m *m"), spaced);
+    }
+
+    #[test]
+    fn test_grouped_derived_expression() {
+        let derived_map = |derived: &str| {
+            let toml_str = format!(
+                r#"
+[mass]
+kg = {{ name = "kilogram", symbol = "kg" }}
+
+[length]
+m = {{ name = "meter", symbol = "m" }}
+
+[time_squared]
+s2 = {{ name = "square second", symbol = "s²" }}
+
+[force]
+N = {{ name = "newton", symbol = "N", derived = "{}" }}
+"#,
+                derived
+            );
+            let definitions: UnitDefinitions = toml::from_str(&toml_str).unwrap();
+            construct_unit_translation_map(&definitions)
+        };
+
+        let flat = derived_map("kg * m / s2").unwrap();
+        assert_eq!(derived_map("(kg * m) / s2").unwrap(), flat);
+        assert_eq!(derived_map("((kg*m))/s2").unwrap(), flat);
+
+        // The inner group is resolved first
+        let grouped = derived_map("kg * (m / s2)").unwrap();
+        assert_eq!(grouped.get(&key("m", "/", "s2")).unwrap(), "m/s2");
+        assert_eq!(grouped.get(&key("kg", "*", "m/s2")).unwrap(), "N");
+
+        for unbalanced in ["(kg * m / s2", "kg * m) / s2", "kg * () m"] {
+            let err = derived_map(unbalanced).unwrap_err();
+            assert!(
+                matches!(err, DefinitionError::InvalidDerivedExpression(expr)
+                if expr == unbalanced)
+            );
+        }
+    }
+
+    #[test]
+    fn test_quotient_symbols() {
+        let toml_str = r#"
+[mass]
+kg = { name = "kilogram", symbol = "kg" }
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[time]
+sec = { name = "second", symbol = "s" }
+
+[time_squared]
+s2 = { name = "square second", symbol = "s2", derived = "sec * sec" }
+
+[speed]
+mps = { name = "meter per second", symbol = "mps", derived = "m / sec" }
+
+[force]
+N = { name = "newton", symbol = "N", derived = "(kg * m) / s2" }
+
+[frequency]
+Hz = { name = "hertz", symbol = "Hz", derived = "kg / (kg * sec)" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let table = UnitTable::new(&definitions).unwrap();
+        let quotient_symbols = table.quotient_symbols();
+
+        assert_eq!(quotient_symbols.get("mps").unwrap(), "m/s");
+        assert_eq!(quotient_symbols.get("N").unwrap(), "kg·m/s²");
+        assert_eq!(quotient_symbols.get("Hz").unwrap(), "kg/(kg·s)");
+        assert!(!quotient_symbols.contains_key("s2"));
+    }
+
+    #[test]
+    fn test_negative_offset() {
+        let toml_str = r#"
+[temperature]
+degC = { name = "degree Celsius", symbol = "°C" }
+degF = { name = "degree Fahrenheit", symbol = "°F", factor = "5/9", offset = -17.77777777777778 }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let table = UnitTable::new(&definitions).unwrap();
+        assert_eq!(table.offset("degF"), -17.77777777777778);
+        assert_eq!(table.offset("degC"), 0.0);
+
+        // The sign rule of factors still applies
+        let toml_str = r#"
+[temperature]
+degC = { name = "degree Celsius", symbol = "°C" }
+degF = { name = "degree Fahrenheit", symbol = "°F", factor = "-5/9", offset = -17.77777777777778 }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidFactor(unit, _) if unit == "degF"));
+    }
+
+    #[test]
+    fn test_malformed_factor_error() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+ft = { name = "foot", symbol = "ft", factor = "3/0" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::MalformedFactor(unit, factor)
+            if unit == "ft" && factor == "3/0"));
+    }
+
+    #[test]
+    fn test_relative_factor() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+yard = { name = "yard", symbol = "yd", factor = 3, relative_to = "foot" }
+foot = { name = "foot", symbol = "ft", factor = 12, relative_to = "inch" }
+inch = { name = "inch", symbol = "in", factor = 0.0254 }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let unit_table = UnitTable::new(&definitions).unwrap();
+        let (factor, base_unit) = &unit_table.base_units_map()["yard"];
+        assert!((factor - 0.9144).abs() < 1e-12);
+        assert_eq!(base_unit, "m");
+    }
+
+    #[test]
+    fn test_relative_factor_errors() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+yard = { name = "yard", symbol = "yd", factor = 3, relative_to = "foot" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(
+            matches!(err, DefinitionError::RelativeUnitNotFound(unit, relative_to)
+            if unit == "yard" && relative_to == "foot")
+        );
+
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+a = { name = "a", symbol = "a", factor = 2, relative_to = "b" }
+b = { name = "b", symbol = "b", factor = 3, relative_to = "a" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::CyclicRelativeUnit(unit) if unit == "b"));
+    }
+
+    #[test]
+    fn test_lint() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+far = { name = "far", symbol = "far", factor = 1e300 }
+farther = { name = "farther", symbol = "farther", factor = 1e300, relative_to = "far" }
+
+[area]
+m2 = { name = "square meter", symbol = "m²", derived = "m * m" }
+
+[luminosity]
+cd = { name = "candela", symbol = "cd" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let unit_table = UnitTable::new(&definitions).unwrap();
+        assert_eq!(
+            unit_table.lint(),
+            vec![
+                "Unit \"cd\" is the only unit of its category and no derived unit refers to it, \
+                 it can't be converted",
+                "Unit \"farther\" has the factor inf, no value converts to or from it",
+            ]
+        );
+
+        let toml_str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let definitions: UnitDefinitions = toml::from_str(&toml_str).unwrap();
+        assert!(UnitTable::new(&definitions).unwrap().lint().is_empty());
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let toml_str = r#"
+[dimensions]
+length = "L"
+energy = "M L^2 T^-2"
+temperature = "Θ"
+ratio = "1"
+
+[length]
+m = { name = "meter", symbol = "m" }
+
+[energy]
+J = { name = "joule", symbol = "J" }
+
+[temperature]
+K = { name = "kelvin", symbol = "K" }
+
+[ratio]
+pct = { name = "percent", symbol = "%" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        assert_eq!(definitions.dimensions["energy"], "M L^2 T^-2");
+        assert_eq!(
+            definitions.categories.keys().collect::<Vec<_>>(),
+            ["length", "energy", "temperature", "ratio"]
+        );
+        assert!(UnitTable::new(&definitions).is_ok());
+
+        let toml_str = r#"
+[dimensions]
+sped = "L T^-1"
+
+[length]
+m = { name = "meter" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(
+            matches!(&err, DefinitionError::UnknownDimensionCategory(category) if category == "sped")
+        );
+
+        for invalid in ["L T^-x", "L*T", "L^0", "L T^", ""] {
+            let toml_str = format!(
+                "[dimensions]\nspeed = \"{}\"\n\n[length]\nm = {{ name = \"meter\" }}\n",
+                invalid
+            );
+            let definitions: UnitDefinitions = toml::from_str(&toml_str).unwrap();
+            let err = UnitTable::new(&definitions).unwrap_err();
+            assert!(
+                matches!(&err, DefinitionError::InvalidDimension(category, dimension)
+                if category == "speed" && dimension == invalid),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_time_unit() {
+        let toml_str = r#"
+[time]
+min = { name = "minute", symbol = "min", factor = 60 }
+sec = { name = "second", symbol = "s" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidTimeUnit(unit, factor)
+            if unit == "min" && factor == 60.0));
+    }
+
+    #[test]
+    fn test_zero_factor_error() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+nothing = { name = "nothing", symbol = "n", factor = 0 }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidFactor(unit, factor)
+            if unit == "nothing" && factor == 0.0));
+    }
+
+    #[test]
+    fn test_negative_factor_error() {
+        let toml_str = r#"
+[length]
+m = { name = "meter", symbol = "m" }
+neg = { name = "negative meter", symbol = "-m", factor = "-1/2" }
+"#;
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let err = UnitTable::new(&definitions).unwrap_err();
+        assert!(matches!(err, DefinitionError::InvalidFactor(unit, factor)
+            if unit == "neg" && factor == -0.5));
+    }
+
+    /// Operands (indices into the units defined before), operators (`true` for `*`) and the index of
+    /// an operand grouped in parentheses with the next one
+    type DerivedRecipe = (Vec<usize>, Vec<bool>, Option<usize>);
+
+    /// Definitions of `base_count` base units `b0`, `b1`... and of a derived unit `d0`, `d1`... per
+    /// recipe, skipping those whose dimension is already taken since several units of the same
+    /// dimension legitimately map the same operations to different results
+    fn random_definitions(base_count: usize, recipes: &[DerivedRecipe]) -> String {
+        let mut units: Vec<(String, Vec<i32>)> = (0..base_count)
+            .map(|i| {
+                let mut dimension = vec![0; base_count];
+                dimension[i] = 1;
+                (format!("b{}", i), dimension)
+            })
+            .collect();
+        let mut toml_str: String = units
+            .iter()
+            .map(|(unit, _)| format!("[{0}_category]\n{0} = {{ name = \"{0}\" }}\n", unit))
+            .collect();
+
+        for (operands, muls, group) in recipes {
+            let operands: Vec<&(String, Vec<i32>)> =
+                operands.iter().map(|i| &units[i % units.len()]).collect();
+            let group = group.map(|g| g % (operands.len() - 1));
+            let mut expr = String::new();
+            let mut dimension = vec![0; base_count];
+            for (i, (unit, unit_dimension)) in operands.iter().enumerate() {
+                let sign = |i: usize| if i == 0 || muls[i - 1] { 1 } else { -1 };
+                if i > 0 {
+                    expr.push_str(if muls[i - 1] { " * " } else { " / " });
+                }
+                let exponent = match group {
+                    Some(g) if g == i => {
+                        expr.push('(');
+                        sign(i)
+                    }
+                    Some(g) if g + 1 == i => sign(g) * sign(i),
+                    _ => sign(i),
+                };
+                expr.push_str(unit);
+                if group.is_some_and(|g| g + 1 == i) {
+                    expr.push(')');
+                }
+                for (total, unit_exponent) in dimension.iter_mut().zip(unit_dimension) {
+                    *total += exponent * unit_exponent;
+                }
+            }
+            let taken = units.iter().any(|(_, other)| *other == dimension);
+            if taken || dimension.iter().all(|&exponent| exponent == 0) {
+                continue;
+            }
+            let unit = format!("d{}", units.len() - base_count);
+            toml_str.push_str(&format!(
+                "[{0}_category]\n{0} = {{ name = \"{0}\", derived = \"{1}\" }}\n",
+                unit, expr
+            ));
+            units.push((unit, dimension));
+        }
+        toml_str
+    }
+
+    fn recipe() -> impl Strategy<Value = DerivedRecipe> {
+        (2..=4usize).prop_flat_map(|len| {
+            (
+                prop::collection::vec(0..16usize, len),
+                prop::collection::vec(any::<bool>(), len - 1),
+                prop::option::of(0..16usize),
+            )
+        })
+    }
+
+    /// Asserts that every operation has its inverses, e.g. `c / a -> b` and `c / b -> a` for `a * b -> c`
+    fn assert_symmetric(map: &UnitMapType) {
+        for ((unit_a, op, unit_b), result) in map {
+            let inverses = if op == "*" {
+                [key(result, "/", unit_a), key(result, "/", unit_b)]
+            } else {
+                [key(result, "*", unit_b), key(unit_a, "/", result)]
+            };
+            let expected = if op == "*" {
+                [unit_b, unit_a]
+            } else {
+                [unit_a, unit_b]
+            };
+            for (inverse, expected) in inverses.iter().zip(expected) {
+                assert_eq!(
+                    map.get(inverse),
+                    Some(expected),
+                    "{:?} is missing the inverse {:?}",
+                    (unit_a, op, unit_b, result),
+                    inverse
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_translation_map_symmetry(
+            base_count in 1..=3usize,
+            recipes in prop::collection::vec(recipe(), 1..=5),
+        ) {
+            let toml_str = random_definitions(base_count, &recipes);
+            let definitions: UnitDefinitions = toml::from_str(&toml_str).unwrap();
+            let map = construct_unit_translation_map(&definitions).unwrap();
+            assert_symmetric(&map);
+        }
+    }
 }
@@ -1,29 +1,141 @@
+use std::{num::NonZeroUsize, path::Path};
+
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+use crate::{DefinitionFileError, ratio::Ratio};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct UnitDefinition {
     pub name: String,
+    #[serde(default)]
     pub symbol: String,
     #[serde(default = "default_factor")]
-    pub factor: f64,
+    pub factor: Factor,
     #[serde(default)]
     pub derived: Option<String>,
+    /// Unit of the same category the factor is relative to, the base unit if not set
+    #[serde(default)]
+    pub relative_to: Option<String>,
+    /// Added after applying the factor, for units whose zero differs from the base unit's, e.g. `°F`.
+    /// Unlike the factor it may be negative.
+    #[serde(default)]
+    pub offset: f64,
+    /// Unit of the same category that results in this unit are displayed in, e.g. `kmph` for `mps`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_as: Option<String>,
+    /// Significant figures results in this unit are shown with, instead of the global precision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig_figs: Option<NonZeroUsize>,
+}
+
+fn default_factor() -> Factor {
+    Factor::Number(1.0)
+}
+
+/// A conversion factor, either a plain number or a string such as `"10000"` or an exact ratio like `"1/3"`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Factor {
+    Number(f64),
+    Text(String),
+}
+
+impl Factor {
+    /// Resolves the factor into a number, `None` if the text is not a number or a `num/den` ratio
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Factor::Number(num) => Some(*num),
+            Factor::Text(text) => match text.split_once('/') {
+                Some((num, den)) => {
+                    let num: f64 = num.trim().parse().ok()?;
+                    let den: f64 = den.trim().parse().ok()?;
+                    (den != 0.0).then_some(num / den)
+                }
+                None => text.trim().parse().ok(),
+            },
+        }
+    }
+
+    /// The factor as an exact fraction, e.g. `1/3` for `"1/3"` or `3/10` for `0.3`
+    pub(crate) fn exact_value(&self) -> Option<Ratio> {
+        let parse = |text: &str| Ratio::from_f64(text.trim().parse().ok()?);
+        match self {
+            Factor::Number(num) => Ratio::from_f64(*num),
+            Factor::Text(text) => match text.split_once('/') {
+                Some((num, den)) => parse(num)?.checked_div(parse(den)?),
+                None => parse(text),
+            },
+        }
+    }
 }
 
-fn default_factor() -> f64 {
-    1.0
+impl PartialEq<f64> for Factor {
+    fn eq(&self, other: &f64) -> bool {
+        self.value() == Some(*other)
+    }
+}
+
+impl std::fmt::Display for Factor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Factor::Number(num) => write!(f, "{}", num),
+            Factor::Text(text) => write!(f, "{}", text),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct UnitDefinitions {
+    /// Dimension of categories in base dimensions with integer exponents, e.g. `speed = "L T^-1"`,
+    /// given by the `[dimensions]` table
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub dimensions: IndexMap<String, String>,
     #[serde(flatten)]
     pub categories: IndexMap<String, IndexMap<String, UnitDefinition>>,
 }
 
+impl UnitDefinitions {
+    /// Loads a `.ud` file, errors name the file and where in it the definitions are malformed
+    pub fn from_file(path: &Path) -> Result<Self, DefinitionFileError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|source| DefinitionFileError::Read {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Self::from_toml_str(&content, path)
+    }
+
+    /// Parses the content of a `.ud` file, `path` is only used to describe errors
+    pub fn from_toml_str(content: &str, path: &Path) -> Result<Self, DefinitionFileError> {
+        toml::from_str(content).map_err(|e| {
+            let offset = e.span().map_or(0, |span| span.start);
+            let before = &content[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            DefinitionFileError::Parse {
+                path: path.to_path_buf(),
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+                message: e.message().to_string(),
+            }
+        })
+    }
+
+    /// Writes the definitions in the TOML format of the `.ud` files, so they can be loaded again
+    pub fn to_toml_string(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    pub fn to_json_string(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::unit::UnitTable;
+
     use super::*;
 
     #[test]
@@ -49,4 +161,83 @@ cm2 = { name = "square center meter", symbol = "cm²", factor = 10000 }
         assert_eq!(cm2.factor, 10000.0);
         assert_eq!(cm2.derived, None);
     }
+
+    #[test]
+    fn test_string_factor_deserialize() {
+        let toml_str = r#"
+[length]
+ft = { name = "foot", symbol = "ft", factor = "0.3048" }
+yd = { name = "yard", symbol = "yd", factor = "1/3" }
+bad = { name = "bad", symbol = "bad", factor = "1/x" }
+        "#;
+
+        let definitions: UnitDefinitions = toml::from_str(toml_str).unwrap();
+        let length_units = definitions.categories.get("length").unwrap();
+
+        assert_eq!(length_units.get("ft").unwrap().factor, 0.3048);
+        assert_eq!(length_units.get("yd").unwrap().factor, 1.0 / 3.0);
+        assert_eq!(length_units.get("bad").unwrap().factor.value(), None);
+    }
+
+    #[test]
+    fn test_malformed_file() {
+        let path = Path::new("defs/broken.ud");
+        let content = "[length]\nm = { name = \"meter\", symbol = \"m\" }\nft = { name = \"foot\" factor = 0.3048 }\n";
+        let error = UnitDefinitions::from_toml_str(content, path).unwrap_err();
+        let DefinitionFileError::Parse { line, column, .. } = &error else {
+            panic!("expected a parse error, found {:?}", error);
+        };
+        assert_eq!((*line, *column), (3, 22));
+        assert!(
+            error
+                .to_string()
+                .starts_with("Invalid definitions file defs/broken.ud at line 3, column 22: "),
+            "{}",
+            error
+        );
+
+        let error = UnitDefinitions::from_file(Path::new("missing.ud")).unwrap_err();
+        assert!(matches!(error, DefinitionFileError::Read { .. }));
+        assert_eq!(
+            error.to_string(),
+            "Failed to read definitions file missing.ud"
+        );
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let str = std::fs::read_to_string("../unit_definitions/basic.ud").unwrap();
+        let mut definitions: UnitDefinitions = toml::from_str(&str).unwrap();
+        definitions.categories.insert(
+            "temperature".to_string(),
+            toml::from_str(
+                r#"
+"°C" = { name = "degree Celsius", symbol = "°C" }
+"°F" = { name = "degree Fahrenheit", symbol = "°F", factor = "5/9", offset = -17.77777777777778 }
+"#,
+            )
+            .unwrap(),
+        );
+        definitions
+            .dimensions
+            .insert("speed".to_string(), "L T^-1".to_string());
+        let table = UnitTable::new(&definitions).unwrap();
+
+        let from_toml: UnitDefinitions =
+            toml::from_str(&definitions.to_toml_string().unwrap()).unwrap();
+        let from_json: UnitDefinitions =
+            serde_json::from_str(&definitions.to_json_string().unwrap()).unwrap();
+        for loaded in [from_toml, from_json] {
+            assert_eq!(
+                loaded.categories.keys().collect::<Vec<_>>(),
+                definitions.categories.keys().collect::<Vec<_>>()
+            );
+            assert_eq!(loaded.dimensions, definitions.dimensions);
+            let loaded_table = UnitTable::new(&loaded).unwrap();
+            assert_eq!(loaded_table.base_units_map(), table.base_units_map());
+            assert_eq!(loaded_table.derived_units_map(), table.derived_units_map());
+            assert_eq!(loaded_table.symbols(), table.symbols());
+            assert_eq!(loaded_table.offset("°F"), table.offset("°F"));
+        }
+    }
 }
@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{FormatOptions, format_value};
+
+const MIXED_VALUE_ERROR: &str = "Cannot compute with a mixed units value";
+const EACH_VALUE_ERROR: &str = "Cannot compute with a value in several units";
+
+/// The magnitude of an evaluated expression, either a single number or a list of numbers sharing one unit.
+/// A mixed value (e.g. `1 h 30 min`) carries its own unit for each part, and so does a value converted to
+/// several units (e.g. `180 kmph` and `111.85 mph` of `50 mps >> {kmph, mph}`).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Value {
+    Number(f64),
+    List(Vec<f64>),
+    Mixed(Vec<(f64, String)>),
+    Each(Vec<(f64, String)>),
+}
+
+impl Value {
+    /// Applies `f` to the number, or to every element of the list.
+    pub(crate) fn map(self, f: impl Fn(f64) -> f64) -> Result<Value, String> {
+        match self {
+            Value::Number(num) => Ok(Value::Number(f(num))),
+            Value::List(list) => Ok(Value::List(list.into_iter().map(f).collect())),
+            Value::Mixed(_) => Err(MIXED_VALUE_ERROR.to_string()),
+            Value::Each(_) => Err(EACH_VALUE_ERROR.to_string()),
+        }
+    }
+
+    /// Combines two values element-wise, broadcasting a number over a list.
+    pub(crate) fn zip_with(
+        self,
+        other: Value,
+        f: impl Fn(f64, f64) -> f64,
+    ) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(f(a, b))),
+            (Value::Number(a), Value::List(b)) => {
+                Ok(Value::List(b.into_iter().map(|b| f(a, b)).collect()))
+            }
+            (Value::List(a), Value::Number(b)) => {
+                Ok(Value::List(a.into_iter().map(|a| f(a, b)).collect()))
+            }
+            (Value::List(a), Value::List(b)) => {
+                if a.len() != b.len() {
+                    return Err(format!(
+                        "Cannot combine lists of different lengths ({} and {})",
+                        a.len(),
+                        b.len()
+                    ));
+                }
+                Ok(Value::List(
+                    a.into_iter().zip(b).map(|(a, b)| f(a, b)).collect(),
+                ))
+            }
+            (Value::Mixed(_), _) | (_, Value::Mixed(_)) => Err(MIXED_VALUE_ERROR.to_string()),
+            (Value::Each(_), _) | (_, Value::Each(_)) => Err(EACH_VALUE_ERROR.to_string()),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(num: f64) -> Self {
+        Value::Number(num)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(list: Vec<f64>) -> Self {
+        Value::List(list)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_value(self, &FormatOptions::default()))
+    }
+}